@@ -0,0 +1,144 @@
+//! In-process bank running a program natively against accounts kept in memory.
+//!
+//! Instructions run atomically like on a cluster: accounts are only updated when the
+//! instruction succeeds. Signatures are taken from the instruction metas and lamports are
+//! not checked. Program logs of the last instruction are kept in `Bank::logs`.
+
+use {
+    borsh::BorshDeserialize,
+    solana_program::{
+        account_info::AccountInfo, entrypoint::ProgramResult, instruction::Instruction,
+        program_error::ProgramError, program_stubs, pubkey::Pubkey,
+    },
+    solcery_crud::RecordKind,
+    std::{cell::RefCell, collections::BTreeMap, sync::Once},
+};
+
+/// Entrypoint of the program the bank runs
+pub type Processor = fn(&Pubkey, &[AccountInfo], &[u8]) -> ProgramResult;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Account {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+}
+
+thread_local! {
+    static LOGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Collects program logs of the current thread, tests running in parallel keep them apart
+struct CaptureStubs;
+
+impl program_stubs::SyscallStubs for CaptureStubs {
+    fn sol_log(&self, message: &str) {
+        LOGS.with(|logs| logs.borrow_mut().push(message.to_string()));
+    }
+}
+
+pub struct Bank {
+    pub program_id: Pubkey,
+    pub accounts: BTreeMap<Pubkey, Account>,
+    pub logs: Vec<String>,
+    processor: Processor,
+}
+
+impl Bank {
+    pub fn new(program_id: Pubkey, processor: Processor) -> Self {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(CaptureStubs));
+        });
+        Bank {
+            program_id,
+            accounts: BTreeMap::new(),
+            logs: Vec::new(),
+            processor,
+        }
+    }
+
+    /// Allocates a zeroed account owned by the program
+    pub fn alloc(&mut self, space: usize) -> Pubkey {
+        self.alloc_data(vec![0; space])
+    }
+
+    pub fn alloc_data(&mut self, data: Vec<u8>) -> Pubkey {
+        let key = Pubkey::new_unique();
+        self.insert(key, data);
+        key
+    }
+
+    pub fn insert(&mut self, key: Pubkey, data: Vec<u8>) {
+        self.accounts.insert(key, Account { lamports: 1, data });
+    }
+
+    pub fn data(&self, key: &Pubkey) -> &[u8] {
+        &self.accounts[key].data
+    }
+
+    /// Data of every account, as exported and imported by bundles
+    pub fn dump(&self) -> BTreeMap<Pubkey, Vec<u8>> {
+        self.accounts
+            .iter()
+            .map(|(key, account)| (*key, account.data.clone()))
+            .collect()
+    }
+
+    pub fn record<T: BorshDeserialize>(&self, key: &Pubkey, kind: RecordKind) -> T {
+        solcery_crud::decode_record(self.data(key), kind).unwrap()
+    }
+
+    pub fn process(&mut self, instruction: &Instruction) -> ProgramResult {
+        let mut accounts = self.accounts.clone();
+        for meta in &instruction.accounts {
+            accounts.entry(meta.pubkey).or_default();
+        }
+        LOGS.with(|logs| logs.borrow_mut().clear());
+        let result = {
+            // Repeated accounts share the same data like in the runtime
+            let mut account_infos = BTreeMap::new();
+            for (key, account) in accounts.iter_mut() {
+                let metas = instruction.accounts.iter().filter(|x| x.pubkey == *key);
+                let (is_signer, is_writable) = metas.fold((false, false), |acc, x| {
+                    (acc.0 || x.is_signer, acc.1 || x.is_writable)
+                });
+                account_infos.insert(
+                    *key,
+                    AccountInfo::new(
+                        key,
+                        is_signer,
+                        is_writable,
+                        &mut account.lamports,
+                        &mut account.data,
+                        &self.program_id,
+                        false,
+                        0,
+                    ),
+                );
+            }
+            let instruction_infos: Vec<AccountInfo> = instruction
+                .accounts
+                .iter()
+                .map(|x| account_infos[&x.pubkey].clone())
+                .collect();
+            (self.processor)(&self.program_id, &instruction_infos, &instruction.data)
+        };
+        self.logs = LOGS.with(|logs| logs.borrow_mut().split_off(0));
+        if result.is_ok() {
+            // Accounts that only took part in the instruction, like the signer, are not kept
+            accounts.retain(|key, x| !x.data.is_empty() || self.accounts.contains_key(key));
+            self.accounts = accounts;
+        }
+        result
+    }
+
+    /// Runs the instruction expecting it to fail with `error` and leave the accounts as they were
+    pub fn process_err(&mut self, instruction: &Instruction, error: ProgramError) {
+        let accounts = self.accounts.clone();
+        assert_eq!(self.process(instruction), Err(error));
+        assert!(
+            self.accounts == accounts,
+            "failed instruction changed accounts"
+        );
+    }
+}
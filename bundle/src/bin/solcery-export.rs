@@ -0,0 +1,29 @@
+//! Usage: solcery-export <dump.json> <project>
+//!
+//! Prints the bundle of the project found in the account dump
+
+use {
+    solana_program::pubkey::Pubkey,
+    solcery_bundle::export::{export, parse_dump},
+    std::{env, fs, process, str::FromStr},
+};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <dump.json> <project>", args[0]);
+        process::exit(2);
+    }
+    if let Err(e) = run(&args[1], &args[2]) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(dump_path: &str, project: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let accounts = parse_dump(&fs::read_to_string(dump_path)?)?;
+    let project = Pubkey::from_str(project)?;
+    let bundle = export(&accounts, &project)?;
+    println!("{}", serde_json::to_string_pretty(&bundle)?);
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use {
+    solana_program::{program_error::ProgramError, pubkey::Pubkey},
+    thiserror::Error,
+};
+
+/// Errors of bundle export and import
+#[derive(Debug, Error)]
+pub enum BundleError {
+    /// Account is referenced but missing in the dump
+    #[error("Account {0} is missing in the dump")]
+    MissingAccount(Pubkey),
+
+    /// Account can't be decoded as the expected record
+    #[error("Account {0} is not a valid record: {1}")]
+    InvalidRecord(Pubkey, ProgramError),
+
+    /// Field value doesn't match the field type
+    #[error("Invalid value of field {0}: {1}")]
+    InvalidValue(u32, String),
+
+    /// Bundle refers to something it doesn't contain
+    #[error("Invalid bundle: {0}")]
+    InvalidBundle(String),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
@@ -0,0 +1,280 @@
+use {
+    crate::{
+        error::BundleError, value, AccountDump, Bundle, FieldEntry, FieldValueEntry, ObjectEntry,
+        ProjectEntry, StorageEntry, TemplateEntry, BUNDLE_FORMAT,
+    },
+    borsh::BorshDeserialize,
+    solana_program::pubkey::Pubkey,
+    solcery_crud::{self as crud, RecordKind},
+    solcery_object::{index::FieldIndex, Object},
+    solcery_project::Project,
+    solcery_storage::{
+        paged::{PagedStorage, StoragePage},
+        AccountStorage,
+    },
+    solcery_template::{Field, TemplateData},
+    std::{collections::BTreeMap, str::FromStr},
+};
+
+/// Builds the bundle of the project from a dump containing the project, its templates
+/// with their version snapshots, storages, storage pages and indexes, and all objects
+pub fn export(accounts: &AccountDump, project_key: &Pubkey) -> Result<Bundle, BundleError> {
+    let project: Project = read(accounts, project_key, RecordKind::Project)?;
+    let template_storage: AccountStorage =
+        read(accounts, &project.template_storage, RecordKind::Storage)?;
+    let mut templates = BTreeMap::new();
+    for key in &template_storage.accounts {
+        let template: TemplateData = read(accounts, key, RecordKind::Template)?;
+        templates.insert(*key, template);
+    }
+
+    let mut template_entries = Vec::new();
+    let mut objects = Vec::new();
+    let mut order: Vec<(&Pubkey, &TemplateData)> = templates.iter().collect();
+    order.sort_by_key(|(_, template)| template.id);
+    for (key, template) in order {
+        let fields = merged_fields(&templates, key)?;
+        let mut snapshots = Vec::new();
+        for snapshot_key in &template.versions {
+            let snapshot: TemplateData = read(accounts, snapshot_key, RecordKind::TemplateVersion)?;
+            if snapshot.id != template.id {
+                return Err(BundleError::InvalidBundle(format!(
+                    "version {} doesn't belong to template {}",
+                    snapshot_key, key
+                )));
+            }
+            snapshots.push(snapshot);
+        }
+        let mut storages = Vec::new();
+        let mut template_objects = Vec::new();
+        for storage_key in &template.storages {
+            let (storage, stored) = storage_entry(accounts, storage_key)?;
+            for object_key in &stored {
+                let object: Object = read(accounts, object_key, RecordKind::Object)?;
+                let snapshot = snapshots
+                    .iter()
+                    .find(|x| x.version == object.template_version);
+                let fields = version_fields(&fields, template, snapshot);
+                template_objects.push(object_entry(object_key, &object, &fields));
+            }
+            storages.push(storage);
+        }
+        template_objects.sort_by_key(|x| x.id);
+        objects.extend(template_objects);
+
+        let mut indexed_fields = Vec::new();
+        for index_key in &template.indexes {
+            let index: FieldIndex = read(accounts, index_key, RecordKind::Index)?;
+            indexed_fields.push(index.field_id);
+        }
+        template_entries.push(TemplateEntry {
+            key: key.to_string(),
+            id: template.id,
+            name: template.name.clone(),
+            code: template.code.clone(),
+            parent: template.parent.map(|x| x.to_string()),
+            version: template.version,
+            published: template.published,
+            max_field_index: template.max_field_index,
+            custom_data: template.custom_data.clone(),
+            fields: template
+                .fields
+                .iter()
+                .map(|field| FieldEntry {
+                    id: field.id,
+                    name: field.params.name.clone(),
+                    code: field.params.code.clone(),
+                    field_type: (&field.params.field_type).into(),
+                    construct_client: field.params.construct_client,
+                    construct_server: field.params.construct_server,
+                    on_delete: template
+                        .links
+                        .iter()
+                        .find(|x| x.field_id == field.id)
+                        .map(|x| x.on_delete.into()),
+                })
+                .collect(),
+            defaults: template
+                .defaults
+                .iter()
+                .map(|x| field_value(x.field_id, &x.value, &fields))
+                .collect(),
+            storages,
+            indexed_fields,
+        });
+    }
+
+    Ok(Bundle {
+        format: BUNDLE_FORMAT,
+        project: ProjectEntry {
+            key: project_key.to_string(),
+            name: project.name,
+            owner: project.owner.to_string(),
+            template_storage: project.template_storage.to_string(),
+            uniq_id: project.uniq_id,
+        },
+        templates: template_entries,
+        objects,
+    })
+}
+
+/// Parses a dump written as a JSON object of base64 account data by base58 account key
+pub fn parse_dump(json: &str) -> Result<AccountDump, BundleError> {
+    let encoded: BTreeMap<String, String> = serde_json::from_str(json)?;
+    let mut accounts = AccountDump::new();
+    for (key, data) in encoded {
+        let key = Pubkey::from_str(&key)
+            .map_err(|_| BundleError::InvalidBundle(format!("invalid account key {}", key)))?;
+        let data = base64::decode(&data)
+            .map_err(|_| BundleError::InvalidBundle(format!("invalid data of account {}", key)))?;
+        accounts.insert(key, data);
+    }
+    Ok(accounts)
+}
+
+/// Writes the dump in the format read by `parse_dump`
+pub fn format_dump(accounts: &AccountDump) -> String {
+    let encoded: BTreeMap<String, String> = accounts
+        .iter()
+        .map(|(key, data)| (key.to_string(), base64::encode(data)))
+        .collect();
+    serde_json::to_string_pretty(&encoded).unwrap()
+}
+
+pub fn read<T: BorshDeserialize>(
+    accounts: &AccountDump,
+    key: &Pubkey,
+    kind: RecordKind,
+) -> Result<T, BundleError> {
+    let data = accounts.get(key).ok_or(BundleError::MissingAccount(*key))?;
+    crud::decode_record(data, kind).map_err(|e| BundleError::InvalidRecord(*key, e))
+}
+
+/// Flat storage or paged one with the accounts of all its pages by key.
+/// Returns the entry and the stored accounts
+fn storage_entry(
+    accounts: &AccountDump,
+    key: &Pubkey,
+) -> Result<(StorageEntry, Vec<Pubkey>), BundleError> {
+    let data = accounts.get(key).ok_or(BundleError::MissingAccount(*key))?;
+    let (sorted, stored, page_capacity) = match crud::record_kind(data) {
+        RecordKind::PagedStorage => {
+            let storage: PagedStorage = read(accounts, key, RecordKind::PagedStorage)?;
+            (
+                false,
+                paged_accounts(accounts, key, &storage)?,
+                Some(storage.page_capacity),
+            )
+        }
+        _ => {
+            let storage: AccountStorage = read(accounts, key, RecordKind::Storage)?;
+            (storage.sorted, storage.accounts, None)
+        }
+    };
+    let entry = StorageEntry {
+        key: key.to_string(),
+        sorted,
+        accounts: stored.iter().map(|x| x.to_string()).collect(),
+        page_capacity,
+    };
+    Ok((entry, stored))
+}
+
+fn paged_accounts(
+    accounts: &AccountDump,
+    key: &Pubkey,
+    storage: &PagedStorage,
+) -> Result<Vec<Pubkey>, BundleError> {
+    let mut pages = Vec::new();
+    let mut next = storage.last_page;
+    while let Some(page_key) = next {
+        let page: StoragePage = read(accounts, &page_key, RecordKind::StoragePage)?;
+        if page.storage != *key || pages.len() >= storage.pages as usize {
+            return Err(BundleError::InvalidBundle(format!(
+                "page {} doesn't belong to storage {}",
+                page_key, key
+            )));
+        }
+        next = page.prev;
+        pages.push(page);
+    }
+    // Pages keep no order of their own, accounts are listed by key
+    let mut accounts: Vec<Pubkey> = pages.into_iter().flat_map(|x| x.accounts).collect();
+    accounts.sort();
+    Ok(accounts)
+}
+
+/// Fields objects of a published version conform to: own fields of the version snapshot
+/// and current fields of the ancestors. Objects of the draft take the current fields
+fn version_fields<'a>(
+    fields: &BTreeMap<u32, &'a Field>,
+    template: &TemplateData,
+    snapshot: Option<&'a TemplateData>,
+) -> BTreeMap<u32, &'a Field> {
+    let mut fields = fields.clone();
+    if let Some(snapshot) = snapshot {
+        for field in &template.fields {
+            fields.remove(&field.id);
+        }
+        fields.extend(snapshot.fields.iter().map(|x| (x.id, x)));
+    }
+    fields
+}
+
+/// Fields of the template and its ancestors by id
+fn merged_fields<'a>(
+    templates: &'a BTreeMap<Pubkey, TemplateData>,
+    key: &Pubkey,
+) -> Result<BTreeMap<u32, &'a Field>, BundleError> {
+    let mut fields = BTreeMap::new();
+    let mut next = Some(*key);
+    let mut visited = Vec::new();
+    while let Some(key) = next {
+        if visited.contains(&key) {
+            return Err(BundleError::InvalidBundle(format!(
+                "template {} is its own ancestor",
+                key
+            )));
+        }
+        let template = templates
+            .get(&key)
+            .ok_or(BundleError::MissingAccount(key))?;
+        for field in &template.fields {
+            fields.entry(field.id).or_insert(field);
+        }
+        visited.push(key);
+        next = template.parent;
+    }
+    Ok(fields)
+}
+
+fn object_entry(key: &Pubkey, object: &Object, fields: &BTreeMap<u32, &Field>) -> ObjectEntry {
+    ObjectEntry {
+        key: key.to_string(),
+        id: object.id,
+        template: object.template.to_string(),
+        template_version: object.template_version,
+        fields: object
+            .data
+            .field_offsets
+            .iter()
+            .map(|x| {
+                let data = object
+                    .data
+                    .field_data
+                    .get(x.start_offset..x.end_offset)
+                    .unwrap_or_default();
+                field_value(x.field_id, data, fields)
+            })
+            .collect(),
+    }
+}
+
+fn field_value(id: u32, data: &[u8], fields: &BTreeMap<u32, &Field>) -> FieldValueEntry {
+    let field = fields.get(&id);
+    FieldValueEntry {
+        id,
+        code: field.map(|x| x.params.code.clone()),
+        value: value::decode(field.map(|x| &x.params.field_type), data),
+    }
+}
@@ -0,0 +1,832 @@
+//! Recreating a bundle in a fresh project.
+//!
+//! Every account of the bundle gets a new key. The plan lists the accounts to allocate for
+//! the engine and the instructions to run in order: project, templates with their storages,
+//! indexes, objects and at last template defaults, storage order and published states.
+//! Paged storages split full pages the way the program does. Links and template
+//! references within the bundle are remapped to the new keys, ids are allocated anew by the
+//! new project, field ids included, and template versions start over. The plan follows the
+//! id counter of the new project, so it knows every new id in advance.
+
+use {
+    crate::{
+        error::BundleError, value, Bundle, FieldEntry, FieldType, NestedType, ObjectEntry,
+        OnDeleteEntry, TemplateEntry, BUNDLE_FORMAT,
+    },
+    borsh::BorshSerialize,
+    solana_program::{instruction::Instruction, pubkey::Pubkey},
+    solcery_crud::RecordData,
+    solcery_engine::instruction,
+    solcery_object::{index::FieldIndex, Object, ObjectData, ObjectFieldData},
+    solcery_project::Project,
+    solcery_storage::{
+        paged::{PagedStorage, StoragePage},
+        AccountStorage,
+    },
+    solcery_template::{
+        Field, FieldDefault, FieldParams, LinkRule, OnDelete, SolceryNestedType, SolceryType,
+        TemplateData,
+    },
+    std::{collections::BTreeMap, str::FromStr},
+};
+
+/// Objects created by a single instruction
+pub const OBJECT_BATCH_SIZE: usize = 8;
+
+/// Ids a template creation takes, the template id and the ids of the default fields
+pub const TEMPLATE_IDS: u32 = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportPlan {
+    pub accounts: Vec<NewAccount>, // Have to be allocated for the engine before running
+    pub instructions: Vec<Instruction>,
+    pub keys: BTreeMap<Pubkey, Pubkey>, // New keys by bundle keys
+    pub ids: BTreeMap<u32, u32>,        // New ids of templates, fields and objects by bundle ids
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewAccount {
+    pub key: Pubkey,
+    pub space: usize,
+}
+
+/// Template of the bundle with everything resolved to new keys
+struct ImportedTemplate<'a> {
+    entry: &'a TemplateEntry,
+    key: Pubkey,
+    data: TemplateData,
+    ancestors: Vec<Pubkey>,
+    fields: BTreeMap<u32, SolceryType>, // Own and inherited fields
+    field_ids: BTreeMap<u32, u32>,      // New ids of own and inherited fields
+    indexes: Vec<(u32, Pubkey)>,
+}
+
+/// Plans the import of the bundle into a new project owned by `owner`,
+/// `new_key` gives keys of the new accounts
+pub fn import<F>(
+    bundle: &Bundle,
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    owner: &Pubkey,
+    mut new_key: F,
+) -> Result<ImportPlan, BundleError>
+where
+    F: FnMut() -> Pubkey,
+{
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(BundleError::InvalidBundle(format!(
+            "unsupported format {}",
+            bundle.format
+        )));
+    }
+    let mut keys = BTreeMap::new();
+    let mut remap = |key: &str| -> Result<Pubkey, BundleError> {
+        let key = parse_key(key)?;
+        Ok(*keys.entry(key).or_insert_with(&mut new_key))
+    };
+    let project = remap(&bundle.project.key)?;
+    let template_storage = remap(&bundle.project.template_storage)?;
+    for template in &bundle.templates {
+        remap(&template.key)?;
+        for storage in &template.storages {
+            remap(&storage.key)?;
+        }
+    }
+    for object in &bundle.objects {
+        remap(&object.key)?;
+    }
+
+    // Templates are created in bundle order on a fresh counter
+    let mut ids = BTreeMap::new();
+    let mut templates = Vec::new();
+    for (i, entry) in bundle.templates.iter().enumerate() {
+        let id = TEMPLATE_IDS * i as u32;
+        ids.insert(entry.id, id);
+        templates.push(ImportedTemplate {
+            entry,
+            key: keys[&parse_key(&entry.key)?],
+            data: template_data(entry, id, &keys)?,
+            ancestors: Vec::new(),
+            fields: BTreeMap::new(),
+            field_ids: BTreeMap::new(),
+            indexes: Vec::new(),
+        });
+    }
+    for i in 0..templates.len() {
+        let (ancestors, fields) = merge(&templates, i)?;
+        let template = &mut templates[i];
+        template.ancestors = ancestors;
+        template.fields = fields;
+        template.indexes = template
+            .entry
+            .indexed_fields
+            .iter()
+            .map(|field_id| (*field_id, new_key()))
+            .collect();
+    }
+
+    // Updates, ancestors first, take the next ids for the fields of the bundle
+    let mut order: Vec<usize> = (0..templates.len()).collect();
+    order.sort_by_key(|i| templates[*i].ancestors.len());
+    let mut next_id = TEMPLATE_IDS * templates.len() as u32;
+    for i in &order {
+        let template = &mut templates[*i];
+        for field in &template.entry.fields {
+            template.field_ids.insert(field.id, next_id);
+            ids.insert(field.id, next_id);
+            next_id += 1;
+        }
+    }
+    for i in 0..templates.len() {
+        let inherited: Vec<(u32, u32)> = templates
+            .iter()
+            .filter(|x| templates[i].ancestors.contains(&x.key))
+            .flat_map(|x| {
+                x.entry
+                    .fields
+                    .iter()
+                    .map(move |field| (field.id, x.field_ids[&field.id]))
+            })
+            .collect();
+        let template = &mut templates[i];
+        template.field_ids.extend(inherited);
+        for field in &mut template.data.fields {
+            field.id = template.field_ids[&field.id];
+        }
+        for link in &mut template.data.links {
+            link.field_id = template.field_ids[&link.field_id];
+        }
+        template.data.max_field_index =
+            template.data.fields.iter().map(|x| x.id).max().unwrap_or(0);
+        let defaults = template.entry.defaults.iter().map(|x| {
+            let value = remap_value(template.fields.get(&x.id), &x.value, &keys);
+            Ok(FieldDefault {
+                field_id: new_field_id(template, x.id)?,
+                value: encode(x.id, template.fields.get(&x.id), &value)?,
+            })
+        });
+        template.data.defaults = defaults.collect::<Result<_, BundleError>>()?;
+    }
+
+    let mut objects = BTreeMap::new();
+    for object in &bundle.objects {
+        let template = templates
+            .iter()
+            .find(|x| x.entry.key == object.template)
+            .ok_or_else(|| {
+                BundleError::InvalidBundle(format!("unknown template {}", object.template))
+            })?;
+        let data = object_data(object, template, &keys)?;
+        objects.insert(object.key.as_str(), (keys[&parse_key(&object.key)?], data));
+    }
+
+    let mut plan = ImportPlan {
+        accounts: Vec::new(),
+        instructions: Vec::new(),
+        keys: BTreeMap::new(),
+        ids: BTreeMap::new(),
+    };
+    let project_data = Project {
+        uniq_id: 0,
+        name: "New project".to_string(), // Name given by the program
+        owner: *owner,
+        template_storage,
+    };
+    plan.allocate(project, &project_data);
+    let storage = AccountStorage {
+        template: project,
+        sorted: false,
+        accounts: templates.iter().map(|x| x.key).collect(),
+    };
+    plan.allocate(template_storage, &storage);
+    plan.instructions.push(instruction::create_project(
+        program_id,
+        signer,
+        &project,
+        &template_storage,
+        owner,
+    ));
+
+    for template in &templates {
+        let storages = &template.data.storages;
+        let page_capacities: Vec<Option<u32>> = template
+            .entry
+            .storages
+            .iter()
+            .map(|x| x.page_capacity)
+            .collect();
+        // Template is created with a flat storage, paged storages are attached to it
+        let first_storage = storages
+            .iter()
+            .zip(&page_capacities)
+            .find(|(_, page_capacity)| page_capacity.is_none())
+            .map(|(storage, _)| storage)
+            .ok_or_else(|| {
+                BundleError::InvalidBundle(format!(
+                    "template {} has no flat storage",
+                    template.entry.key
+                ))
+            })?;
+        let mut stored = template.data.clone();
+        stored.indexes = template.indexes.iter().map(|x| x.1).collect();
+        stored.backrefs = templates
+            .iter()
+            .flat_map(|x| x.indexes.iter().map(move |index| (x, index)))
+            .filter(|(x, (field_id, _))| link_target(x, *field_id) == Some(template.key))
+            .map(|(_, (_, index))| *index)
+            .collect();
+        if template.entry.published {
+            stored.versions.push(Pubkey::default()); // Snapshot taken on publish
+        }
+        let mut created = TemplateData::new(0, *first_storage);
+        created.storages = storages.clone(); // Before the update
+        plan.accounts.push(NewAccount {
+            key: template.key,
+            space: record_size(&stored).max(record_size(&created)),
+        });
+        plan.instructions.push(instruction::create_template(
+            program_id,
+            signer,
+            &project,
+            &template.key,
+            first_storage,
+            &template_storage,
+        ));
+        for (storage, page_capacity) in storages.iter().zip(&page_capacities) {
+            let attach = match page_capacity {
+                _ if storage == first_storage => continue,
+                None => instruction::attach_storage(
+                    program_id,
+                    signer,
+                    &project,
+                    &template.key,
+                    storage,
+                ),
+                Some(page_capacity) => instruction::attach_paged_storage(
+                    program_id,
+                    signer,
+                    &project,
+                    &template.key,
+                    storage,
+                    *page_capacity,
+                ),
+            };
+            plan.instructions.push(attach);
+        }
+    }
+    for template in order.iter().map(|i| &templates[*i]) {
+        plan.instructions.push(instruction::update_template(
+            program_id,
+            signer,
+            &project,
+            &template.key,
+            &template.data,
+            &template.ancestors,
+        ));
+        for link in &template.data.links {
+            plan.instructions.push(instruction::set_link_rule(
+                program_id,
+                signer,
+                &project,
+                &template.key,
+                link,
+            ));
+        }
+    }
+
+    for template in &templates {
+        for (bundle_field_id, index_key) in &template.indexes {
+            let field_id = new_field_id(template, *bundle_field_id)?;
+            let mut index = FieldIndex {
+                template: template.key,
+                field_id,
+                entries: Vec::new(),
+                cursor: Some(Pubkey::default()), // Room for the cursor of the build
+            };
+            for object in bundle
+                .objects
+                .iter()
+                .filter(|x| x.template == template.entry.key)
+            {
+                let (object_key, data) = &objects[object.key.as_str()];
+                if let Some(value) = field_value(data, field_id) {
+                    index.insert(value, *object_key);
+                }
+            }
+            plan.allocate(*index_key, &index);
+            let mut extra = template.ancestors.clone();
+            extra.extend(link_target(template, *bundle_field_id).filter(|x| *x != template.key));
+            plan.instructions.push(instruction::create_index(
+                program_id,
+                signer,
+                &project,
+                &template.key,
+                index_key,
+                field_id,
+                &extra,
+            ));
+            let mut extra = template.ancestors.clone();
+            extra.extend(&template.data.storages); // Still empty, objects come later
+            plan.instructions.push(instruction::build_index(
+                program_id,
+                signer,
+                &project,
+                &template.key,
+                index_key,
+                None,
+                &extra,
+            ));
+        }
+    }
+
+    let mut created = Vec::new();
+    for template in &templates {
+        let mut extra = template.ancestors.clone();
+        extra.extend(template.indexes.iter().map(|x| x.1));
+        for storage in &template.entry.storages {
+            let storage_key = keys[&parse_key(&storage.key)?];
+            if let Some(page_capacity) = storage.page_capacity {
+                // Pages are split like on chain whenever the page of the next key is full
+                if page_capacity < 2 {
+                    return Err(BundleError::InvalidBundle(format!(
+                        "storage {} has pages of less than two accounts",
+                        storage.key
+                    )));
+                }
+                let mut pages: Vec<(Pubkey, StoragePage)> = Vec::new();
+                for account in &storage.accounts {
+                    let (object_key, _) = objects.get(account.as_str()).ok_or_else(|| {
+                        BundleError::InvalidBundle(format!("unknown object {}", account))
+                    })?;
+                    let covering = |pages: &[(Pubkey, StoragePage)]| {
+                        pages.iter().position(|(_, page)| page.covers(object_key))
+                    };
+                    let mut i = match covering(&pages) {
+                        Some(i) => i,
+                        None => {
+                            pages.push((new_key(), StoragePage::new(storage_key)));
+                            0
+                        }
+                    };
+                    if pages[i].1.accounts.len() >= page_capacity as usize {
+                        let new_page = new_key();
+                        let lower = pages[i].1.split(&new_page).unwrap();
+                        plan.instructions.push(instruction::split_page(
+                            program_id,
+                            signer,
+                            &project,
+                            &storage_key,
+                            &pages[i].0,
+                            &new_page,
+                        ));
+                        pages.push((new_page, lower));
+                        i = covering(&pages).unwrap();
+                    }
+                    pages[i].1.accounts.push(*object_key);
+                    let page = pages[i].0;
+                    if created.contains(object_key) {
+                        plan.instructions.push(instruction::add_to_page(
+                            program_id,
+                            signer,
+                            &project,
+                            &storage_key,
+                            &page,
+                            object_key,
+                        ));
+                        continue;
+                    }
+                    created.push(*object_key);
+                    plan.instructions.push(instruction::create_object(
+                        program_id,
+                        signer,
+                        &project,
+                        &template.key,
+                        &storage_key,
+                        object_key,
+                        Some(&page),
+                        &extra,
+                    ));
+                }
+                for (page, _) in &pages {
+                    plan.accounts.push(NewAccount {
+                        key: *page,
+                        space: StoragePage::space(page_capacity),
+                    });
+                }
+                let storage_data = PagedStorage {
+                    template: template.key,
+                    page_capacity,
+                    len: storage.accounts.len() as u32,
+                    pages: pages.len() as u32,
+                    last_page: Some(Pubkey::default()),
+                };
+                plan.allocate(storage_key, &storage_data);
+                continue;
+            }
+            let mut batch = Vec::new();
+            for account in &storage.accounts {
+                let (object_key, _) = objects.get(account.as_str()).ok_or_else(|| {
+                    BundleError::InvalidBundle(format!("unknown object {}", account))
+                })?;
+                if created.contains(object_key) {
+                    // Objects listed in several storages are created in the first one
+                    plan.instructions.push(instruction::add_to_storage(
+                        program_id,
+                        signer,
+                        &project,
+                        &storage_key,
+                        object_key,
+                    ));
+                    continue;
+                }
+                created.push(*object_key);
+                batch.push(*object_key);
+            }
+            for chunk in batch.chunks(OBJECT_BATCH_SIZE) {
+                let empty = ObjectData {
+                    field_offsets: Vec::new(),
+                    field_data: Vec::new(),
+                };
+                plan.instructions.push(instruction::create_objects(
+                    program_id,
+                    signer,
+                    &project,
+                    &template.key,
+                    &storage_key,
+                    chunk,
+                    &vec![empty; chunk.len()],
+                    &extra,
+                ));
+            }
+            let storage_data = AccountStorage {
+                template: template.key,
+                sorted: storage.sorted,
+                accounts: storage
+                    .accounts
+                    .iter()
+                    .map(|x| objects[x.as_str()].0)
+                    .collect(),
+            };
+            plan.allocate(storage_key, &storage_data);
+        }
+    }
+
+    for object in &bundle.objects {
+        let (object_key, data) = &objects[object.key.as_str()];
+        let template = templates
+            .iter()
+            .find(|x| x.entry.key == object.template)
+            .unwrap();
+        // Objects take ids in the order they are created
+        let position = created.iter().position(|x| x == object_key).ok_or_else(|| {
+            BundleError::InvalidBundle(format!(
+                "object {} is not in any storage of its template",
+                object.key
+            ))
+        })?;
+        let id = next_id + position as u32;
+        ids.insert(object.id, id);
+        plan.allocate(
+            *object_key,
+            &Object {
+                id,
+                template: template.key,
+                template_version: 0,
+                data: data.clone(),
+            },
+        );
+        if data.field_offsets.is_empty() {
+            continue;
+        }
+        let mut extra = template.ancestors.clone();
+        extra.extend(template.indexes.iter().map(|x| x.1));
+        for (field_id, field_type) in &template.fields {
+            if let SolceryType::SLink { .. } = field_type {
+                match field_value(data, new_field_id(template, *field_id)?) {
+                    Some(link) if link.len() == 32 && !extra.contains(&Pubkey::new(&link)) => {
+                        extra.push(Pubkey::new(&link))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        plan.instructions.push(instruction::update_object(
+            program_id,
+            signer,
+            &project,
+            object_key,
+            &template.key,
+            data,
+            &extra,
+        ));
+    }
+
+    // Defaults come after the objects, which carry all their values
+    for template in &templates {
+        for (entry, default) in template.entry.defaults.iter().zip(&template.data.defaults) {
+            let mut extra = template.ancestors.clone();
+            if let Some(SolceryType::SLink { .. }) = template.fields.get(&entry.id) {
+                extra.push(Pubkey::new(&default.value));
+            }
+            plan.instructions.push(instruction::set_default(
+                program_id,
+                signer,
+                &project,
+                &template.key,
+                default,
+                &extra,
+            ));
+        }
+    }
+    for template in &templates {
+        let sorted = template.entry.storages.iter();
+        for storage in sorted.filter(|x| x.sorted && x.page_capacity.is_none()) {
+            plan.instructions.push(instruction::set_sorted(
+                program_id,
+                signer,
+                &project,
+                &keys[&parse_key(&storage.key)?],
+                true,
+            ));
+        }
+    }
+    for template in templates.iter().filter(|x| x.entry.published) {
+        let snapshot = new_key();
+        let space = plan
+            .accounts
+            .iter()
+            .find(|x| x.key == template.key)
+            .unwrap()
+            .space;
+        plan.accounts.push(NewAccount {
+            key: snapshot,
+            space,
+        });
+        plan.instructions.push(instruction::publish_template(
+            program_id,
+            signer,
+            &project,
+            &template.key,
+            &snapshot,
+        ));
+    }
+    plan.keys = keys;
+    plan.ids = ids;
+    Ok(plan)
+}
+
+impl ImportPlan {
+    fn allocate<T: BorshSerialize>(&mut self, key: Pubkey, record: &T) {
+        self.accounts.push(NewAccount {
+            key,
+            space: record_size(record),
+        });
+    }
+}
+
+fn record_size<T: BorshSerialize>(record: &T) -> usize {
+    RecordData::WRITABLE_START_INDEX + record.try_to_vec().unwrap().len()
+}
+
+fn parse_key(key: &str) -> Result<Pubkey, BundleError> {
+    Pubkey::from_str(key).map_err(|_| BundleError::InvalidBundle(format!("invalid key {}", key)))
+}
+
+/// New key of a bundle account, other keys are kept as they are
+fn new_key_of(key: &Pubkey, keys: &BTreeMap<Pubkey, Pubkey>) -> Pubkey {
+    *keys.get(key).unwrap_or(key)
+}
+
+/// Id the project gives to a field of the bundle, own or inherited
+fn new_field_id(template: &ImportedTemplate, field_id: u32) -> Result<u32, BundleError> {
+    template
+        .field_ids
+        .get(&field_id)
+        .copied()
+        .ok_or_else(|| BundleError::InvalidValue(field_id, "unknown field".to_string()))
+}
+
+fn link_target(template: &ImportedTemplate, field_id: u32) -> Option<Pubkey> {
+    match template.fields.get(&field_id) {
+        Some(SolceryType::SLink { template }) => Some(*template),
+        _ => None,
+    }
+}
+
+/// Ancestors of the template and the types of its own and inherited fields
+fn merge(
+    templates: &[ImportedTemplate],
+    index: usize,
+) -> Result<(Vec<Pubkey>, BTreeMap<u32, SolceryType>), BundleError> {
+    let mut ancestors = Vec::new();
+    let mut fields = BTreeMap::new();
+    let mut next = Some(&templates[index]);
+    while let Some(template) = next {
+        for field in &template.data.fields {
+            fields
+                .entry(field.id)
+                .or_insert_with(|| field.params.field_type.clone());
+        }
+        next = match template.data.parent {
+            Some(parent) => {
+                if parent == templates[index].key || ancestors.contains(&parent) {
+                    return Err(BundleError::InvalidBundle(format!(
+                        "template {} is its own ancestor",
+                        templates[index].entry.key
+                    )));
+                }
+                ancestors.push(parent);
+                Some(templates.iter().find(|x| x.key == parent).ok_or_else(|| {
+                    BundleError::InvalidBundle(format!(
+                        "parent of template {} is not in the bundle",
+                        templates[index].entry.key
+                    ))
+                })?)
+            }
+            None => None,
+        };
+    }
+    Ok((ancestors, fields))
+}
+
+/// Template data without defaults, which need the fields of ancestors
+fn template_data(
+    entry: &TemplateEntry,
+    id: u32,
+    keys: &BTreeMap<Pubkey, Pubkey>,
+) -> Result<TemplateData, BundleError> {
+    let fields = entry
+        .fields
+        .iter()
+        .map(|x| field(x, keys))
+        .collect::<Result<Vec<_>, _>>()?;
+    let links = entry
+        .fields
+        .iter()
+        .filter_map(|x| {
+            x.on_delete.map(|on_delete| LinkRule {
+                field_id: x.id,
+                on_delete: match on_delete {
+                    OnDeleteEntry::Restrict => OnDelete::Restrict,
+                    OnDeleteEntry::Nullify => OnDelete::Nullify,
+                    OnDeleteEntry::Cascade => OnDelete::Cascade,
+                },
+            })
+        })
+        .collect();
+    let storages = entry
+        .storages
+        .iter()
+        .map(|x| Ok(new_key_of(&parse_key(&x.key)?, keys)))
+        .collect::<Result<_, BundleError>>()?;
+    let parent = match &entry.parent {
+        Some(parent) => Some(new_key_of(&parse_key(parent)?, keys)),
+        None => None,
+    };
+    Ok(TemplateData {
+        id,
+        name: entry.name.clone(),
+        code: entry.code.clone(),
+        storages,
+        max_field_index: entry.max_field_index,
+        fields,
+        custom_data: entry.custom_data.clone(),
+        indexes: Vec::new(),
+        backrefs: Vec::new(),
+        links,
+        parent,
+        defaults: Vec::new(),
+        version: 0,
+        published: false,
+        versions: Vec::new(),
+    })
+}
+
+fn field(entry: &FieldEntry, keys: &BTreeMap<Pubkey, Pubkey>) -> Result<Field, BundleError> {
+    Ok(Field {
+        id: entry.id,
+        params: FieldParams {
+            field_type: field_type(&entry.field_type, keys)?,
+            name: entry.name.clone(),
+            code: entry.code.clone(),
+            construct_client: entry.construct_client,
+            construct_server: entry.construct_server,
+        },
+    })
+}
+
+fn field_type(
+    field_type: &FieldType,
+    keys: &BTreeMap<Pubkey, Pubkey>,
+) -> Result<SolceryType, BundleError> {
+    Ok(match field_type {
+        FieldType::Error => SolceryType::Error,
+        FieldType::Bool => SolceryType::SBool,
+        FieldType::Int => SolceryType::SInt,
+        FieldType::String => SolceryType::SString,
+        FieldType::Url => SolceryType::SUrl,
+        FieldType::Link { template } => SolceryType::SLink {
+            template: new_key_of(&parse_key(template)?, keys),
+        },
+        FieldType::Brick { brick_type } => SolceryType::SBrick {
+            brick_type: *brick_type,
+        },
+        FieldType::Array { nested_type } => SolceryType::SArray {
+            nested_type: self::nested_type(nested_type, keys)?,
+        },
+        FieldType::Enum { values } => SolceryType::SEnum {
+            values: values.clone(),
+        },
+        FieldType::Map {
+            key_type,
+            value_type,
+        } => SolceryType::SMap {
+            key_type: nested_type(key_type, keys)?,
+            value_type: nested_type(value_type, keys)?,
+        },
+    })
+}
+
+fn nested_type(
+    nested_type: &NestedType,
+    keys: &BTreeMap<Pubkey, Pubkey>,
+) -> Result<SolceryNestedType, BundleError> {
+    Ok(match nested_type {
+        NestedType::Error => SolceryNestedType::Error,
+        NestedType::Bool => SolceryNestedType::SBool,
+        NestedType::Int => SolceryNestedType::SInt,
+        NestedType::String => SolceryNestedType::SString,
+        NestedType::Url => SolceryNestedType::SUrl,
+        NestedType::Link { template } => SolceryNestedType::SLink {
+            template: new_key_of(&parse_key(template)?, keys),
+        },
+        NestedType::Brick => SolceryNestedType::SBrick,
+        NestedType::Array => SolceryNestedType::SArray,
+        NestedType::Enum { values } => SolceryNestedType::SEnum {
+            values: values.clone(),
+        },
+        NestedType::Map => SolceryNestedType::SMap,
+    })
+}
+
+/// Link values pointing into the bundle are replaced with new keys
+fn remap_value(
+    field_type: Option<&SolceryType>,
+    value: &serde_json::Value,
+    keys: &BTreeMap<Pubkey, Pubkey>,
+) -> serde_json::Value {
+    match (field_type, value.as_str().map(Pubkey::from_str)) {
+        (Some(SolceryType::SLink { .. }), Some(Ok(key))) => {
+            serde_json::Value::from(new_key_of(&key, keys).to_string())
+        }
+        _ => value.clone(),
+    }
+}
+
+fn encode(
+    field_id: u32,
+    field_type: Option<&SolceryType>,
+    value: &serde_json::Value,
+) -> Result<Vec<u8>, BundleError> {
+    value::encode(field_type, value).map_err(|e| BundleError::InvalidValue(field_id, e))
+}
+
+fn object_data(
+    object: &ObjectEntry,
+    template: &ImportedTemplate,
+    keys: &BTreeMap<Pubkey, Pubkey>,
+) -> Result<ObjectData, BundleError> {
+    let mut data = ObjectData {
+        field_offsets: Vec::new(),
+        field_data: Vec::new(),
+    };
+    for field in &object.fields {
+        let field_type = template
+            .fields
+            .get(&field.id)
+            .ok_or_else(|| BundleError::InvalidValue(field.id, "unknown field".to_string()))?;
+        // The program only writes links to indexed fields
+        if let SolceryType::SLink { .. } = field_type {
+            if !template.indexes.iter().any(|x| x.0 == field.id) {
+                let message = "link field isn't indexed".to_string();
+                return Err(BundleError::InvalidValue(field.id, message));
+            }
+        }
+        let value = remap_value(Some(field_type), &field.value, keys);
+        let value = encode(field.id, Some(field_type), &value)?;
+        data.field_offsets.push(ObjectFieldData {
+            field_id: new_field_id(template, field.id)?,
+            start_offset: data.field_data.len(),
+            end_offset: data.field_data.len() + value.len(),
+        });
+        data.field_data.extend(value);
+    }
+    Ok(data)
+}
+
+fn field_value(data: &ObjectData, field_id: u32) -> Option<Vec<u8>> {
+    let field = data.field_offsets.iter().find(|x| x.field_id == field_id)?;
+    data.field_data
+        .get(field.start_offset..field.end_offset)
+        .map(|x| x.to_vec())
+}
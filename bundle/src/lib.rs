@@ -0,0 +1,206 @@
+//! Project snapshots as self-contained JSON bundles.
+//!
+//! Bundles are built from account dumps (pubkey to account data) and keep pubkeys as base58
+//! strings, templates ordered by id and objects ordered by template and id, so exporting the
+//! same project twice gives the same bundle.
+
+pub mod error;
+pub mod export;
+pub mod import;
+pub mod value;
+
+use {
+    serde::{Deserialize, Serialize},
+    solana_program::pubkey::Pubkey,
+    solcery_template::{OnDelete, SolceryNestedType, SolceryType},
+    std::collections::BTreeMap,
+};
+
+/// Account data by account key
+pub type AccountDump = BTreeMap<Pubkey, Vec<u8>>;
+
+/// Format of bundles written by this version
+pub const BUNDLE_FORMAT: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Bundle {
+    pub format: u32,
+    pub project: ProjectEntry,
+    pub templates: Vec<TemplateEntry>,
+    pub objects: Vec<ObjectEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProjectEntry {
+    pub key: String,
+    pub name: String,
+    pub owner: String,
+    pub template_storage: String,
+    pub uniq_id: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TemplateEntry {
+    pub key: String,
+    pub id: u32,
+    pub name: String,
+    pub code: String,
+    pub parent: Option<String>,
+    pub version: u32,
+    pub published: bool,
+    pub max_field_index: u32,
+    pub custom_data: String,
+    pub fields: Vec<FieldEntry>,
+    pub defaults: Vec<FieldValueEntry>, // Also overrides defaults of inherited fields
+    pub storages: Vec<StorageEntry>,
+    pub indexed_fields: Vec<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FieldEntry {
+    pub id: u32,
+    pub name: String,
+    pub code: String,
+    pub field_type: FieldType,
+    pub construct_client: bool,
+    pub construct_server: bool,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub on_delete: Option<OnDeleteEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StorageEntry {
+    pub key: String,
+    pub sorted: bool,
+    pub accounts: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub page_capacity: Option<u32>, // Paged storages only, accounts are listed page by page
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ObjectEntry {
+    pub key: String,
+    pub id: u32,
+    pub template: String,
+    pub template_version: u32,
+    pub fields: Vec<FieldValueEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FieldValueEntry {
+    pub id: u32,
+    pub code: Option<String>, // None for fields missing in the template
+    pub value: serde_json::Value,
+}
+
+/// JSON form of `SolceryType`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum FieldType {
+    Error,
+    Bool,
+    Int,
+    String,
+    Url,
+    Link {
+        template: String,
+    },
+    Brick {
+        brick_type: u32,
+    },
+    Array {
+        nested_type: NestedType,
+    },
+    Enum {
+        values: Vec<String>,
+    },
+    Map {
+        key_type: NestedType,
+        value_type: NestedType,
+    },
+}
+
+/// JSON form of `SolceryNestedType`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum NestedType {
+    Error,
+    Bool,
+    Int,
+    String,
+    Url,
+    Link { template: String },
+    Brick,
+    Array,
+    Enum { values: Vec<String> },
+    Map,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum OnDeleteEntry {
+    Restrict,
+    Nullify,
+    Cascade,
+}
+
+impl From<&SolceryType> for FieldType {
+    fn from(field_type: &SolceryType) -> Self {
+        match field_type {
+            SolceryType::Error => FieldType::Error,
+            SolceryType::SBool => FieldType::Bool,
+            SolceryType::SInt => FieldType::Int,
+            SolceryType::SString => FieldType::String,
+            SolceryType::SUrl => FieldType::Url,
+            SolceryType::SLink { template } => FieldType::Link {
+                template: template.to_string(),
+            },
+            SolceryType::SBrick { brick_type } => FieldType::Brick {
+                brick_type: *brick_type,
+            },
+            SolceryType::SArray { nested_type } => FieldType::Array {
+                nested_type: nested_type.into(),
+            },
+            SolceryType::SEnum { values } => FieldType::Enum {
+                values: values.clone(),
+            },
+            SolceryType::SMap {
+                key_type,
+                value_type,
+            } => FieldType::Map {
+                key_type: key_type.into(),
+                value_type: value_type.into(),
+            },
+        }
+    }
+}
+
+impl From<&SolceryNestedType> for NestedType {
+    fn from(nested_type: &SolceryNestedType) -> Self {
+        match nested_type {
+            SolceryNestedType::Error => NestedType::Error,
+            SolceryNestedType::SBool => NestedType::Bool,
+            SolceryNestedType::SInt => NestedType::Int,
+            SolceryNestedType::SString => NestedType::String,
+            SolceryNestedType::SUrl => NestedType::Url,
+            SolceryNestedType::SLink { template } => NestedType::Link {
+                template: template.to_string(),
+            },
+            SolceryNestedType::SBrick => NestedType::Brick,
+            SolceryNestedType::SArray => NestedType::Array,
+            SolceryNestedType::SEnum { values } => NestedType::Enum {
+                values: values.clone(),
+            },
+            SolceryNestedType::SMap => NestedType::Map,
+        }
+    }
+}
+
+impl From<OnDelete> for OnDeleteEntry {
+    fn from(on_delete: OnDelete) -> Self {
+        match on_delete {
+            OnDelete::Restrict => OnDeleteEntry::Restrict,
+            OnDelete::Nullify => OnDeleteEntry::Nullify,
+            OnDelete::Cascade => OnDeleteEntry::Cascade,
+        }
+    }
+}
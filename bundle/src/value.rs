@@ -0,0 +1,147 @@
+//! Field values in object data are borsh encoded by field type:
+//! bools as `bool`, ints as `i32`, strings and urls as `String`, links as the 32 bytes of the
+//! pubkey and enums as the `u32` index of the value. Other types are kept as raw bytes.
+
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    serde_json::{json, Value},
+    solana_program::pubkey::Pubkey,
+    solcery_template::SolceryType,
+    std::{convert::TryFrom, str::FromStr},
+};
+
+/// JSON value of the field, `{"raw": <base64>}` if the data doesn't decode as the field type
+pub fn decode(field_type: Option<&SolceryType>, data: &[u8]) -> Value {
+    decode_typed(field_type, data).unwrap_or_else(|| json!({ "raw": base64::encode(data) }))
+}
+
+fn decode_typed(field_type: Option<&SolceryType>, data: &[u8]) -> Option<Value> {
+    match field_type? {
+        SolceryType::SBool => bool::try_from_slice(data).ok().map(Value::from),
+        SolceryType::SInt => i32::try_from_slice(data).ok().map(Value::from),
+        SolceryType::SString | SolceryType::SUrl => {
+            String::try_from_slice(data).ok().map(Value::from)
+        }
+        SolceryType::SLink { .. } if data.len() == 32 => {
+            Some(Value::from(Pubkey::new(data).to_string()))
+        }
+        SolceryType::SEnum { values } => {
+            let index = u32::try_from_slice(data).ok()?;
+            values.get(index as usize).map(|x| Value::from(x.as_str()))
+        }
+        _ => None,
+    }
+}
+
+/// Field data of the JSON value, the inverse of `decode`
+pub fn encode(field_type: Option<&SolceryType>, value: &Value) -> Result<Vec<u8>, String> {
+    if let Some(raw) = value.get("raw").and_then(Value::as_str) {
+        return base64::decode(raw).map_err(|e| e.to_string());
+    }
+    let invalid = || format!("{} doesn't match the field type", value);
+    let data = match field_type.ok_or("unknown field type")? {
+        SolceryType::SBool => value.as_bool().ok_or_else(invalid)?.try_to_vec(),
+        SolceryType::SInt => {
+            let int = value.as_i64().ok_or_else(invalid)?;
+            i32::try_from(int).map_err(|_| invalid())?.try_to_vec()
+        }
+        SolceryType::SString | SolceryType::SUrl => {
+            value.as_str().ok_or_else(invalid)?.to_string().try_to_vec()
+        }
+        SolceryType::SLink { .. } => {
+            let key = value.as_str().ok_or_else(invalid)?;
+            return Ok(Pubkey::from_str(key)
+                .map_err(|_| invalid())?
+                .to_bytes()
+                .to_vec());
+        }
+        SolceryType::SEnum { values } => {
+            let name = value.as_str().ok_or_else(invalid)?;
+            let index = values.iter().position(|x| x == name).ok_or_else(invalid)?;
+            (index as u32).try_to_vec()
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(data.unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solcery_template::SolceryNestedType};
+
+    #[test]
+    fn round_trip() {
+        let key = Pubkey::new_unique();
+        let kinds = SolceryType::SEnum {
+            values: vec!["Fire".to_string(), "Water".to_string()],
+        };
+        let link = SolceryType::SLink { template: key };
+        let cases = [
+            (SolceryType::SBool, json!(true), vec![1]),
+            (SolceryType::SInt, json!(-2), (-2i32).try_to_vec().unwrap()),
+            (
+                SolceryType::SString,
+                json!("Card"),
+                "Card".try_to_vec().unwrap(),
+            ),
+            (
+                SolceryType::SUrl,
+                json!("https://x"),
+                "https://x".try_to_vec().unwrap(),
+            ),
+            (link, json!(key.to_string()), key.to_bytes().to_vec()),
+            (kinds, json!("Water"), 1u32.try_to_vec().unwrap()),
+        ];
+        for (field_type, value, data) in cases.iter() {
+            assert_eq!(encode(Some(field_type), value).as_ref(), Ok(data));
+            assert_eq!(decode(Some(field_type), data), *value);
+        }
+    }
+
+    #[test]
+    fn raw() {
+        // Data that doesn't decode as the field type is kept as is
+        let array = SolceryType::SArray {
+            nested_type: SolceryNestedType::SInt,
+        };
+        let kinds = SolceryType::SEnum {
+            values: vec!["Fire".to_string()],
+        };
+        let cases = [
+            (None, vec![1, 2]),
+            (Some(&array), vec![1, 0, 0, 0, 7, 0, 0, 0]),
+            (Some(&SolceryType::SInt), vec![1, 2]),
+            (Some(&SolceryType::SBool), vec![2]),
+            (Some(&kinds), 1u32.try_to_vec().unwrap()),
+        ];
+        for (field_type, data) in cases.iter() {
+            let value = decode(*field_type, data);
+            assert_eq!(value, json!({ "raw": base64::encode(data) }));
+            assert_eq!(encode(*field_type, &value).as_ref(), Ok(data));
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let kinds = SolceryType::SEnum {
+            values: vec!["Fire".to_string()],
+        };
+        let link = SolceryType::SLink {
+            template: Pubkey::new_unique(),
+        };
+        let cases = [
+            (None, json!(1)),
+            (Some(&SolceryType::SBool), json!(1)),
+            (Some(&SolceryType::SInt), json!("1")),
+            (Some(&SolceryType::SInt), json!(i64::from(i32::MAX) + 1)),
+            (Some(&SolceryType::SString), json!(true)),
+            (Some(&link), json!("not a key")),
+            (Some(&kinds), json!("Water")),
+            (Some(&SolceryType::SBrick { brick_type: 0 }), json!(1)),
+            (Some(&SolceryType::SInt), json!({ "raw": "%" })),
+        ];
+        for (field_type, value) in cases.iter() {
+            assert!(encode(*field_type, value).is_err(), "{}", value);
+        }
+    }
+}
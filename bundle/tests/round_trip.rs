@@ -0,0 +1,378 @@
+//! A project exported to a bundle and imported into a fresh project comes back the same,
+//! up to the new keys and ids listed in the import plan.
+
+use {
+    borsh::BorshSerialize,
+    serde_json::json,
+    solana_program::{instruction::Instruction, pubkey::Pubkey},
+    solcery_bank::Bank,
+    solcery_bundle::{
+        export::export,
+        import::{import, ImportPlan},
+        Bundle, FieldType, FieldValueEntry,
+    },
+    solcery_crud::RecordKind,
+    solcery_engine::{
+        entrypoint::{process_instruction, super_admins},
+        instruction,
+    },
+    solcery_object::{ObjectData, ObjectFieldData},
+    solcery_storage::paged::StoragePage,
+    solcery_template::{FieldDefault, FieldParams, SolceryType, TemplateData},
+    std::str::FromStr,
+};
+
+const SPACE: usize = 1024;
+
+fn run(bank: &mut Bank, instruction: Instruction) {
+    bank.process(&instruction).unwrap();
+}
+
+fn template(bank: &Bank, key: &Pubkey) -> TemplateData {
+    bank.record(key, RecordKind::Template)
+}
+
+/// Id of the field the last `add_field` created
+fn last_field(bank: &Bank, template_key: &Pubkey) -> u32 {
+    template(bank, template_key).fields.last().unwrap().id
+}
+
+/// Runs the plan the way a client would, allocating the accounts first
+fn apply(bank: &mut Bank, plan: &ImportPlan) {
+    for account in &plan.accounts {
+        bank.insert(account.key, vec![0; account.space]);
+    }
+    for instruction in &plan.instructions {
+        run(bank, instruction.clone());
+    }
+}
+
+fn params(code: &str, field_type: SolceryType) -> FieldParams {
+    FieldParams {
+        field_type,
+        name: code.to_string(),
+        code: code.to_string(),
+        construct_client: false,
+        construct_server: false,
+    }
+}
+
+fn object_data(values: &[(u32, Vec<u8>)]) -> ObjectData {
+    let mut data = ObjectData {
+        field_offsets: Vec::new(),
+        field_data: Vec::new(),
+    };
+    for (field_id, value) in values {
+        data.field_offsets.push(ObjectFieldData {
+            field_id: *field_id,
+            start_offset: data.field_data.len(),
+            end_offset: data.field_data.len() + value.len(),
+        });
+        data.field_data.extend_from_slice(value);
+    }
+    data
+}
+
+/// Project with a published base template, a card template inheriting from it with a flat and
+/// a paged storage, field values of every encoded type, a link default and indexes
+fn source_project(bank: &mut Bank, admin: &Pubkey, owner: &Pubkey) -> Pubkey {
+    let program_id = bank.program_id;
+    let (project, template_storage) = (bank.alloc(SPACE), bank.alloc(SPACE));
+    run(
+        bank,
+        instruction::create_project(&program_id, admin, &project, &template_storage, owner),
+    );
+    let create_template = |bank: &mut Bank| {
+        let (template, storage) = (bank.alloc(SPACE), bank.alloc(SPACE));
+        run(
+            bank,
+            instruction::create_template(
+                &program_id,
+                admin,
+                &project,
+                &template,
+                &storage,
+                &template_storage,
+            ),
+        );
+        (template, storage)
+    };
+    let (base, base_storage) = create_template(bank);
+    let add_field = |bank: &mut Bank, template: &Pubkey, code: &str, field_type| {
+        let params = params(code, field_type);
+        run(
+            bank,
+            instruction::add_field(&program_id, admin, &project, template, &params),
+        );
+        last_field(bank, template)
+    };
+    let power = add_field(bank, &base, "power", SolceryType::SInt);
+    // The card takes an id the import gives to another template
+    let (card, card_storage) = create_template(bank);
+    let kind = SolceryType::SEnum {
+        values: vec!["Fire".to_string(), "Water".to_string()],
+    };
+    let kind = add_field(bank, &base, "kind", kind);
+    let name = template(bank, &base).fields[0].id;
+    let enabled = template(bank, &base).fields[1].id;
+
+    run(
+        bank,
+        instruction::set_parent(&program_id, admin, &project, &card, &base, &[]),
+    );
+    let target = SolceryType::SLink { template: base };
+    let target = add_field(bank, &card, "target", target);
+    let url = add_field(bank, &card, "art", SolceryType::SUrl);
+    let paged = bank.alloc(SPACE);
+    run(
+        bank,
+        instruction::attach_paged_storage(&program_id, admin, &project, &card, &paged, 2),
+    );
+
+    let mut bases = Vec::new();
+    for (power_value, kind_value) in [(3i32, 0u32), (-7, 1)].iter() {
+        let object = bank.alloc(SPACE);
+        run(
+            bank,
+            instruction::create_object(
+                &program_id,
+                admin,
+                &project,
+                &base,
+                &base_storage,
+                &object,
+                None,
+                &[],
+            ),
+        );
+        let data = object_data(&[
+            (name, "Spark".to_string().try_to_vec().unwrap()),
+            (enabled, true.try_to_vec().unwrap()),
+            (power, power_value.try_to_vec().unwrap()),
+            (kind, kind_value.try_to_vec().unwrap()),
+        ]);
+        run(
+            bank,
+            instruction::update_object(&program_id, admin, &project, &object, &base, &data, &[]),
+        );
+        bases.push(object);
+    }
+    let index = bank.alloc(SPACE);
+    run(
+        bank,
+        instruction::create_index(&program_id, admin, &project, &base, &index, power, &[]),
+    );
+    run(
+        bank,
+        instruction::build_index(
+            &program_id,
+            admin,
+            &project,
+            &base,
+            &index,
+            None,
+            &[base_storage, bases[0], bases[1]],
+        ),
+    );
+
+    // Links are only written to indexed fields
+    let links = bank.alloc(SPACE);
+    run(
+        bank,
+        instruction::create_index(&program_id, admin, &project, &card, &links, target, &[base]),
+    );
+    run(
+        bank,
+        instruction::build_index(
+            &program_id,
+            admin,
+            &project,
+            &card,
+            &links,
+            None,
+            &[base, card_storage, paged],
+        ),
+    );
+
+    // Two cards fill the first page, which is split for the third one,
+    // the fourth one is in the flat storage
+    let first_page = bank.alloc(StoragePage::space(2));
+    let mut pages = vec![first_page];
+    for i in 0..4 {
+        let object = bank.alloc(SPACE);
+        if i == 2 {
+            let new_page = bank.alloc(StoragePage::space(2));
+            run(
+                bank,
+                instruction::split_page(
+                    &program_id,
+                    admin,
+                    &project,
+                    &paged,
+                    &first_page,
+                    &new_page,
+                ),
+            );
+            pages.push(new_page);
+        }
+        let (storage, page) = match i {
+            3 => (card_storage, None),
+            0 | 1 => (paged, Some(first_page)),
+            _ => {
+                let covering = pages.iter().copied().find(|x| {
+                    bank.record::<StoragePage>(x, RecordKind::StoragePage)
+                        .covers(&object)
+                });
+                (paged, covering)
+            }
+        };
+        let extra = vec![base, links];
+        run(
+            bank,
+            instruction::create_object(
+                &program_id,
+                admin,
+                &project,
+                &card,
+                &storage,
+                &object,
+                page.as_ref(),
+                &extra,
+            ),
+        );
+        let data = object_data(&[
+            (power, (i as i32).try_to_vec().unwrap()),
+            (target, bases[i % 2].to_bytes().to_vec()),
+            (url, format!("https://art/{}", i).try_to_vec().unwrap()),
+        ]);
+        run(
+            bank,
+            instruction::update_object(
+                &program_id,
+                admin,
+                &project,
+                &object,
+                &card,
+                &data,
+                &[base, links, bases[i % 2]],
+            ),
+        );
+    }
+
+    let default = FieldDefault {
+        field_id: target,
+        value: bases[0].to_bytes().to_vec(),
+    };
+    run(
+        bank,
+        instruction::set_default(
+            &program_id,
+            admin,
+            &project,
+            &card,
+            &default,
+            &[base, bases[0]],
+        ),
+    );
+    run(
+        bank,
+        instruction::set_sorted(&program_id, admin, &project, &base_storage, true),
+    );
+    let snapshot = bank.alloc(SPACE);
+    run(
+        bank,
+        instruction::publish_template(&program_id, admin, &project, &base, &snapshot),
+    );
+    project
+}
+
+/// Bundle with the keys and ids replaced as the import plan says, objects ordered by key
+/// and sorted storages compared as sets
+fn normalize(bundle: &Bundle, plan: Option<&ImportPlan>) -> Bundle {
+    let key = |key: &str| match plan {
+        Some(plan) => plan.keys[&Pubkey::from_str(key).unwrap()].to_string(),
+        None => key.to_string(),
+    };
+    let id = |id: u32| plan.map_or(id, |plan| plan.ids[&id]);
+    let value = |entry: &FieldValueEntry| {
+        let remapped = entry.value.as_str().and_then(|x| Pubkey::from_str(x).ok());
+        FieldValueEntry {
+            id: id(entry.id),
+            code: entry.code.clone(),
+            value: match (remapped, plan) {
+                (Some(linked), Some(plan)) => json!(plan.keys[&linked].to_string()),
+                _ => entry.value.clone(),
+            },
+        }
+    };
+    let mut bundle = bundle.clone();
+    bundle.project.key = key(&bundle.project.key);
+    bundle.project.template_storage = key(&bundle.project.template_storage);
+    for template in &mut bundle.templates {
+        template.key = key(&template.key);
+        template.id = id(template.id);
+        template.parent = template.parent.as_deref().map(key);
+        template.max_field_index = id(template.max_field_index);
+        for field in &mut template.fields {
+            field.id = id(field.id);
+            if let FieldType::Link { template } = &mut field.field_type {
+                *template = key(template);
+            }
+        }
+        template.defaults = template.defaults.iter().map(value).collect();
+        for storage in &mut template.storages {
+            storage.key = key(&storage.key);
+            storage.accounts = storage.accounts.iter().map(|x| key(x)).collect();
+            if storage.sorted {
+                storage.accounts.sort();
+            }
+        }
+        template.indexed_fields = template.indexed_fields.iter().map(|x| id(*x)).collect();
+    }
+    for object in &mut bundle.objects {
+        object.key = key(&object.key);
+        object.id = id(object.id);
+        object.template = key(&object.template);
+        object.fields = object.fields.iter().map(value).collect();
+    }
+    bundle.objects.sort_by(|a, b| a.key.cmp(&b.key));
+    bundle
+}
+
+#[test]
+fn round_trip() {
+    let mut bank = Bank::new(Pubkey::new_unique(), process_instruction);
+    let (admin, owner) = (super_admins()[0], Pubkey::new_unique());
+    let project = source_project(&mut bank, &admin, &owner);
+    let bundle = export(&bank.dump(), &project).unwrap();
+
+    let plan = import(
+        &bundle,
+        &bank.program_id,
+        &admin,
+        &owner,
+        Pubkey::new_unique,
+    )
+    .unwrap();
+    apply(&mut bank, &plan);
+    let new_project = plan.keys[&project];
+    let imported = export(&bank.dump(), &new_project).unwrap();
+
+    // Ids are the ones the new project gave, the plan knows them in advance
+    let max_id = plan.ids.values().max().unwrap();
+    assert_eq!(imported.project.uniq_id, max_id + 1);
+    assert_eq!(bundle.templates[1].id, 4);
+    assert_eq!(imported.templates[1].id, 3);
+    let mut expected = normalize(&bundle, Some(&plan));
+    expected.project.uniq_id = imported.project.uniq_id;
+    assert_eq!(normalize(&imported, None), expected);
+
+    // Values of every encoded type made it through the bundle
+    let values: Vec<_> = bundle.objects[0].fields.iter().map(|x| &x.value).collect();
+    assert_eq!(
+        values,
+        vec![&json!("Spark"), &json!(true), &json!(3), &json!("Fire")]
+    );
+    let paged = &bundle.templates[1].storages[1];
+    assert_eq!((paged.page_capacity, paged.accounts.len()), (Some(2), 3));
+}
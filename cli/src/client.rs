@@ -0,0 +1,41 @@
+use {
+    crate::Error,
+    borsh::BorshDeserialize,
+    solana_program::{instruction::Instruction, pubkey::Pubkey},
+    solana_sdk::signature::Keypair,
+    solcery_crud::RecordKind,
+};
+
+/// Account allocated for the engine before running instructions
+pub struct NewAccount {
+    pub keypair: Keypair,
+    pub space: usize,
+}
+
+/// Cluster or in-process bank the commands run against
+pub trait Client {
+    fn program_id(&self) -> Pubkey;
+
+    /// Signer of engine instructions
+    fn signer(&self) -> Pubkey;
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Allocates the accounts and runs the instructions in order
+    fn send(
+        &mut self,
+        accounts: Vec<NewAccount>,
+        instructions: Vec<Instruction>,
+    ) -> Result<(), Error>;
+
+    fn record<T: BorshDeserialize>(&self, key: &Pubkey, kind: RecordKind) -> Result<T, Error>
+    where
+        Self: Sized,
+    {
+        let data = self
+            .account(key)?
+            .ok_or_else(|| format!("Account {} not found", key))?;
+        solcery_crud::decode_record(&data, kind)
+            .map_err(|e| format!("Account {} is not a {:?} record: {}", key, kind, e).into())
+    }
+}
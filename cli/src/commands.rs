@@ -0,0 +1,448 @@
+use {
+    crate::{
+        client::{Client, NewAccount},
+        inspect::{self, TemplateInfo},
+        Error,
+    },
+    serde_json::Value,
+    solana_program::pubkey::Pubkey,
+    solana_sdk::signature::{Keypair, Signer},
+    solcery_bundle::value,
+    solcery_crud::{RecordData, RecordKind},
+    solcery_engine::instruction,
+    solcery_object::{index, Object, ObjectData, ObjectFieldData},
+    solcery_project::Project,
+    solcery_storage::AccountStorage,
+    solcery_template::{Field, FieldParams, SolceryNestedType, SolceryType, TemplateData},
+    std::{fs, str::FromStr},
+};
+
+pub const PROJECT_SPACE: usize = 256;
+
+/// Accounts a new storage has room for
+pub const STORAGE_CAPACITY: usize = 256;
+
+fn storage_space() -> usize {
+    AccountStorage::KEYS_OFFSET + STORAGE_CAPACITY * 32
+}
+
+fn new_account(space: usize) -> NewAccount {
+    NewAccount {
+        keypair: Keypair::new(),
+        space,
+    }
+}
+
+/// Project the record belongs to, as stored in its header
+fn project_of<C: Client>(client: &C, key: &Pubkey) -> Result<Pubkey, Error> {
+    let data = client
+        .account(key)?
+        .ok_or_else(|| format!("Account {} not found", key))?;
+    let project = data
+        .get(1..RecordData::KIND_INDEX)
+        .ok_or_else(|| format!("Account {} is not a record", key))?;
+    Ok(Pubkey::new(project))
+}
+
+/// Fields of the template and its ancestors sorted by id, and the ancestors
+fn merged_fields<C: Client>(
+    client: &C,
+    template: &TemplateData,
+) -> Result<(Vec<Field>, Vec<Pubkey>), Error> {
+    let mut fields = template.fields.clone();
+    let mut ancestors = Vec::new();
+    let mut next = template.parent;
+    while let Some(parent_key) = next {
+        if ancestors.contains(&parent_key) {
+            return Err(format!("Template {} is its own ancestor", parent_key).into());
+        }
+        let parent: TemplateData = client.record(&parent_key, RecordKind::Template)?;
+        fields.extend(parent.fields);
+        ancestors.push(parent_key);
+        next = parent.parent;
+    }
+    fields.sort_by_key(|x| x.id);
+    Ok((fields, ancestors))
+}
+
+/// Field types as written on the command line: `bool`, `int`, `string`, `url`,
+/// `link:<template>` and `enum:<value>,<value>...`
+pub fn parse_type(field_type: &str) -> Result<SolceryType, Error> {
+    let (name, arg) = match field_type.find(':') {
+        Some(index) => (&field_type[..index], Some(&field_type[index + 1..])),
+        None => (field_type, None),
+    };
+    Ok(match (name, arg) {
+        ("bool", None) => SolceryType::SBool,
+        ("int", None) => SolceryType::SInt,
+        ("string", None) => SolceryType::SString,
+        ("url", None) => SolceryType::SUrl,
+        ("link", Some(template)) => SolceryType::SLink {
+            template: Pubkey::from_str(template)?,
+        },
+        ("enum", Some(values)) => SolceryType::SEnum {
+            values: values.split(',').map(str::to_string).collect(),
+        },
+        _ => return Err(format!("Unsupported field type {}", field_type).into()),
+    })
+}
+
+pub fn format_type(field_type: &SolceryType) -> String {
+    match field_type {
+        SolceryType::Error => "error".to_string(),
+        SolceryType::SBool => "bool".to_string(),
+        SolceryType::SInt => "int".to_string(),
+        SolceryType::SString => "string".to_string(),
+        SolceryType::SUrl => "url".to_string(),
+        SolceryType::SLink { template } => format!("link:{}", template),
+        SolceryType::SBrick { brick_type } => format!("brick:{}", brick_type),
+        SolceryType::SArray { nested_type } => format!("array:{}", format_nested(nested_type)),
+        SolceryType::SEnum { values } => format!("enum:{}", values.join(",")),
+        SolceryType::SMap {
+            key_type,
+            value_type,
+        } => format!(
+            "map:{}:{}",
+            format_nested(key_type),
+            format_nested(value_type)
+        ),
+    }
+}
+
+fn format_nested(nested_type: &SolceryNestedType) -> String {
+    match nested_type {
+        SolceryNestedType::Error => "error".to_string(),
+        SolceryNestedType::SBool => "bool".to_string(),
+        SolceryNestedType::SInt => "int".to_string(),
+        SolceryNestedType::SString => "string".to_string(),
+        SolceryNestedType::SUrl => "url".to_string(),
+        SolceryNestedType::SLink { template } => format!("link({})", template),
+        SolceryNestedType::SBrick => "brick".to_string(),
+        SolceryNestedType::SArray => "array".to_string(),
+        SolceryNestedType::SEnum { values } => format!("enum({})", values.join(",")),
+        SolceryNestedType::SMap => "map".to_string(),
+    }
+}
+
+pub fn project_create<C: Client>(client: &mut C, owner: Option<Pubkey>) -> Result<(), Error> {
+    let project = new_account(PROJECT_SPACE);
+    let template_storage = new_account(storage_space());
+    let owner = owner.unwrap_or_else(|| client.signer());
+    let create = instruction::create_project(
+        &client.program_id(),
+        &client.signer(),
+        &project.keypair.pubkey(),
+        &template_storage.keypair.pubkey(),
+        &owner,
+    );
+    println!("Project: {}", project.keypair.pubkey());
+    println!("Template storage: {}", template_storage.keypair.pubkey());
+    client.send(vec![project, template_storage], vec![create])
+}
+
+pub fn project_show<C: Client>(client: &C, key: &Pubkey) -> Result<(), Error> {
+    let project: Project = client.record(key, RecordKind::Project)?;
+    println!("Project: {}", key);
+    println!("Name: {}", project.name);
+    println!("Owner: {}", project.owner);
+    println!("Template storage: {}", project.template_storage);
+    println!("Next id: {}", project.uniq_id);
+    Ok(())
+}
+
+pub fn template_list<C: Client>(client: &C, project_key: &Pubkey) -> Result<(), Error> {
+    let project: Project = client.record(project_key, RecordKind::Project)?;
+    let storage: AccountStorage = client.record(&project.template_storage, RecordKind::Storage)?;
+    for key in &storage.accounts {
+        let template: TemplateData = client.record(key, RecordKind::Template)?;
+        println!(
+            "{} {:>5} {} ({}) v{}{}",
+            key,
+            template.id,
+            template.code,
+            template.name,
+            template.version,
+            if template.published { "" } else { " draft" }
+        );
+    }
+    Ok(())
+}
+
+pub fn template_create<C: Client>(
+    client: &mut C,
+    project_key: &Pubkey,
+    space: usize,
+) -> Result<(), Error> {
+    let project: Project = client.record(project_key, RecordKind::Project)?;
+    let template = new_account(space);
+    let storage = new_account(storage_space());
+    let create = instruction::create_template(
+        &client.program_id(),
+        &client.signer(),
+        project_key,
+        &template.keypair.pubkey(),
+        &storage.keypair.pubkey(),
+        &project.template_storage,
+    );
+    println!("Template: {}", template.keypair.pubkey());
+    println!("Storage: {}", storage.keypair.pubkey());
+    client.send(vec![template, storage], vec![create])
+}
+
+/// Adds the field through the add_field instruction, the id comes from the project counter
+pub fn template_add_field<C: Client>(
+    client: &mut C,
+    template_key: &Pubkey,
+    params: FieldParams,
+) -> Result<(), Error> {
+    let project_key = project_of(client, template_key)?;
+    let add = instruction::add_field(
+        &client.program_id(),
+        &client.signer(),
+        &project_key,
+        template_key,
+        &params,
+    );
+    client.send(Vec::new(), vec![add])?;
+    let template: TemplateData = client.record(template_key, RecordKind::Template)?;
+    let field = template
+        .fields
+        .last()
+        .ok_or_else(|| format!("Template {} has no fields", template_key))?;
+    println!("Field id: {}", field.id);
+    Ok(())
+}
+
+pub fn template_show<C: Client>(client: &C, key: &Pubkey) -> Result<(), Error> {
+    let template: TemplateData = client.record(key, RecordKind::Template)?;
+    let (fields, _) = merged_fields(client, &template)?;
+    println!("Template: {}", key);
+    println!("Id: {}", template.id);
+    println!("Name: {}", template.name);
+    println!("Code: {}", template.code);
+    println!(
+        "Version: {}{}",
+        template.version,
+        if template.published { "" } else { " (draft)" }
+    );
+    if let Some(parent) = template.parent {
+        println!("Parent: {}", parent);
+    }
+    for storage in &template.storages {
+        println!("Storage: {}", storage);
+    }
+    for index_key in &template.indexes {
+        let index: index::FieldIndex = client.record(index_key, RecordKind::Index)?;
+        println!("Index: {} (field {})", index_key, index.field_id);
+    }
+    println!("Fields:");
+    for field in &fields {
+        let inherited = !template.fields.iter().any(|x| x.id == field.id);
+        println!(
+            "{:>5} {} ({}): {}{}",
+            field.id,
+            field.params.code,
+            field.params.name,
+            format_type(&field.params.field_type),
+            if inherited { ", inherited" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+pub fn object_create<C: Client>(
+    client: &mut C,
+    template_key: &Pubkey,
+    storage: Option<Pubkey>,
+    space: usize,
+) -> Result<(), Error> {
+    let project = project_of(client, template_key)?;
+    let template: TemplateData = client.record(template_key, RecordKind::Template)?;
+    let storage = match storage {
+        Some(storage) => storage,
+        None => *template
+            .storages
+            .first()
+            .ok_or_else(|| format!("Template {} has no storages", template_key))?,
+    };
+    let (fields, mut extra) = merged_fields(client, &template)?;
+    // Objects that link defaults point to are checked on create
+    let mut defaults = template.defaults.clone();
+    for ancestor in extra.clone() {
+        let ancestor: TemplateData = client.record(&ancestor, RecordKind::Template)?;
+        defaults.extend(ancestor.defaults);
+    }
+    for field in fields
+        .iter()
+        .filter(|x| matches!(x.params.field_type, SolceryType::SLink { .. }))
+    {
+        if let Some(default) = defaults
+            .iter()
+            .find(|x| x.field_id == field.id && x.value.len() == 32)
+        {
+            extra.push(Pubkey::new(&default.value));
+        }
+    }
+    extra.extend(&template.indexes);
+    let object = new_account(space);
+    let create = instruction::create_object(
+        &client.program_id(),
+        &client.signer(),
+        &project,
+        template_key,
+        &storage,
+        &object.keypair.pubkey(),
+        None,
+        &extra,
+    );
+    println!("Object: {}", object.keypair.pubkey());
+    client.send(vec![object], vec![create])
+}
+
+pub fn object_get<C: Client>(client: &C, key: &Pubkey) -> Result<(), Error> {
+    let object: Object = client.record(key, RecordKind::Object)?;
+    let template: TemplateData = client.record(&object.template, RecordKind::Template)?;
+    let (fields, _) = merged_fields(client, &template)?;
+    println!("Object: {}", key);
+    println!("Id: {}", object.id);
+    println!("Template: {} ({})", object.template, template.code);
+    println!("Template version: {}", object.template_version);
+    println!("Fields:");
+    for offsets in &object.data.field_offsets {
+        let field = fields.iter().find(|x| x.id == offsets.field_id);
+        let data = object
+            .data
+            .field_data
+            .get(offsets.start_offset..offsets.end_offset)
+            .unwrap_or_default();
+        println!(
+            "{:>5} {}: {}",
+            offsets.field_id,
+            field.map_or("<unknown>", |x| x.params.code.as_str()),
+            value::decode(field.map(|x| &x.params.field_type), data)
+        );
+    }
+    Ok(())
+}
+
+/// Sets the field given by code or id, the value is JSON or a plain string
+pub fn object_set_field<C: Client>(
+    client: &mut C,
+    key: &Pubkey,
+    field: &str,
+    value: &str,
+) -> Result<(), Error> {
+    let project = project_of(client, key)?;
+    let object: Object = client.record(key, RecordKind::Object)?;
+    let template: TemplateData = client.record(&object.template, RecordKind::Template)?;
+    let (fields, ancestors) = merged_fields(client, &template)?;
+    let field = fields
+        .iter()
+        .find(|x| x.params.code == field || x.id.to_string() == field)
+        .ok_or_else(|| format!("Unknown field {}", field))?;
+    let json = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+    let value = value::encode(Some(&field.params.field_type), &json)?;
+
+    let mut data = ObjectData {
+        field_offsets: Vec::new(),
+        field_data: Vec::new(),
+    };
+    let mut push = |field_id: u32, value: &[u8]| {
+        data.field_offsets.push(ObjectFieldData {
+            field_id,
+            start_offset: data.field_data.len(),
+            end_offset: data.field_data.len() + value.len(),
+        });
+        data.field_data.extend_from_slice(value);
+    };
+    let mut replaced = false;
+    for offsets in &object.data.field_offsets {
+        if offsets.field_id == field.id {
+            push(field.id, &value);
+            replaced = true;
+        } else {
+            let old = index::field_value(&object, offsets.field_id).unwrap_or_default();
+            push(offsets.field_id, &old);
+        }
+    }
+    if !replaced {
+        push(field.id, &value);
+    }
+
+    let mut extra = ancestors;
+    extra.extend(&template.indexes);
+    let updated = Object {
+        data: data.clone(),
+        ..object.clone()
+    };
+    for field in &fields {
+        if let SolceryType::SLink { .. } = field.params.field_type {
+            match index::field_value(&updated, field.id) {
+                Some(link) if link.len() == 32 => extra.push(Pubkey::new(&link)),
+                _ => {}
+            }
+        }
+    }
+    let update = instruction::update_object(
+        &client.program_id(),
+        &client.signer(),
+        &project,
+        key,
+        &object.template,
+        &data,
+        &extra,
+    );
+    client.send(Vec::new(), vec![update])
+}
+
+pub fn storage_list<C: Client>(client: &C, key: &Pubkey) -> Result<(), Error> {
+    let storage: AccountStorage = client.record(key, RecordKind::Storage)?;
+    println!("Storage: {}", key);
+    println!("Template: {}", storage.template);
+    println!("Sorted: {}", storage.sorted);
+    println!("Accounts: {}", storage.accounts.len());
+    for account in &storage.accounts {
+        println!("{}", account);
+    }
+    Ok(())
+}
+
+/// Decodes the account given by key or read from a file. Object fields are named after the
+/// supplied template or the template of the object if it can be fetched
+pub fn inspect_account<C: Client>(
+    client: Option<&C>,
+    key: Option<Pubkey>,
+    file: Option<&str>,
+    template: Option<Pubkey>,
+) -> Result<(), Error> {
+    let data = match (file, key, client) {
+        (Some(file), _, _) => fs::read(file)?,
+        (None, Some(key), Some(client)) => client
+            .account(&key)?
+            .ok_or_else(|| format!("Account {} not found", key))?,
+        _ => return Err("Nothing to inspect".into()),
+    };
+    let template_info = match (client, template.or_else(|| inspect::object_template(&data))) {
+        (Some(client), Some(template_key)) => {
+            let fetched = client.record::<TemplateData>(&template_key, RecordKind::Template);
+            match (fetched, template) {
+                (Ok(data), _) => {
+                    let (fields, _) = merged_fields(client, &data)?;
+                    Some(TemplateInfo {
+                        key: template_key,
+                        data,
+                        fields,
+                    })
+                }
+                (Err(e), Some(_)) => return Err(e),
+                (Err(_), None) => None, // Fields stay unnamed
+            }
+        }
+        _ => None,
+    };
+    print!(
+        "{}",
+        inspect::inspect(&data, key.as_ref(), template_info.as_ref())
+    );
+    Ok(())
+}
@@ -0,0 +1,860 @@
+//! Decoding of raw account data for troubleshooting.
+//!
+//! The record kind is taken from the header, the record is decoded with the current layout and
+//! everything that doesn't add up is reported as an issue instead of failing: bytes left after
+//! the record, field offsets out of the field data, unsorted storages and the like.
+
+use {
+    crate::commands::format_type,
+    borsh::BorshDeserialize,
+    solana_program::pubkey::Pubkey,
+    solcery_bundle::value,
+    solcery_crud::{RecordData, RecordKind},
+    solcery_object::{index::FieldIndex, Object},
+    solcery_project::Project,
+    solcery_storage::{
+        paged::{PagedStorage, StoragePage},
+        AccountStorage,
+    },
+    solcery_template::{Field, SolceryType, TemplateData},
+    std::fmt,
+};
+
+/// Template used to resolve object fields, `fields` include inherited ones
+pub struct TemplateInfo {
+    pub key: Pubkey,
+    pub data: TemplateData,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub lines: Vec<String>,
+    pub issues: Vec<String>,
+}
+
+impl Report {
+    fn line(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    fn issue(&mut self, issue: String) {
+        self.issues.push(issue);
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        if self.issues.is_empty() {
+            return writeln!(f, "No issues found");
+        }
+        writeln!(f, "Issues:")?;
+        for issue in &self.issues {
+            writeln!(f, "  - {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Template of the object record in `data`, for looking up field names
+pub fn object_template(data: &[u8]) -> Option<Pubkey> {
+    let payload = data.get(RecordData::WRITABLE_START_INDEX..)?;
+    match data.get(RecordData::KIND_INDEX) {
+        Some(kind) if *kind == RecordKind::Object as u8 => {
+            Object::deserialize(&mut &*payload).ok().map(|x| x.template)
+        }
+        _ => None,
+    }
+}
+
+pub fn inspect(data: &[u8], key: Option<&Pubkey>, template: Option<&TemplateInfo>) -> Report {
+    let mut report = Report::default();
+    report.line(format!("Size: {} bytes", data.len()));
+    let version = match data.first() {
+        None => {
+            report.issue("account is empty".to_string());
+            return report;
+        }
+        Some(&RecordData::UNINITIALIZED) => {
+            report.line("Version: uninitialized".to_string());
+            if let Some(offset) = data.iter().position(|x| *x != 0) {
+                report.issue(format!(
+                    "uninitialized account has data at offset {}",
+                    offset
+                ));
+            }
+            return report;
+        }
+        Some(version) => *version,
+    };
+    report.line(format!("Version: {}", version));
+    if data.len() < RecordData::WRITABLE_START_INDEX {
+        report.issue(format!(
+            "account is shorter than the {} byte header",
+            RecordData::WRITABLE_START_INDEX
+        ));
+        return report;
+    }
+    let project = Pubkey::new(&data[1..RecordData::KIND_INDEX]);
+    report.line(format!("Project: {}", project));
+    if version <= RecordData::LEGACY_VERSION {
+        report.issue(format!(
+            "version {} predates record kinds, the record has to be migrated",
+            version
+        ));
+        return report;
+    }
+    let kind = match RecordKind::try_from_slice(&data[RecordData::KIND_INDEX..][..1]) {
+        Ok(kind) => kind,
+        Err(_) => {
+            report.issue(format!(
+                "unknown record kind {}",
+                data[RecordData::KIND_INDEX]
+            ));
+            return report;
+        }
+    };
+    report.line(format!("Kind: {:?}", kind));
+    if version < RecordData::CURRENT_VERSION {
+        report.issue(format!(
+            "version {} is outdated, the record has to be migrated before it is decoded",
+            version
+        ));
+        return report;
+    }
+    if version > RecordData::CURRENT_VERSION {
+        report.issue(format!(
+            "version {} is newer than supported version {}",
+            version,
+            RecordData::CURRENT_VERSION
+        ));
+        return report;
+    }
+
+    let payload = &data[RecordData::WRITABLE_START_INDEX..];
+    match kind {
+        RecordKind::Project => {
+            if let Some(project_data) = decode::<Project>(payload, &mut report) {
+                inspect_project(&project_data, &project, key, &mut report);
+            }
+        }
+        RecordKind::Template => {
+            if let Some(template_data) = decode::<TemplateData>(payload, &mut report) {
+                inspect_template(&template_data, &mut report);
+            }
+        }
+        RecordKind::TemplateVersion => {
+            if let Some(snapshot) = decode::<TemplateData>(payload, &mut report) {
+                inspect_template(&snapshot, &mut report);
+                if !snapshot.published {
+                    report.issue("template version is not published".to_string());
+                }
+            }
+        }
+        RecordKind::Storage => {
+            if let Some(storage) = decode::<AccountStorage>(payload, &mut report) {
+                inspect_storage(&storage, &mut report);
+            }
+        }
+        RecordKind::PagedStorage => {
+            if let Some(storage) = decode::<PagedStorage>(payload, &mut report) {
+                inspect_paged_storage(&storage, &mut report);
+            }
+        }
+        RecordKind::StoragePage => {
+            if let Some(page) = decode::<StoragePage>(payload, &mut report) {
+                inspect_page(&page, data.len(), &mut report);
+            }
+        }
+        RecordKind::Object => {
+            if let Some(object) = decode::<Object>(payload, &mut report) {
+                inspect_object(&object, template, &mut report);
+            }
+        }
+        RecordKind::Index => {
+            if let Some(index) = decode::<FieldIndex>(payload, &mut report) {
+                inspect_index(&index, &mut report);
+            }
+        }
+        _ => report.line(format!("{:?} records are not decoded", kind)),
+    }
+    report
+}
+
+/// Decodes the record and checks that the rest of the account is zeroed
+fn decode<T: BorshDeserialize>(payload: &[u8], report: &mut Report) -> Option<T> {
+    let mut rest = payload;
+    match T::deserialize(&mut rest) {
+        Ok(record) => {
+            let used = payload.len() - rest.len();
+            report.line(format!("Record: {} bytes, {} bytes free", used, rest.len()));
+            if let Some(offset) = rest.iter().position(|x| *x != 0) {
+                report.issue(format!(
+                    "data after the record at offset {}",
+                    RecordData::WRITABLE_START_INDEX + used + offset
+                ));
+            }
+            Some(record)
+        }
+        Err(e) => {
+            report.issue(format!("record doesn't decode: {}", e));
+            None
+        }
+    }
+}
+
+fn inspect_project(project: &Project, header: &Pubkey, key: Option<&Pubkey>, report: &mut Report) {
+    report.line(format!("Name: {}", project.name));
+    report.line(format!("Owner: {}", project.owner));
+    report.line(format!("Template storage: {}", project.template_storage));
+    report.line(format!("Next id: {}", project.uniq_id));
+    match key {
+        Some(key) if key != header => report.issue(format!(
+            "project in the header is {} instead of itself",
+            header
+        )),
+        _ => {}
+    }
+}
+
+fn inspect_template(template: &TemplateData, report: &mut Report) {
+    report.line(format!("Id: {}", template.id));
+    report.line(format!("Name: {}", template.name));
+    report.line(format!("Code: {}", template.code));
+    report.line(format!(
+        "Version: {}{}",
+        template.version,
+        if template.published { "" } else { " (draft)" }
+    ));
+    if let Some(parent) = template.parent {
+        report.line(format!("Parent: {}", parent));
+    }
+    report.line(format!("Max field index: {}", template.max_field_index));
+    for storage in &template.storages {
+        report.line(format!("Storage: {}", storage));
+    }
+    for index in &template.indexes {
+        report.line(format!("Index: {}", index));
+    }
+    for backref in &template.backrefs {
+        report.line(format!("Backref: {}", backref));
+    }
+    report.line("Fields:".to_string());
+    for field in &template.fields {
+        report.line(format!(
+            "  {:>5} {} ({}): {}",
+            field.id,
+            field.params.code,
+            field.params.name,
+            format_type(&field.params.field_type)
+        ));
+        if template.fields.iter().filter(|x| x.id == field.id).count() > 1 {
+            report.issue(format!("field id {} is used more than once", field.id));
+        }
+        if field.id > template.max_field_index {
+            report.issue(format!(
+                "field id {} is above the max field index {}",
+                field.id, template.max_field_index
+            ));
+        }
+    }
+    for rule in &template.links {
+        report.line(format!(
+            "Link rule: field {} {:?}",
+            rule.field_id, rule.on_delete
+        ));
+        match template.fields.iter().find(|x| x.id == rule.field_id) {
+            Some(field) if matches!(field.params.field_type, SolceryType::SLink { .. }) => {}
+            _ => report.issue(format!(
+                "link rule for field {} which is not an own link field",
+                rule.field_id
+            )),
+        }
+    }
+    for default in &template.defaults {
+        let field = template.fields.iter().find(|x| x.id == default.field_id);
+        report.line(format!(
+            "Default: field {} = {}",
+            default.field_id,
+            value::decode(field.map(|x| &x.params.field_type), &default.value)
+        ));
+        if field.is_none() && template.parent.is_none() {
+            report.issue(format!("default for unknown field {}", default.field_id));
+        }
+    }
+}
+
+fn inspect_storage(storage: &AccountStorage, report: &mut Report) {
+    report.line(format!("Template: {}", storage.template));
+    report.line(format!("Sorted: {}", storage.sorted));
+    report.line(format!("Accounts: {}", storage.accounts.len()));
+    for (index, account) in storage.accounts.iter().enumerate() {
+        report.line(format!("  {:>5} {}", index, account));
+        if storage.accounts[..index].contains(account) {
+            report.issue(format!("account {} is stored more than once", account));
+        }
+    }
+    if storage.sorted && storage.accounts.windows(2).any(|x| x[0] > x[1]) {
+        report.issue("storage is marked sorted but accounts are out of order".to_string());
+    }
+}
+
+fn inspect_paged_storage(storage: &PagedStorage, report: &mut Report) {
+    report.line(format!("Template: {}", storage.template));
+    report.line(format!("Page capacity: {}", storage.page_capacity));
+    report.line(format!("Accounts: {}", storage.len));
+    report.line(format!("Pages: {}", storage.pages));
+    if let Some(last_page) = storage.last_page {
+        report.line(format!("Last page: {}", last_page));
+    }
+    if storage.len as u64 > storage.pages as u64 * storage.page_capacity as u64 {
+        report.issue(format!(
+            "{} accounts don't fit {} pages of {}",
+            storage.len, storage.pages, storage.page_capacity
+        ));
+    }
+    match (storage.pages, storage.last_page) {
+        (0, Some(_)) => report.issue("storage has a last page but no pages".to_string()),
+        (pages, None) if pages > 0 => {
+            report.issue(format!("storage has {} pages but no last page", pages))
+        }
+        _ => {}
+    }
+}
+
+/// Pages are allocated for their capacity, so the account size tells how many accounts fit
+fn inspect_page(page: &StoragePage, size: usize, report: &mut Report) {
+    let capacity = size.saturating_sub(StoragePage::space(0)) / 32;
+    report.line(format!("Storage: {}", page.storage));
+    if let Some(prev) = page.prev {
+        report.line(format!("Previous page: {}", prev));
+    }
+    report.line(format!("Start: {}", page.start));
+    if let Some(end) = page.end {
+        report.line(format!("End: {}", end));
+    }
+    report.line(format!("Accounts: {} of {}", page.accounts.len(), capacity));
+    for (index, account) in page.accounts.iter().enumerate() {
+        report.line(format!("  {:>5} {}", index, account));
+        if !page.covers(account) {
+            report.issue(format!(
+                "account {} is outside the range of the page",
+                account
+            ));
+        }
+        if page.accounts[..index].contains(account) {
+            report.issue(format!("account {} is stored more than once", account));
+        }
+    }
+}
+
+fn inspect_object(object: &Object, template: Option<&TemplateInfo>, report: &mut Report) {
+    report.line(format!("Id: {}", object.id));
+    report.line(format!("Template: {}", object.template));
+    report.line(format!("Template version: {}", object.template_version));
+    if let Some(template) = template {
+        if template.key != object.template {
+            report.issue(format!(
+                "object belongs to template {}, not to the supplied {}",
+                object.template, template.key
+            ));
+        }
+        if object.template_version > template.data.version {
+            report.issue(format!(
+                "template version {} is above the template's version {}",
+                object.template_version, template.data.version
+            ));
+        }
+    }
+    report.line("Fields:".to_string());
+    let data = &object.data;
+    let mut used = vec![false; data.field_data.len()];
+    for (position, offsets) in data.field_offsets.iter().enumerate() {
+        let field = template.and_then(|x| x.fields.iter().find(|x| x.id == offsets.field_id));
+        let name = match (template, field) {
+            (_, Some(field)) => field.params.code.clone(),
+            (Some(_), None) => {
+                report.issue(format!("field {} is not in the template", offsets.field_id));
+                "<unknown>".to_string()
+            }
+            (None, None) => "?".to_string(),
+        };
+        if data.field_offsets[..position]
+            .iter()
+            .any(|x| x.field_id == offsets.field_id)
+        {
+            report.issue(format!(
+                "field {} is stored more than once",
+                offsets.field_id
+            ));
+        }
+        let bytes = match data
+            .field_data
+            .get(offsets.start_offset..offsets.end_offset)
+        {
+            Some(bytes) => bytes,
+            None => {
+                report.line(format!(
+                    "  {:>5} {}: <out of bounds>",
+                    offsets.field_id, name
+                ));
+                report.issue(format!(
+                    "field {} spans {}..{} out of {} bytes of field data",
+                    offsets.field_id,
+                    offsets.start_offset,
+                    offsets.end_offset,
+                    data.field_data.len()
+                ));
+                continue;
+            }
+        };
+        if used[offsets.start_offset..offsets.end_offset].contains(&true) {
+            report.issue(format!("field {} overlaps another field", offsets.field_id));
+        }
+        used[offsets.start_offset..offsets.end_offset].fill(true);
+        let decoded = value::decode(field.map(|x| &x.params.field_type), bytes);
+        if field.is_some() && decoded.get("raw").is_some() {
+            report.issue(format!(
+                "value of field {} doesn't match its type",
+                offsets.field_id
+            ));
+        }
+        report.line(format!("  {:>5} {}: {}", offsets.field_id, name, decoded));
+    }
+    let unused = used.iter().filter(|x| !**x).count();
+    if unused > 0 {
+        report.issue(format!("{} bytes of field data belong to no field", unused));
+    }
+}
+
+fn inspect_index(index: &FieldIndex, report: &mut Report) {
+    report.line(format!("Template: {}", index.template));
+    report.line(format!("Field: {}", index.field_id));
+    if let Some(cursor) = index.cursor {
+        report.line(format!("Built up to: {}", cursor));
+    }
+    report.line(format!("Entries: {}", index.entries.len()));
+    for entry in &index.entries {
+        report.line(format!(
+            "  {} {}",
+            base64::encode(&entry.value),
+            entry.object
+        ));
+    }
+    if index.entries.windows(2).any(|x| x[0] >= x[1]) {
+        report.issue("index entries are out of order or repeated".to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        borsh::BorshSerialize,
+        solcery_object::{index::IndexEntry, ObjectData, ObjectFieldData},
+        solcery_template::FieldParams,
+    };
+
+    const SPACE: usize = 256;
+
+    fn key(seed: u8) -> Pubkey {
+        Pubkey::new(&[seed; 32])
+    }
+
+    /// Account data as the program writes it: header, record and zeros up to `space`
+    fn record<T: BorshSerialize>(kind: RecordKind, record: &T, space: usize) -> Vec<u8> {
+        let mut data = vec![RecordData::CURRENT_VERSION];
+        data.extend_from_slice(key(1).as_ref());
+        data.push(kind as u8);
+        data.extend(record.try_to_vec().unwrap());
+        data.resize(space, 0);
+        data
+    }
+
+    /// Record with `byte` written over the last byte of the account
+    fn with_last(mut data: Vec<u8>, byte: u8) -> Vec<u8> {
+        *data.last_mut().unwrap() = byte;
+        data
+    }
+
+    fn project() -> Project {
+        Project {
+            uniq_id: 7,
+            name: "Cards".to_string(),
+            owner: key(2),
+            template_storage: key(3),
+        }
+    }
+
+    fn template(field_ids: &[u32], published: bool) -> TemplateData {
+        let fields = field_ids
+            .iter()
+            .map(|id| Field {
+                id: *id,
+                params: FieldParams {
+                    field_type: SolceryType::SInt,
+                    name: "Power".to_string(),
+                    code: "power".to_string(),
+                    construct_client: false,
+                    construct_server: false,
+                },
+            })
+            .collect();
+        TemplateData {
+            id: 3,
+            name: "Card".to_string(),
+            code: "card".to_string(),
+            storages: vec![key(4)],
+            max_field_index: 10,
+            fields,
+            custom_data: String::new(),
+            indexes: Vec::new(),
+            backrefs: Vec::new(),
+            links: Vec::new(),
+            parent: None,
+            defaults: Vec::new(),
+            version: 1,
+            published,
+            versions: Vec::new(),
+        }
+    }
+
+    fn storage(sorted: bool, accounts: &[u8]) -> AccountStorage {
+        AccountStorage {
+            template: key(5),
+            sorted,
+            accounts: accounts.iter().map(|x| key(*x)).collect(),
+        }
+    }
+
+    fn object(end_offset: usize) -> Object {
+        Object {
+            id: 12,
+            template: key(5),
+            template_version: 0,
+            data: ObjectData {
+                field_offsets: vec![ObjectFieldData {
+                    field_id: 4,
+                    start_offset: 0,
+                    end_offset,
+                }],
+                field_data: 3i32.to_le_bytes().to_vec(),
+            },
+        }
+    }
+
+    fn index(values: &[u8]) -> FieldIndex {
+        FieldIndex {
+            template: key(5),
+            field_id: 4,
+            entries: values
+                .iter()
+                .map(|x| IndexEntry {
+                    value: vec![*x],
+                    object: key(*x),
+                })
+                .collect(),
+            cursor: Some(key(9)),
+        }
+    }
+
+    fn paged(len: u32, pages: u32, last_page: Option<Pubkey>) -> PagedStorage {
+        PagedStorage {
+            template: key(5),
+            page_capacity: 2,
+            len,
+            pages,
+            last_page,
+        }
+    }
+
+    fn page(accounts: &[u8]) -> StoragePage {
+        StoragePage {
+            storage: key(6),
+            prev: Some(key(7)),
+            start: key(1),
+            end: Some(key(9)),
+            accounts: accounts.iter().map(|x| key(*x)).collect(),
+        }
+    }
+
+    /// Account data, account key, lines the report has and the issues it reports
+    type Case = (Vec<u8>, Option<Pubkey>, Vec<String>, Vec<String>);
+
+    #[test]
+    fn inspect_records() {
+        let mut legacy = record(RecordKind::Storage, &storage(false, &[]), SPACE);
+        legacy[0] = RecordData::LEGACY_VERSION;
+        let mut newer = record(RecordKind::Storage, &storage(false, &[]), SPACE);
+        newer[0] = RecordData::CURRENT_VERSION + 1;
+        let mut unknown = record(RecordKind::Storage, &storage(false, &[]), SPACE);
+        unknown[RecordData::KIND_INDEX] = 200;
+        let mut truncated = record(RecordKind::Template, &template(&[1], true), SPACE);
+        truncated.truncate(RecordData::WRITABLE_START_INDEX + 8);
+        let page_space = StoragePage::space(3);
+
+        let cases: Vec<Case> = vec![
+            (
+                Vec::new(),
+                None,
+                vec![],
+                vec!["account is empty".to_string()],
+            ),
+            (
+                vec![0; 8],
+                None,
+                vec!["Version: uninitialized".to_string()],
+                vec![],
+            ),
+            (
+                with_last(vec![0; 8], 1),
+                None,
+                vec!["Version: uninitialized".to_string()],
+                vec!["uninitialized account has data at offset 7".to_string()],
+            ),
+            (
+                vec![RecordData::CURRENT_VERSION; 10],
+                None,
+                vec!["Version: 2".to_string()],
+                vec!["account is shorter than the 34 byte header".to_string()],
+            ),
+            (
+                legacy,
+                None,
+                vec![format!("Project: {}", key(1))],
+                vec!["version 1 predates record kinds, the record has to be migrated".to_string()],
+            ),
+            (
+                newer,
+                None,
+                vec!["Kind: Storage".to_string()],
+                vec!["version 3 is newer than supported version 2".to_string()],
+            ),
+            (
+                unknown,
+                None,
+                vec![],
+                vec!["unknown record kind 200".to_string()],
+            ),
+            (
+                record(RecordKind::Raw, &0u32, SPACE),
+                None,
+                vec!["Raw records are not decoded".to_string()],
+                vec![],
+            ),
+            (
+                record(RecordKind::Project, &project(), SPACE),
+                Some(key(1)),
+                vec!["Name: Cards".to_string(), "Next id: 7".to_string()],
+                vec![],
+            ),
+            (
+                with_last(record(RecordKind::Project, &project(), SPACE), 9),
+                Some(key(8)),
+                vec![format!("Owner: {}", key(2))],
+                vec![
+                    format!("data after the record at offset {}", SPACE - 1),
+                    format!("project in the header is {} instead of itself", key(1)),
+                ],
+            ),
+            (
+                truncated,
+                None,
+                vec!["Kind: Template".to_string()],
+                vec!["record doesn't decode: Unexpected length of input".to_string()],
+            ),
+            (
+                record(RecordKind::Template, &template(&[1, 2], true), SPACE),
+                None,
+                vec!["Code: card".to_string(), "Version: 1".to_string()],
+                vec![],
+            ),
+            (
+                record(RecordKind::Template, &template(&[1, 11, 1], false), SPACE),
+                None,
+                vec!["Version: 1 (draft)".to_string()],
+                vec![
+                    "field id 1 is used more than once".to_string(),
+                    "field id 11 is above the max field index 10".to_string(),
+                    "field id 1 is used more than once".to_string(),
+                ],
+            ),
+            (
+                record(RecordKind::TemplateVersion, &template(&[1], true), SPACE),
+                None,
+                vec![
+                    "Kind: TemplateVersion".to_string(),
+                    "Name: Card".to_string(),
+                ],
+                vec![],
+            ),
+            (
+                record(RecordKind::TemplateVersion, &template(&[1], false), SPACE),
+                None,
+                vec!["Version: 1 (draft)".to_string()],
+                vec!["template version is not published".to_string()],
+            ),
+            (
+                record(RecordKind::Storage, &storage(true, &[2, 3]), SPACE),
+                None,
+                vec!["Sorted: true".to_string(), "Accounts: 2".to_string()],
+                vec![],
+            ),
+            (
+                record(RecordKind::Storage, &storage(true, &[3, 2, 3]), SPACE),
+                None,
+                vec![format!("      2 {}", key(3))],
+                vec![
+                    format!("account {} is stored more than once", key(3)),
+                    "storage is marked sorted but accounts are out of order".to_string(),
+                ],
+            ),
+            (
+                record(RecordKind::Object, &object(4), SPACE),
+                None,
+                vec!["Id: 12".to_string(), "Template version: 0".to_string()],
+                vec![],
+            ),
+            (
+                record(RecordKind::Object, &object(6), SPACE),
+                None,
+                vec!["      4 ?: <out of bounds>".to_string()],
+                vec![
+                    "field 4 spans 0..6 out of 4 bytes of field data".to_string(),
+                    "4 bytes of field data belong to no field".to_string(),
+                ],
+            ),
+            (
+                record(RecordKind::Index, &index(&[1, 2]), SPACE),
+                None,
+                vec![
+                    "Field: 4".to_string(),
+                    format!("Built up to: {}", key(9)),
+                    "Entries: 2".to_string(),
+                ],
+                vec![],
+            ),
+            (
+                record(RecordKind::Index, &index(&[2, 1]), SPACE),
+                None,
+                vec![format!("  AQ== {}", key(1))],
+                vec!["index entries are out of order or repeated".to_string()],
+            ),
+            (
+                record(RecordKind::Game, &0u32, SPACE),
+                None,
+                vec!["Game records are not decoded".to_string()],
+                vec![],
+            ),
+            (
+                record(RecordKind::Log, &0u32, SPACE),
+                None,
+                vec!["Log records are not decoded".to_string()],
+                vec![],
+            ),
+            (
+                record(RecordKind::PagedStorage, &paged(3, 2, Some(key(7))), SPACE),
+                None,
+                vec![
+                    "Page capacity: 2".to_string(),
+                    "Accounts: 3".to_string(),
+                    "Pages: 2".to_string(),
+                    format!("Last page: {}", key(7)),
+                ],
+                vec![],
+            ),
+            (
+                record(RecordKind::PagedStorage, &paged(5, 2, None), SPACE),
+                None,
+                vec!["Pages: 2".to_string()],
+                vec![
+                    "5 accounts don't fit 2 pages of 2".to_string(),
+                    "storage has 2 pages but no last page".to_string(),
+                ],
+            ),
+            (
+                record(RecordKind::PagedStorage, &paged(0, 0, Some(key(7))), SPACE),
+                None,
+                vec!["Accounts: 0".to_string()],
+                vec!["storage has a last page but no pages".to_string()],
+            ),
+            (
+                record(RecordKind::StoragePage, &page(&[2, 3]), page_space),
+                None,
+                vec![
+                    format!("Storage: {}", key(6)),
+                    format!("Previous page: {}", key(7)),
+                    format!("Start: {}", key(1)),
+                    format!("End: {}", key(9)),
+                    "Accounts: 2 of 3".to_string(),
+                    "Record: 198 bytes, 32 bytes free".to_string(),
+                ],
+                vec![],
+            ),
+            (
+                record(RecordKind::StoragePage, &page(&[2, 2]), page_space),
+                None,
+                vec![format!("      1 {}", key(2))],
+                vec![format!("account {} is stored more than once", key(2))],
+            ),
+            (
+                record(RecordKind::StoragePage, &page(&[2, 9]), page_space),
+                None,
+                vec![format!("      1 {}", key(9))],
+                vec![format!(
+                    "account {} is outside the range of the page",
+                    key(9)
+                )],
+            ),
+        ];
+        for (data, account, lines, issues) in cases {
+            let report = inspect(&data, account.as_ref(), None);
+            for line in &lines {
+                assert!(
+                    report.lines.contains(line),
+                    "{:?} not in {:?}",
+                    line,
+                    report.lines
+                );
+            }
+            assert_eq!(report.issues, issues, "{:?}", report.lines);
+        }
+    }
+
+    #[test]
+    fn inspect_object_fields() {
+        let card = TemplateInfo {
+            key: key(5),
+            data: template(&[4], true),
+            fields: template(&[4], true).fields,
+        };
+        let data = record(RecordKind::Object, &object(4), SPACE);
+        assert_eq!(object_template(&data), Some(key(5)));
+        let report = inspect(&data, None, Some(&card));
+        assert!(report.lines.contains(&"      4 power: 3".to_string()));
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+        let other = TemplateInfo {
+            key: key(6),
+            data: template(&[1], true),
+            fields: template(&[1], true).fields,
+        };
+        let report = inspect(&data, None, Some(&other));
+        assert_eq!(
+            report.issues,
+            vec![
+                format!(
+                    "object belongs to template {}, not to the supplied {}",
+                    key(5),
+                    key(6)
+                ),
+                "field 4 is not in the template".to_string(),
+            ]
+        );
+        let storage = record(RecordKind::Storage, &storage(false, &[]), SPACE);
+        assert_eq!(object_template(&storage), None);
+    }
+}
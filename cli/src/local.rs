@@ -0,0 +1,199 @@
+//! In-process bank: the engine runs natively against accounts kept in a state file.
+//!
+//! The state file is an account dump as read by `solcery_bundle::export::parse_dump`, so the
+//! state can be exported right away. Instructions run through `solcery_bank::Bank`, so they
+//! either apply all their changes or none of them.
+
+use {
+    crate::{
+        client::{Client, NewAccount},
+        Error,
+    },
+    solana_program::{instruction::Instruction, pubkey::Pubkey},
+    solana_sdk::signature::Signer,
+    solcery_bank::Bank,
+    solcery_bundle::{
+        export::{format_dump, parse_dump},
+        AccountDump,
+    },
+    std::{fs, path::PathBuf},
+};
+
+pub struct LocalClient {
+    path: PathBuf,
+    signer: Pubkey,
+    bank: Bank,
+}
+
+impl LocalClient {
+    /// Opens the state file, a missing file is an empty bank
+    pub fn open(path: PathBuf, program_id: Pubkey, signer: Pubkey) -> Result<Self, Error> {
+        let accounts = match fs::read_to_string(&path) {
+            Ok(json) => parse_dump(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AccountDump::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut bank = Bank::new(program_id, solcery_engine::entrypoint::process_instruction);
+        for (key, data) in accounts {
+            bank.insert(key, data);
+        }
+        Ok(LocalClient { path, signer, bank })
+    }
+
+    fn execute(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let result = self.bank.process(instruction);
+        // Program logs go to stderr to keep command output apart
+        for message in &self.bank.logs {
+            eprintln!("Program log: {}", message);
+        }
+        Ok(result?)
+    }
+}
+
+impl Client for LocalClient {
+    fn program_id(&self) -> Pubkey {
+        self.bank.program_id
+    }
+
+    fn signer(&self) -> Pubkey {
+        self.signer
+    }
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.bank.accounts.get(key).map(|x| x.data.clone()))
+    }
+
+    fn send(
+        &mut self,
+        accounts: Vec<NewAccount>,
+        instructions: Vec<Instruction>,
+    ) -> Result<(), Error> {
+        for account in accounts {
+            self.bank
+                .insert(account.keypair.pubkey(), vec![0; account.space]);
+        }
+        for instruction in &instructions {
+            self.execute(instruction)?;
+        }
+        fs::write(&self.path, format_dump(&self.bank.dump()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::commands::*,
+        solcery_crud::{RecordData, RecordKind},
+        solcery_object::{index, Object},
+        solcery_project::Project,
+        solcery_storage::AccountStorage,
+        solcery_template::{FieldParams, TemplateData},
+    };
+
+    /// Client over a fresh state file in the temp dir, signed by the first engine admin
+    fn client(name: &str) -> LocalClient {
+        let file = format!("solcery-{}-{}.json", name, std::process::id());
+        let path = std::env::temp_dir().join(file);
+        let _ = fs::remove_file(&path);
+        let signer = solcery_engine::entrypoint::super_admins()[0];
+        LocalClient::open(path, Pubkey::new_unique(), signer).unwrap()
+    }
+
+    /// Keys of the records of the kind in the bank, as new keys are only printed
+    fn records(client: &LocalClient, kind: RecordKind) -> Vec<Pubkey> {
+        client
+            .bank
+            .accounts
+            .iter()
+            .filter(|(_, account)| {
+                account.data.first() == Some(&RecordData::CURRENT_VERSION)
+                    && account.data.get(RecordData::KIND_INDEX) == Some(&(kind as u8))
+            })
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    fn params(code: &str, field_type: &str) -> FieldParams {
+        FieldParams {
+            field_type: parse_type(field_type).unwrap(),
+            name: code.to_string(),
+            code: code.to_string(),
+            construct_client: false,
+            construct_server: false,
+        }
+    }
+
+    /// Project with one template, returned with the template
+    fn setup(client: &mut LocalClient) -> (Pubkey, Pubkey) {
+        project_create(client, None).unwrap();
+        let project = records(client, RecordKind::Project)[0];
+        template_create(client, &project, 1024).unwrap();
+        let project_data: Project = client.record(&project, RecordKind::Project).unwrap();
+        let templates: AccountStorage = client
+            .record(&project_data.template_storage, RecordKind::Storage)
+            .unwrap();
+        (project, templates.accounts[0])
+    }
+
+    #[test]
+    fn template_commands() {
+        let mut client = client("templates");
+        let (project, template) = setup(&mut client);
+        let next_id = client
+            .record::<Project>(&project, RecordKind::Project)
+            .unwrap()
+            .uniq_id;
+
+        template_add_field(&mut client, &template, params("power", "int")).unwrap();
+        template_add_field(&mut client, &template, params("title", "string")).unwrap();
+        let data: TemplateData = client.record(&template, RecordKind::Template).unwrap();
+        // New templates start with the legacy name and enabled fields
+        let fields: Vec<_> = data
+            .fields
+            .iter()
+            .skip(2)
+            .map(|x| (x.id, x.params.code.as_str()))
+            .collect();
+        assert_eq!(fields, vec![(next_id, "power"), (next_id + 1, "title")]);
+        assert_eq!(data.max_field_index, next_id + 1);
+
+        template_list(&client, &project).unwrap();
+        template_show(&client, &template).unwrap();
+        assert!(template_show(&client, &project).is_err());
+    }
+
+    #[test]
+    fn object_and_storage_commands() {
+        let mut client = client("objects");
+        let (_, template) = setup(&mut client);
+        template_add_field(&mut client, &template, params("power", "int")).unwrap();
+        let data: TemplateData = client.record(&template, RecordKind::Template).unwrap();
+        let (storage, power) = (data.storages[0], data.fields.last().unwrap().id);
+
+        object_create(&mut client, &template, None, 256).unwrap();
+        let objects = records(&client, RecordKind::Object);
+        assert_eq!(objects.len(), 1);
+        let object = objects[0];
+        let accounts = client
+            .record::<AccountStorage>(&storage, RecordKind::Storage)
+            .unwrap()
+            .accounts;
+        assert_eq!(accounts, vec![object]);
+
+        object_set_field(&mut client, &object, "power", "42").unwrap();
+        object_set_field(&mut client, &object, &power.to_string(), "7").unwrap();
+        assert!(object_set_field(&mut client, &object, "speed", "1").is_err());
+        object_get(&client, &object).unwrap();
+        storage_list(&client, &storage).unwrap();
+
+        // Changes are kept in the state file
+        let (path, program_id) = (client.path.clone(), client.program_id());
+        let reopened = LocalClient::open(path, program_id, client.signer).unwrap();
+        let data: Object = reopened.record(&object, RecordKind::Object).unwrap();
+        let value = 7i32.to_le_bytes().to_vec();
+        assert_eq!(index::field_value(&data, power), Some(value));
+        fs::remove_file(&client.path).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+//! Command line client of the Solcery engine.
+//!
+//! Commands run against a cluster reached through JSON RPC, e.g. a local test validator with
+//! the engine deployed, or against an in-process bank kept in a state file with `--local`.
+
+mod client;
+mod commands;
+mod inspect;
+mod local;
+mod rpc;
+
+use {
+    crate::{client::Client, commands::*, local::LocalClient, rpc::RpcClient},
+    clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand},
+    solana_program::pubkey::Pubkey,
+    solana_sdk::signature::read_keypair_file,
+    solcery_template::FieldParams,
+    std::{process, str::FromStr},
+};
+
+pub type Error = Box<dyn std::error::Error>;
+
+fn pubkey_arg(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .value_name("PUBKEY")
+        .takes_value(true)
+        .required(true)
+        .help(help)
+}
+
+fn space_arg(default: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("space")
+        .long("space")
+        .value_name("BYTES")
+        .takes_value(true)
+        .default_value(default)
+        .help("Size of the new account")
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("solcery")
+        .version(crate_version!())
+        .about("Manages projects, templates and objects of the Solcery engine")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .global(true)
+                .value_name("URL")
+                .takes_value(true)
+                .default_value("http://127.0.0.1:8899")
+                .help("JSON RPC endpoint of the cluster"),
+        )
+        .arg(
+            Arg::with_name("keypair")
+                .long("keypair")
+                .global(true)
+                .value_name("PATH")
+                .takes_value(true)
+                .help("Payer and signer [default: ~/.config/solana/id.json]"),
+        )
+        .arg(
+            Arg::with_name("program")
+                .long("program")
+                .global(true)
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .help("Engine program id, required unless running locally"),
+        )
+        .arg(
+            Arg::with_name("local")
+                .long("local")
+                .global(true)
+                .value_name("STATE")
+                .takes_value(true)
+                .help("Runs the engine in process with accounts kept in the state file"),
+        )
+        .arg(
+            Arg::with_name("signer")
+                .long("signer")
+                .global(true)
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .help("Signer of local instructions [default: first engine admin]"),
+        )
+        .subcommand(
+            SubCommand::with_name("project")
+                .about("Projects")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Creates a project")
+                        .arg(
+                            Arg::with_name("owner")
+                                .long("owner")
+                                .value_name("PUBKEY")
+                                .takes_value(true)
+                                .help("Owner of the project [default: signer]"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows a project")
+                        .arg(pubkey_arg("project", "Project")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("template")
+                .about("Templates")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists templates of a project")
+                        .arg(pubkey_arg("project", "Project")),
+                )
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Creates a template with a storage")
+                        .arg(pubkey_arg("project", "Project"))
+                        .arg(space_arg("4096")),
+                )
+                .subcommand(
+                    SubCommand::with_name("add-field")
+                        .about("Adds a field to a template draft")
+                        .arg(pubkey_arg("template", "Template"))
+                        .arg(
+                            Arg::with_name("code")
+                                .value_name("CODE")
+                                .required(true)
+                                .help("Field code"),
+                        )
+                        .arg(
+                            Arg::with_name("type")
+                                .value_name("TYPE")
+                                .required(true)
+                                .help(
+                                    "bool, int, string, url, link:<template> or \
+                                     enum:<value>,<value>...",
+                                ),
+                        )
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .help("Field name [default: code]"),
+                        )
+                        .arg(
+                            Arg::with_name("client")
+                                .long("client")
+                                .help("Built on clients"),
+                        )
+                        .arg(
+                            Arg::with_name("server")
+                                .long("server")
+                                .help("Built on server"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows a template with inherited fields")
+                        .arg(pubkey_arg("template", "Template")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("object")
+                .about("Objects")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Creates an object with default values")
+                        .arg(pubkey_arg("template", "Template"))
+                        .arg(
+                            Arg::with_name("storage")
+                                .long("storage")
+                                .value_name("PUBKEY")
+                                .takes_value(true)
+                                .help("Storage of the template [default: first one]"),
+                        )
+                        .arg(space_arg("1024")),
+                )
+                .subcommand(
+                    SubCommand::with_name("get")
+                        .about("Shows object fields")
+                        .arg(pubkey_arg("object", "Object")),
+                )
+                .subcommand(
+                    SubCommand::with_name("set-field")
+                        .about("Sets an object field")
+                        .arg(pubkey_arg("object", "Object"))
+                        .arg(
+                            Arg::with_name("field")
+                                .value_name("FIELD")
+                                .required(true)
+                                .help("Field code or id"),
+                        )
+                        .arg(
+                            Arg::with_name("value")
+                                .value_name("VALUE")
+                                .required(true)
+                                .help("JSON value, anything else is taken as a string"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Decodes a record account and reports layout issues")
+                .arg(
+                    Arg::with_name("account")
+                        .value_name("PUBKEY")
+                        .required_unless("file")
+                        .help("Account to fetch"),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .long("file")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .conflicts_with("account")
+                        .help("Raw account data to decode instead"),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .help("Template to name object fields after [default: the object's]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("storage")
+                .about("Storages")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists accounts of a storage")
+                        .arg(pubkey_arg("storage", "Storage")),
+                ),
+        )
+}
+
+fn pubkey(matches: &ArgMatches, name: &str) -> Result<Pubkey, Error> {
+    let value = matches
+        .value_of(name)
+        .ok_or_else(|| format!("{} is missing", name))?;
+    Pubkey::from_str(value).map_err(|_| format!("Invalid {} {}", name, value).into())
+}
+
+fn optional_pubkey(matches: &ArgMatches, name: &str) -> Result<Option<Pubkey>, Error> {
+    match matches.value_of(name) {
+        Some(_) => pubkey(matches, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn space(matches: &ArgMatches) -> Result<usize, Error> {
+    Ok(matches.value_of("space").unwrap().parse()?)
+}
+
+fn run_command<C: Client>(client: &mut C, matches: &ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        ("project", Some(matches)) => match matches.subcommand() {
+            ("create", Some(matches)) => project_create(client, optional_pubkey(matches, "owner")?),
+            ("show", Some(matches)) => project_show(client, &pubkey(matches, "project")?),
+            _ => unreachable!(),
+        },
+        ("template", Some(matches)) => match matches.subcommand() {
+            ("list", Some(matches)) => template_list(client, &pubkey(matches, "project")?),
+            ("create", Some(matches)) => {
+                template_create(client, &pubkey(matches, "project")?, space(matches)?)
+            }
+            ("add-field", Some(matches)) => {
+                let code = matches.value_of("code").unwrap();
+                let params = FieldParams {
+                    field_type: parse_type(matches.value_of("type").unwrap())?,
+                    name: matches.value_of("name").unwrap_or(code).to_string(),
+                    code: code.to_string(),
+                    construct_client: matches.is_present("client"),
+                    construct_server: matches.is_present("server"),
+                };
+                template_add_field(client, &pubkey(matches, "template")?, params)
+            }
+            ("show", Some(matches)) => template_show(client, &pubkey(matches, "template")?),
+            _ => unreachable!(),
+        },
+        ("object", Some(matches)) => match matches.subcommand() {
+            ("create", Some(matches)) => object_create(
+                client,
+                &pubkey(matches, "template")?,
+                optional_pubkey(matches, "storage")?,
+                space(matches)?,
+            ),
+            ("get", Some(matches)) => object_get(client, &pubkey(matches, "object")?),
+            ("set-field", Some(matches)) => object_set_field(
+                client,
+                &pubkey(matches, "object")?,
+                matches.value_of("field").unwrap(),
+                matches.value_of("value").unwrap(),
+            ),
+            _ => unreachable!(),
+        },
+        ("inspect", Some(matches)) => inspect_account(
+            Some(&*client),
+            optional_pubkey(matches, "account")?,
+            matches.value_of("file"),
+            optional_pubkey(matches, "template")?,
+        ),
+        ("storage", Some(matches)) => match matches.subcommand() {
+            ("list", Some(matches)) => storage_list(client, &pubkey(matches, "storage")?),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Global arguments end up in the matches of the innermost subcommand
+fn innermost<'a>(matches: &'a ArgMatches<'a>) -> &'a ArgMatches<'a> {
+    match matches.subcommand() {
+        (_, Some(matches)) => innermost(matches),
+        _ => matches,
+    }
+}
+
+fn run(app_matches: &ArgMatches) -> Result<(), Error> {
+    let matches = innermost(app_matches);
+    if app_matches.subcommand_name() == Some("inspect")
+        && matches.is_present("file")
+        && !matches.is_present("template")
+    {
+        // Raw data is decoded without a cluster
+        return inspect_account(None::<&LocalClient>, None, matches.value_of("file"), None);
+    }
+    match matches.value_of("local") {
+        Some(state) => {
+            let program_id = optional_pubkey(matches, "program")?.unwrap_or_default();
+            let signer = match optional_pubkey(matches, "signer")? {
+                Some(signer) => signer,
+                None => solcery_engine::entrypoint::super_admins()[0],
+            };
+            let mut client = LocalClient::open(state.into(), program_id, signer)?;
+            run_command(&mut client, app_matches)
+        }
+        None => {
+            let program_id = optional_pubkey(matches, "program")?
+                .ok_or("--program is required unless running locally")?;
+            let keypair_path = match matches.value_of("keypair") {
+                Some(path) => path.to_string(),
+                None => format!("{}/.config/solana/id.json", std::env::var("HOME")?),
+            };
+            let payer = read_keypair_file(&keypair_path)
+                .map_err(|e| format!("Can't read keypair {}: {}", keypair_path, e))?;
+            let url = matches.value_of("url").unwrap().to_string();
+            let mut client = RpcClient::new(url, program_id, payer);
+            run_command(&mut client, app_matches)
+        }
+    }
+}
+
+fn main() {
+    let matches = app().get_matches();
+    if let Err(e) = run(&matches) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
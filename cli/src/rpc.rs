@@ -0,0 +1,130 @@
+//! Client of a cluster reached through JSON RPC, e.g. a local test validator
+
+use {
+    crate::{
+        client::{Client, NewAccount},
+        Error,
+    },
+    serde_json::{json, Value},
+    solana_program::{hash::Hash, instruction::Instruction, pubkey::Pubkey, system_instruction},
+    solana_sdk::{
+        signature::{Keypair, Signature, Signer},
+        transaction::Transaction,
+    },
+    std::{str::FromStr, thread, time::Duration},
+};
+
+/// Status polls before giving up on a transaction
+const CONFIRMATION_POLLS: usize = 60;
+
+pub struct RpcClient {
+    url: String,
+    program_id: Pubkey,
+    payer: Keypair, // Pays for new accounts and signs engine instructions
+}
+
+impl RpcClient {
+    pub fn new(url: String, program_id: Pubkey, payer: Keypair) -> Self {
+        RpcClient {
+            url,
+            program_id,
+            payer,
+        }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let mut response: Value = ureq::post(&self.url).send_json(request)?.into_json()?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("{} failed: {}", method, error).into());
+        }
+        Ok(response["result"].take())
+    }
+
+    fn recent_blockhash(&self) -> Result<Hash, Error> {
+        let result = self.call("getRecentBlockhash", json!([]))?;
+        let blockhash = result["value"]["blockhash"]
+            .as_str()
+            .ok_or("Invalid getRecentBlockhash response")?;
+        Ok(Hash::from_str(blockhash)?)
+    }
+
+    fn rent_exempt_balance(&self, space: usize) -> Result<u64, Error> {
+        self.call("getMinimumBalanceForRentExemption", json!([space]))?
+            .as_u64()
+            .ok_or_else(|| "Invalid getMinimumBalanceForRentExemption response".into())
+    }
+
+    fn send_transaction(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<Signature, Error> {
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &signers.to_vec(),
+            self.recent_blockhash()?,
+        );
+        let encoded = base64::encode(bincode::serialize(&transaction)?);
+        self.call(
+            "sendTransaction",
+            json!([encoded, { "encoding": "base64" }]),
+        )?;
+        let signature = transaction.signatures[0];
+        for _ in 0..CONFIRMATION_POLLS {
+            let result = self.call("getSignatureStatuses", json!([[signature.to_string()]]))?;
+            let status = &result["value"][0];
+            if !status["err"].is_null() {
+                return Err(format!("Transaction {} failed: {}", signature, status["err"]).into());
+            }
+            match status["confirmationStatus"].as_str() {
+                Some("confirmed") | Some("finalized") => return Ok(signature),
+                _ => thread::sleep(Duration::from_millis(500)),
+            }
+        }
+        Err(format!("Transaction {} is not confirmed", signature).into())
+    }
+}
+
+impl Client for RpcClient {
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn signer(&self) -> Pubkey {
+        self.payer.pubkey()
+    }
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>, Error> {
+        let result = self.call(
+            "getAccountInfo",
+            json!([key.to_string(), { "encoding": "base64" }]),
+        )?;
+        match result["value"]["data"][0].as_str() {
+            Some(data) => Ok(Some(base64::decode(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn send(
+        &mut self,
+        accounts: Vec<NewAccount>,
+        instructions: Vec<Instruction>,
+    ) -> Result<(), Error> {
+        for account in &accounts {
+            let create = system_instruction::create_account(
+                &self.payer.pubkey(),
+                &account.keypair.pubkey(),
+                self.rent_exempt_balance(account.space)?,
+                account.space as u64,
+                &self.program_id,
+            );
+            self.send_transaction(&[create], &[&self.payer, &account.keypair])?;
+        }
+        for instruction in instructions {
+            self.send_transaction(&[instruction], &[&self.payer])?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use {
+    solana_program::{decode_error::DecodeError, program_error::ProgramError},
+    thiserror::Error,
+};
+
+/// Errors that may be returned by record operations, codes 0x300..0x3ff
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum CrudError {
+    /// Record has to be migrated to the current version first
+    #[error("Record has to be migrated to the current version first")]
+    OutdatedVersion = 0x300,
+
+    /// Record version is newer than the program supports
+    #[error("Record version is newer than the program supports")]
+    UnsupportedVersion,
+
+    /// No migration is registered for the record kind and version
+    #[error("No migration is registered for the record kind and version")]
+    UnknownMigration,
+
+    /// Record is of another kind
+    #[error("Record is of another kind")]
+    InvalidKind,
+}
+
+impl From<CrudError> for ProgramError {
+    fn from(e: CrudError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for CrudError {
+    fn type_of() -> &'static str {
+        "Crud Error"
+    }
+}
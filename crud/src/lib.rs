@@ -1,3 +1,6 @@
+pub mod error;
+
+use crate::error::CrudError;
 use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
@@ -8,7 +11,8 @@ use solana_program::{
     pubkey::Pubkey,
 };
 
-/// Struct wrapping data and providing metadata
+/// Header of every record account. The record itself is borsh serialized right after
+/// the header at `RecordData::WRITABLE_START_INDEX`, see `read_record` and `write_record`
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
 pub struct RecordData {
     /// Struct version, allows for upgrades to the program
@@ -17,60 +21,308 @@ pub struct RecordData {
     /// Project which owns the account
     pub project: Pubkey,
 
-    /// The data contained by the account, could be anything serializable
-    pub data: Data,
+    /// Kind of the record, checked when the record is loaded
+    pub kind: RecordKind,
+}
+
+/// Kind of record an account holds
+#[derive(Clone, Copy, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+pub enum RecordKind {
+    Raw,
+    Project,
+    Template,
+    Storage,
+    Object,
+    Index,
+    Game,
+    Log,
+    PagedStorage,
+    StoragePage,
+    TemplateVersion,
+}
+
+#[derive(Clone, Copy, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+pub enum Change {
+    Create,
+    Update,
+    Delete,
 }
 
-/// Struct just for data
-#[derive(Clone, Debug, Default, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
-pub struct Data {
-    /// The data contained by the account, could be anything or serializable
-    pub bytes: Vec<u8>,
+/// Event logged on every record mutation, so indexers can follow changes without polling
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+pub struct RecordEvent {
+    pub kind: RecordKind,
+    pub key: Pubkey,
+    pub project: Pubkey,
+    pub change: Change,
 }
 
+/// Events are logged as `Program log: Solcery/Event <base64 of borsh RecordEvent>`
+pub const EVENT_LOG_PREFIX: &str = "Solcery/Event ";
+
 pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
     let (tag, data) = instruction_data.split_first().unwrap();
     let accounts_iter = &mut accounts.iter();
-    let signer_info = next_account_info(accounts_iter)?;
-    match (tag) {
+    let _signer_info = next_account_info(accounts_iter)?;
+    match tag {
         0 => {
             let account_info = next_account_info(accounts_iter)?;
             let buf = &mut &data[..];
             let offset = u64::deserialize(buf)?;
-            write_raw(account_info, offset, buf.to_vec())
+            write_raw(account_info, offset, buf.to_vec())?;
+            emit(header_kind(account_info), account_info, Change::Update);
+            Ok(())
         }
         _ => Err(ProgramError::InvalidAccountData),
     }
 }
 
 impl RecordData {
+    /// Version byte of accounts that were never initialized
+    pub const UNINITIALIZED: u8 = 0;
+
     /// Version to fill in on new created accounts
-    pub const CURRENT_VERSION: u8 = 1;
+    pub const CURRENT_VERSION: u8 = 2;
+
+    /// Version of the original layout: no kind in the header and the records of the time
+    pub const LEGACY_VERSION: u8 = 1;
 
-    /// Start of writable account data, after version and authority
-    pub const WRITABLE_START_INDEX: usize = 33;
+    /// Position of the record kind, after version and project
+    pub const KIND_INDEX: usize = 33;
+
+    /// Start of writable account data, after version, project and kind
+    pub const WRITABLE_START_INDEX: usize = 34;
+
+    /// Start of writable account data in `LEGACY_VERSION` records
+    pub const LEGACY_WRITABLE_START_INDEX: usize = 33;
 }
 
 impl IsInitialized for RecordData {
     /// Is initialized
     fn is_initialized(&self) -> bool {
-        self.version == Self::CURRENT_VERSION
+        self.version != Self::UNINITIALIZED
     }
 }
 
-pub fn initialize(project_info: &AccountInfo, account_info: &AccountInfo) -> ProgramResult {
-    let mut account_data = RecordData::deserialize(&mut &account_info.data.borrow()[..])?;
-    if account_data.is_initialized() {
+pub fn initialize(
+    project_info: &AccountInfo,
+    account_info: &AccountInfo,
+    kind: RecordKind,
+) -> ProgramResult {
+    if account_info.data_len() < RecordData::WRITABLE_START_INDEX {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    if is_initialized(account_info) {
         return Err(ProgramError::AccountAlreadyInitialized);
     }
-    account_data.version = RecordData::CURRENT_VERSION;
-    account_data.project = *project_info.key;
-    account_data
-        .serialize(&mut &mut account_info.data.borrow_mut()[..])
+    let header = RecordData {
+        version: RecordData::CURRENT_VERSION,
+        project: *project_info.key,
+        kind,
+    };
+    header
+        .serialize(&mut &mut account_info.data.borrow_mut()[..RecordData::WRITABLE_START_INDEX])
         .map_err(|e| e.into())
 }
 
-pub fn write_raw(account_info: &AccountInfo, offset: u64, mut data: Vec<u8>) -> ProgramResult {
+/// Loads the record checking its version and kind
+pub fn read_record<T: BorshDeserialize>(
+    account_info: &AccountInfo,
+    kind: RecordKind,
+) -> Result<T, ProgramError> {
+    decode_record(&account_info.data.borrow(), kind)
+}
+
+/// Decodes the record from raw account data with the same checks as `read_record`,
+/// for off-chain tools working with account dumps
+pub fn decode_record<T: BorshDeserialize>(
+    data: &[u8],
+    kind: RecordKind,
+) -> Result<T, ProgramError> {
+    check_header(data, kind)?;
+    T::deserialize(&mut &data[RecordData::WRITABLE_START_INDEX..]).map_err(|e| e.into())
+}
+
+/// Saves the record over the previous one, the rest of the account is zeroed
+pub fn write_record<T: BorshSerialize>(
+    account_info: &AccountInfo,
+    kind: RecordKind,
+    record: &T,
+) -> ProgramResult {
+    check_kind(account_info, kind)?;
+    write(account_info, 0, record.try_to_vec()?)
+}
+
+/// Checks the header version without parsing the rest of the account,
+/// records of any version are initialized
+pub fn is_initialized(account_info: &AccountInfo) -> bool {
+    !matches!(
+        account_info.data.borrow().first(),
+        None | Some(&RecordData::UNINITIALIZED)
+    )
+}
+
+pub fn get_version(account_info: &AccountInfo) -> Result<u8, ProgramError> {
+    match account_info.data.borrow().first() {
+        None | Some(&RecordData::UNINITIALIZED) => Err(ProgramError::UninitializedAccount),
+        Some(version) => Ok(*version),
+    }
+}
+
+/// Records of older versions have to be migrated before they can be used
+pub fn check_version(account_info: &AccountInfo) -> ProgramResult {
+    let version = get_version(account_info)?;
+    if version < RecordData::CURRENT_VERSION {
+        return Err(CrudError::OutdatedVersion.into());
+    }
+    if version > RecordData::CURRENT_VERSION {
+        return Err(CrudError::UnsupportedVersion.into());
+    }
+    Ok(())
+}
+
+pub fn get_kind(account_info: &AccountInfo) -> Result<RecordKind, ProgramError> {
+    let account_data = account_info.data.borrow();
+    let kind_data = account_data
+        .get(RecordData::KIND_INDEX..RecordData::WRITABLE_START_INDEX)
+        .ok_or(ProgramError::AccountDataTooSmall)?;
+    RecordKind::deserialize(&mut &*kind_data).map_err(|_| CrudError::InvalidKind.into())
+}
+
+/// Kind of a current record as written in its header, `Raw` for anything else
+pub fn header_kind(account_info: &AccountInfo) -> RecordKind {
+    record_kind(&account_info.data.borrow())
+}
+
+/// `header_kind` of raw account data
+pub fn record_kind(data: &[u8]) -> RecordKind {
+    match data.first() {
+        Some(&RecordData::CURRENT_VERSION) => data
+            .get(RecordData::KIND_INDEX..RecordData::WRITABLE_START_INDEX)
+            .and_then(|mut kind_data| RecordKind::deserialize(&mut kind_data).ok())
+            .unwrap_or(RecordKind::Raw),
+        _ => RecordKind::Raw,
+    }
+}
+
+/// Every load goes through this check, so a record of another kind fails instead of misparsing
+pub fn check_kind(account_info: &AccountInfo, kind: RecordKind) -> ProgramResult {
+    check_header(&account_info.data.borrow(), kind)
+}
+
+fn check_header(data: &[u8], kind: RecordKind) -> ProgramResult {
+    match data.first() {
+        None | Some(&RecordData::UNINITIALIZED) => return Err(ProgramError::UninitializedAccount),
+        Some(version) if *version < RecordData::CURRENT_VERSION => {
+            return Err(CrudError::OutdatedVersion.into())
+        }
+        Some(version) if *version > RecordData::CURRENT_VERSION => {
+            return Err(CrudError::UnsupportedVersion.into())
+        }
+        _ => {}
+    }
+    let kind_data = data
+        .get(RecordData::KIND_INDEX..RecordData::WRITABLE_START_INDEX)
+        .ok_or(ProgramError::AccountDataTooSmall)?;
+    match RecordKind::deserialize(&mut &*kind_data) {
+        Ok(record_kind) if record_kind == kind => Ok(()),
+        _ => Err(CrudError::InvalidKind.into()),
+    }
+}
+
+/// Upgrades record data from version `from` to `from + 1` in place
+pub type Migration = fn(&AccountInfo) -> ProgramResult;
+
+pub struct MigrationStep {
+    pub kind: RecordKind,
+    pub from: u8,
+    pub migrate: Migration,
+}
+
+/// Makes room for the kind in a `LEGACY_VERSION` header, `migrate` fills it in.
+/// Migrations of every legacy record start with it
+pub fn insert_kind(account_info: &AccountInfo) -> ProgramResult {
+    let mut account_data = account_info.data.borrow_mut();
+    let end = account_data.len();
+    if end < RecordData::WRITABLE_START_INDEX || account_data[end - 1] != 0 {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    account_data.copy_within(
+        RecordData::KIND_INDEX..end - 1,
+        RecordData::WRITABLE_START_INDEX,
+    );
+    Ok(())
+}
+
+/// Upgrades the record by one version with the step registered for its kind and version
+pub fn migrate(
+    account_info: &AccountInfo,
+    kind: RecordKind,
+    registry: &[MigrationStep],
+) -> ProgramResult {
+    msg!("Crud/Migrate");
+    let version = get_version(account_info)?;
+    if version >= RecordData::CURRENT_VERSION {
+        return Err(CrudError::UnsupportedVersion.into());
+    }
+    if version > RecordData::LEGACY_VERSION && get_kind(account_info)? != kind {
+        return Err(CrudError::InvalidKind.into());
+    }
+    let step = registry
+        .iter()
+        .find(|x| x.kind == kind && x.from == version)
+        .ok_or(CrudError::UnknownMigration)?;
+    (step.migrate)(account_info)?;
+    let mut account_data = account_info.data.borrow_mut();
+    account_data[0] = version + 1;
+    if version == RecordData::LEGACY_VERSION {
+        account_data[RecordData::KIND_INDEX] = kind as u8;
+    }
+    drop(account_data);
+    emit(kind, account_info, Change::Update);
+    Ok(())
+}
+
+/// Project recorded in the account header
+pub fn get_project(account_info: &AccountInfo) -> Result<Pubkey, ProgramError> {
+    let account_data = account_info.data.borrow();
+    if account_data.len() < RecordData::KIND_INDEX {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let project = Pubkey::new(&account_data[1..RecordData::KIND_INDEX]);
+    Ok(project)
+}
+
+impl RecordEvent {
+    /// Log line of the event as `emit` writes it, `parse_event` reads it back
+    pub fn to_log(&self) -> String {
+        format!(
+            "{}{}",
+            EVENT_LOG_PREFIX,
+            base64::encode(self.try_to_vec().unwrap())
+        )
+    }
+}
+
+pub fn emit(kind: RecordKind, account_info: &AccountInfo, change: Change) {
+    let event = RecordEvent {
+        kind,
+        key: *account_info.key,
+        project: get_project(account_info).unwrap_or_default(),
+        change,
+    };
+    msg!("{}", event.to_log());
+}
+
+/// Decodes an event from a program log line, returns None for other lines
+pub fn parse_event(log: &str) -> Option<RecordEvent> {
+    let log = log.strip_prefix("Program log: ").unwrap_or(log);
+    let encoded = log.strip_prefix(EVENT_LOG_PREFIX)?;
+    let bytes = base64::decode(encoded).ok()?;
+    RecordEvent::try_from_slice(&bytes).ok()
+}
+
+pub fn write_raw(account_info: &AccountInfo, offset: u64, data: Vec<u8>) -> ProgramResult {
     msg!("Crud/Write raw");
     let start = offset as usize;
     let end = start + data.len();
@@ -82,7 +334,7 @@ pub fn write_raw(account_info: &AccountInfo, offset: u64, mut data: Vec<u8>) ->
     }
 }
 
-pub fn write(account_info: &AccountInfo, offset: u64, mut data: Vec<u8>) -> ProgramResult {
+pub fn write(account_info: &AccountInfo, offset: u64, data: Vec<u8>) -> ProgramResult {
     msg!("Crud/Write");
     let start = RecordData::WRITABLE_START_INDEX + offset as usize;
     let end = start + data.len();
@@ -97,3 +349,179 @@ pub fn write(account_info: &AccountInfo, offset: u64, mut data: Vec<u8>) -> Prog
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+    struct TestRecord {
+        id: u32,
+        name: String,
+    }
+
+    #[test]
+    fn record_layout() {
+        let (key, owner, project) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut lamports = 0;
+        let mut data = vec![0; 64];
+        let account_info = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let (mut project_lamports, mut project_data) = (0, []);
+        let project_info = AccountInfo::new(
+            &project,
+            false,
+            false,
+            &mut project_lamports,
+            &mut project_data,
+            &owner,
+            false,
+            0,
+        );
+        initialize(&project_info, &account_info, RecordKind::Object).unwrap();
+        let record = TestRecord {
+            id: 7,
+            name: "card".to_string(),
+        };
+        write_record(&account_info, RecordKind::Object, &record).unwrap();
+
+        let account_data = account_info.data.borrow();
+        assert_eq!(account_data[0], RecordData::CURRENT_VERSION);
+        assert_eq!(&account_data[1..RecordData::KIND_INDEX], project.as_ref());
+        assert_eq!(
+            account_data[RecordData::KIND_INDEX],
+            RecordKind::Object as u8
+        );
+        assert_eq!(
+            &account_data[RecordData::WRITABLE_START_INDEX..][..12],
+            &record.try_to_vec().unwrap()[..]
+        );
+        drop(account_data);
+        assert_eq!(get_project(&account_info).unwrap(), project);
+        assert_eq!(
+            read_record::<TestRecord>(&account_info, RecordKind::Object).unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn record_checks() {
+        let (key, owner, project) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut lamports = 0;
+        let mut data = vec![0; 64];
+        let account_info = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let (mut project_lamports, mut project_data) = (0, []);
+        let project_info = AccountInfo::new(
+            &project,
+            false,
+            false,
+            &mut project_lamports,
+            &mut project_data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            read_record::<TestRecord>(&account_info, RecordKind::Object),
+            Err(ProgramError::UninitializedAccount)
+        );
+        initialize(&project_info, &account_info, RecordKind::Template).unwrap();
+        assert_eq!(
+            initialize(&project_info, &account_info, RecordKind::Template),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        assert_eq!(
+            read_record::<TestRecord>(&account_info, RecordKind::Object),
+            Err(CrudError::InvalidKind.into())
+        );
+        account_info.data.borrow_mut()[0] = RecordData::CURRENT_VERSION - 1;
+        assert_eq!(
+            read_record::<TestRecord>(&account_info, RecordKind::Template),
+            Err(CrudError::OutdatedVersion.into())
+        );
+    }
+
+    #[test]
+    fn raw_write_kind() {
+        let (key, owner, project) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut lamports = 0;
+        let mut data = vec![0; 64];
+        let account_info = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let (mut project_lamports, mut project_data) = (0, []);
+        let project_info = AccountInfo::new(
+            &project,
+            false,
+            false,
+            &mut project_lamports,
+            &mut project_data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(header_kind(&account_info), RecordKind::Raw);
+        initialize(&project_info, &account_info, RecordKind::Template).unwrap();
+        write_raw(&account_info, RecordData::WRITABLE_START_INDEX as u64, vec![1, 2]).unwrap();
+        assert_eq!(header_kind(&account_info), RecordKind::Template);
+        account_info.data.borrow_mut()[RecordData::KIND_INDEX] = u8::MAX;
+        assert_eq!(header_kind(&account_info), RecordKind::Raw);
+        account_info.data.borrow_mut()[RecordData::KIND_INDEX] = RecordKind::Template as u8;
+        account_info.data.borrow_mut()[0] = RecordData::LEGACY_VERSION;
+        assert_eq!(header_kind(&account_info), RecordKind::Raw);
+    }
+
+    #[test]
+    fn event_round_trip() {
+        for change in [Change::Create, Change::Update, Change::Delete] {
+            let event = RecordEvent {
+                kind: RecordKind::Object,
+                key: Pubkey::new_unique(),
+                project: Pubkey::new_unique(),
+                change,
+            };
+            let log = event.to_log();
+            assert_eq!(parse_event(&log), Some(event.clone()));
+            assert_eq!(parse_event(&format!("Program log: {}", log)), Some(event));
+        }
+        assert_eq!(parse_event("Program log: Crud/Write"), None);
+        assert_eq!(parse_event(&format!("{}not base64", EVENT_LOG_PREFIX)), None);
+        assert_eq!(parse_event(&format!("{}AQID", EVENT_LOG_PREFIX)), None);
+    }
+}
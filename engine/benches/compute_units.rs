@@ -0,0 +1,181 @@
+//! Compute units of storage instructions on storages of 10, 100 and 1000 entries, measured on
+//! the BPF build of the engine run by the BPF loader:
+//!
+//! `cargo build-bpf --manifest-path engine/Cargo.toml`
+//! `cargo bench -p solcery_engine --bench compute_units`
+//!
+//! The program is read from `BPF_OUT_DIR`, `target/deploy` by default. Counts are the ones the
+//! loader logs for the program, so they include the entrypoint deserializing the accounts.
+
+use {
+    solana_bpf_loader_program::process_instruction,
+    solana_program::{bpf_loader, instruction::Instruction, pubkey::Pubkey},
+    solana_sdk::{
+        account::{Account, AccountSharedData},
+        keyed_account::create_keyed_accounts_unified,
+        process_instruction::MockInvokeContext,
+    },
+    solcery_bank::Bank,
+    solcery_engine::{
+        entrypoint::{self, super_admins},
+        instruction,
+    },
+    solcery_storage::AccountStorage,
+    std::{cell::RefCell, env, fs, path::PathBuf, process},
+};
+
+const SPACE: usize = 1024;
+
+/// Project whose template storage holds `entries` objects plus `object`, which comes first
+struct Setup {
+    bank: Bank,
+    project: Pubkey,
+    storage: Pubkey,
+    object: Pubkey,
+}
+
+fn setup(entries: usize) -> Setup {
+    let mut bank = Bank::new(Pubkey::new_unique(), entrypoint::process_instruction);
+    let (program_id, admin) = (bank.program_id, super_admins()[0]);
+    let (project, template_storage) = (bank.alloc(SPACE), bank.alloc(SPACE));
+    let template = bank.alloc(SPACE);
+    let storage = bank.alloc(AccountStorage::KEYS_OFFSET + (entries + 1) * 32);
+    bank.process(&instruction::create_project(
+        &program_id,
+        &admin,
+        &project,
+        &template_storage,
+        &Pubkey::new_unique(),
+    ))
+    .unwrap();
+    bank.process(&instruction::create_template(
+        &program_id,
+        &admin,
+        &project,
+        &template,
+        &storage,
+        &template_storage,
+    ))
+    .unwrap();
+    let objects: Vec<Pubkey> = (0..=entries)
+        .map(|_| {
+            let object = bank.alloc(SPACE);
+            bank.process(&instruction::create_object(
+                &program_id,
+                &admin,
+                &project,
+                &template,
+                &storage,
+                &object,
+                None,
+                &[],
+            ))
+            .unwrap();
+            object
+        })
+        .collect();
+    Setup {
+        bank,
+        project,
+        storage,
+        object: objects[0],
+    }
+}
+
+fn program_path() -> PathBuf {
+    let dir = env::var("BPF_OUT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target/deploy"));
+    dir.join("solcery_engine.so")
+}
+
+fn account(data: Vec<u8>, owner: Pubkey, executable: bool) -> RefCell<AccountSharedData> {
+    RefCell::new(AccountSharedData::from(Account {
+        lamports: 1,
+        data,
+        owner,
+        executable,
+        rent_epoch: 0,
+    }))
+}
+
+/// Runs `instruction` on the accounts of `bank` in the BPF loader and reads the units it reports
+fn compute_units(program: &[u8], bank: &Bank, instruction: &Instruction) -> u64 {
+    let program_id = bank.program_id;
+    let program_account = account(program.to_vec(), bpf_loader::id(), true);
+    let accounts: Vec<_> = instruction
+        .accounts
+        .iter()
+        .map(|meta| {
+            let data = bank.accounts.get(&meta.pubkey).map(|x| x.data.clone());
+            account(data.unwrap_or_default(), program_id, false)
+        })
+        .collect();
+    let mut keyed_accounts = vec![(false, false, &program_id, &program_account)];
+    keyed_accounts.extend(
+        instruction
+            .accounts
+            .iter()
+            .zip(&accounts)
+            .map(|(meta, account)| (meta.is_signer, meta.is_writable, &meta.pubkey, account)),
+    );
+    let mut invoke_context = MockInvokeContext::new(create_keyed_accounts_unified(&keyed_accounts));
+    let result = process_instruction(&program_id, &instruction.data, &mut invoke_context);
+    let log = invoke_context.logger.log.borrow();
+    if let Err(e) = result {
+        panic!("instruction failed: {:?}, logs: {:#?}", e, log);
+    }
+    let prefix = format!("Program {} consumed ", program_id);
+    log.iter()
+        .find_map(|x| x.strip_prefix(&prefix)?.split(' ').next()?.parse().ok())
+        .unwrap()
+}
+
+fn main() {
+    let path = program_path();
+    let program = match fs::read(&path) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!(
+                "{}: {}, build the program with `cargo build-bpf` first",
+                path.display(),
+                e
+            );
+            process::exit(1);
+        }
+    };
+    println!(
+        "{:>8} {:>10} {:>10} {:>12}",
+        "entries", "add", "remove", "swap_remove"
+    );
+    for entries in [10, 100, 1000] {
+        let Setup {
+            mut bank,
+            project,
+            storage,
+            object,
+        } = setup(entries);
+        let program_id = bank.program_id;
+        let signer = super_admins()[0];
+        // The object is the first of `entries + 1` keys, removing it moves every other key
+        let remove =
+            instruction::remove_from_storage(&program_id, &signer, &project, &storage, &object);
+        let swap_remove = instruction::swap_remove_from_storage(
+            &program_id,
+            &signer,
+            &project,
+            &storage,
+            &object,
+        );
+        let remove_units = compute_units(&program, &bank, &remove);
+        let swap_remove_units = compute_units(&program, &bank, &swap_remove);
+        // Adding it back to `entries` keys checks them all for a duplicate
+        bank.process(&remove).unwrap();
+        let add = instruction::add_to_storage(&program_id, &signer, &project, &storage, &object);
+        let add_units = compute_units(&program, &bank, &add);
+        println!(
+            "{:>8} {:>10} {:>10} {:>12}",
+            entries, add_units, remove_units, swap_remove_units
+        );
+    }
+}
@@ -1,37 +1,55 @@
 use solana_program::{
-    entrypoint,
     entrypoint::ProgramResult,
     program_error::ProgramError,
     account_info::{ AccountInfo, next_account_info },
     pubkey::Pubkey,
-    msg,
 };
 use std::str::FromStr;
+use borsh::BorshDeserialize;
+use crate::migrations::MIGRATIONS;
 
 
-entrypoint!(process_instruction);
+/// Signers allowed to run everything except game instructions
+pub fn super_admins() -> Vec<Pubkey> {
+    vec![
+        Pubkey::from_str("9kXLhvDcWc4wzuapQpWkKVnJ8wKVhEDomwoFxkn58nfX").unwrap(),
+        Pubkey::from_str("ESrHRyZKaC9VjTdvd7QHppxevXpiasUAbzx2XGBRanrv").unwrap(),
+        Pubkey::from_str("CmxScbqG1imzdkmehMD1VoHait6oYx7o6CLtaHbDkdG1").unwrap(),
+        Pubkey::from_str("25MhYRx9CFLyQxf5HQKLPFd86QbkNFUWwUcVvQcTvPHJ").unwrap(),
+    ]
+}
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 pub fn process_instruction(
     _program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    let super_admins: Vec<Pubkey> = vec![
-        Pubkey::from_str("9kXLhvDcWc4wzuapQpWkKVnJ8wKVhEDomwoFxkn58nfX").unwrap(),
-        Pubkey::from_str("ESrHRyZKaC9VjTdvd7QHppxevXpiasUAbzx2XGBRanrv").unwrap(),
-        Pubkey::from_str("CmxScbqG1imzdkmehMD1VoHait6oYx7o6CLtaHbDkdG1").unwrap(),
-        Pubkey::from_str("25MhYRx9CFLyQxf5HQKLPFd86QbkNFUWwUcVvQcTvPHJ").unwrap(),
-    ];
+    let (tag, rest) = instruction_data
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    if *tag == 5 {
+        // Sessions are played by regular users, game module checks signers itself
+        return solcery_game::process_instruction(accounts, rest);
+    }
     let signer_account = next_account_info(&mut accounts.iter())?;
-    if !super_admins.contains(signer_account.key) {
+    if !super_admins().contains(signer_account.key) {
         return Err(ProgramError::InvalidAccountData); // closed for now
     }
-    let (tag, rest) = instruction_data.split_first().unwrap();
     match tag {
         0 => solcery_template::process_instruction(accounts, rest)?,
         1 => solcery_object::process_instruction(accounts, rest)?,
         2 => solcery_storage::process_instruction(accounts, rest)?,
         3 => solcery_crud::process_instruction(accounts, rest)?,
         4 => solcery_project::process_instruction(accounts, rest)?,
+        6 => {
+            let accounts_iter = &mut accounts.iter();
+            let _signer_info = next_account_info(accounts_iter)?;
+            let record_info = next_account_info(accounts_iter)?;
+            let kind = solcery_crud::RecordKind::deserialize(&mut &rest[..])?;
+            solcery_crud::migrate(record_info, kind, MIGRATIONS)?
+        }
         _ => return Err(ProgramError::InvalidAccountData),
     }
     Ok(())
@@ -0,0 +1,635 @@
+//! Builders of engine instructions for off-chain clients.
+//!
+//! Every instruction starts with the module tag and the tag of the module instruction,
+//! the signer goes first among the accounts and has to be one of the engine admins
+//! except for game instructions.
+//!
+//! Module errors are `ProgramError::Custom` codes in a range of 0x100 per module tag:
+//! - `0x000..0x0ff` template, `TemplateError`
+//! - `0x100..0x1ff` object, `ObjectError`
+//! - `0x200..0x2ff` storage, `StorageError`
+//! - `0x300..0x3ff` crud and migrations, `CrudError`
+//! - `0x400..0x4ff` project, `ProjectError`
+//! - `0x500..0x5ff` game, `GameError`
+
+use {
+    borsh::BorshSerialize,
+    solana_program::{
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+    },
+    solcery_crud::RecordKind,
+    solcery_object::ObjectData,
+    solcery_template::{FieldDefault, FieldParams, LinkRule, TemplateData},
+};
+
+pub const TEMPLATE: u8 = 0;
+pub const OBJECT: u8 = 1;
+pub const STORAGE: u8 = 2;
+pub const CRUD: u8 = 3;
+pub const PROJECT: u8 = 4;
+pub const GAME: u8 = 5;
+pub const MIGRATE: u8 = 6;
+
+/// Instruction with the signer followed by writable `accounts`
+pub fn engine_instruction(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    tag: &[u8],
+    data: Vec<u8>,
+    accounts: &[Pubkey],
+) -> Instruction {
+    let mut metas = vec![AccountMeta::new(*signer, true)];
+    metas.extend(accounts.iter().map(|x| AccountMeta::new(*x, false)));
+    Instruction {
+        program_id: *program_id,
+        accounts: metas,
+        data: [tag, &data].concat(),
+    }
+}
+
+pub fn create_project(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template_storage: &Pubkey,
+    owner: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[PROJECT, 0],
+        Vec::new(),
+        &[*project, *template_storage, *owner],
+    )
+}
+
+pub fn create_template(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    storage: &Pubkey,
+    template_storage: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 0],
+        Vec::new(),
+        &[*project, *template, *storage, *template_storage],
+    )
+}
+
+pub fn add_field(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    params: &FieldParams,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 1],
+        params.try_to_vec().unwrap(),
+        &[*project, *template],
+    )
+}
+
+/// Replaces the template draft, the id, version, storages, links, defaults, indexes and
+/// backrefs are kept by the program.
+/// New fields take ids from the project counter on, `ancestors` are the parent and its
+/// ancestors when `data` has a parent
+pub fn update_template(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    data: &TemplateData,
+    ancestors: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *template], ancestors].concat();
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 5],
+        data.try_to_vec().unwrap(),
+        &accounts,
+    )
+}
+
+pub fn attach_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    storage: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 7],
+        Vec::new(),
+        &[*project, *template, *storage],
+    )
+}
+
+pub fn attach_paged_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    storage: &Pubkey,
+    page_capacity: u32,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 14],
+        page_capacity.try_to_vec().unwrap(),
+        &[*project, *template, *storage],
+    )
+}
+
+/// Only empty storages can be detached
+pub fn detach_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    storage: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 8],
+        Vec::new(),
+        &[*project, *template, *storage],
+    )
+}
+
+pub fn set_link_rule(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    rule: &LinkRule,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 9],
+        rule.try_to_vec().unwrap(),
+        &[*project, *template],
+    )
+}
+
+/// `ancestors` are the ancestors of the parent
+pub fn set_parent(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    parent: &Pubkey,
+    ancestors: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *template, *parent], ancestors].concat();
+    engine_instruction(program_id, signer, &[TEMPLATE, 10], Vec::new(), &accounts)
+}
+
+/// `extra` are the ancestors of the template and the object a link default points to
+pub fn set_default(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    default: &FieldDefault,
+    extra: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *template], extra].concat();
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 11],
+        default.try_to_vec().unwrap(),
+        &accounts,
+    )
+}
+
+/// An uninitialized `snapshot` receives the immutable copy of the published version
+pub fn publish_template(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    snapshot: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 12],
+        Vec::new(),
+        &[*project, *template, *snapshot],
+    )
+}
+
+pub fn new_draft(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[TEMPLATE, 13],
+        Vec::new(),
+        &[*project, *template],
+    )
+}
+
+/// `page` is the page to add the object to when `storage` is paged, `extra` are ancestors
+/// and indexes of the template, objects linked by defaults and the other pages of the storage
+#[allow(clippy::too_many_arguments)]
+pub fn create_object(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    storage: &Pubkey,
+    object: &Pubkey,
+    page: Option<&Pubkey>,
+    extra: &[Pubkey],
+) -> Instruction {
+    let head = [*project, *template, *storage, *object];
+    let accounts = [&head[..], page.map(std::slice::from_ref).unwrap_or(&[]), extra].concat();
+    engine_instruction(
+        program_id,
+        signer,
+        &[OBJECT, 0],
+        vec![page.is_some() as u8],
+        &accounts,
+    )
+}
+
+/// Replaces object data, `extra` are ancestors and indexes of the template and linked objects
+pub fn update_object(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    object: &Pubkey,
+    template: &Pubkey,
+    data: &ObjectData,
+    extra: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *object, *template], extra].concat();
+    engine_instruction(
+        program_id,
+        signer,
+        &[OBJECT, 1],
+        data.try_to_vec().unwrap(),
+        &accounts,
+    )
+}
+
+pub fn clone_object(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    object: &Pubkey,
+    src: &Pubkey,
+    template: &Pubkey,
+    extra: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *object, *src, *template], extra].concat();
+    engine_instruction(program_id, signer, &[OBJECT, 3], Vec::new(), &accounts)
+}
+
+/// Creates the object in the first of `storages` of the template that isn't full,
+/// the rest of `storages` are ancestors, indexes and objects linked by defaults
+pub fn create_object_routed(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    object: &Pubkey,
+    storages: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *template, *object], storages].concat();
+    engine_instruction(program_id, signer, &[OBJECT, 4], Vec::new(), &accounts)
+}
+
+/// `extra` are indexes, linking objects and everything their delete rules touch
+pub fn delete_object(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    storage: &Pubkey,
+    object: &Pubkey,
+    extra: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *template, *storage, *object], extra].concat();
+    engine_instruction(program_id, signer, &[OBJECT, 5], Vec::new(), &accounts)
+}
+
+/// Creates an empty index, `extra` are the ancestors and the linked template
+pub fn create_index(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    index: &Pubkey,
+    field_id: u32,
+    extra: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *template, *index], extra].concat();
+    engine_instruction(
+        program_id,
+        signer,
+        &[OBJECT, 6],
+        field_id.try_to_vec().unwrap(),
+        &accounts,
+    )
+}
+
+/// Indexes objects from the cursor of the index up to `end`, or up to the last one.
+/// `extra` are the ancestors, the storages of the template with their pages and every
+/// object in the range
+pub fn build_index(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    index: &Pubkey,
+    end: Option<&Pubkey>,
+    extra: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *template, *index], extra].concat();
+    engine_instruction(
+        program_id,
+        signer,
+        &[OBJECT, 8],
+        end.copied().try_to_vec().unwrap(),
+        &accounts,
+    )
+}
+
+/// Creates an object for every data in pre-allocated `objects`,
+/// `extra` are ancestors and indexes of the template and linked objects
+#[allow(clippy::too_many_arguments)]
+pub fn create_objects(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    template: &Pubkey,
+    storage: &Pubkey,
+    objects: &[Pubkey],
+    data: &[ObjectData],
+    extra: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[*project, *template, *storage], objects, extra].concat();
+    engine_instruction(
+        program_id,
+        signer,
+        &[OBJECT, 7],
+        data.try_to_vec().unwrap(),
+        &accounts,
+    )
+}
+
+pub fn add_to_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    account: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 0],
+        Vec::new(),
+        &[*project, *storage, *account],
+    )
+}
+
+pub fn remove_from_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    account: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 1],
+        Vec::new(),
+        &[*project, *storage, *account],
+    )
+}
+
+/// `target` is the template of the storage, the storage itself for an abstract one
+pub fn create_paged_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    target: &Pubkey,
+    page_capacity: u32,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 2],
+        page_capacity.try_to_vec().unwrap(),
+        &[*project, *storage, *target],
+    )
+}
+
+/// An uninitialized `page` becomes the newest page of the storage,
+/// `pages` are the other pages of the storage
+/// `page` is the page whose range has the account, an uninitialized account for the first one
+pub fn add_to_page(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    page: &Pubkey,
+    account: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 3],
+        Vec::new(),
+        &[*project, *storage, *page, *account],
+    )
+}
+
+pub fn remove_from_page(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    page: &Pubkey,
+    account: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 4],
+        Vec::new(),
+        &[*project, *storage, *page, *account],
+    )
+}
+
+/// `new_page` is an uninitialized account taking the lower half of the page
+pub fn split_page(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    page: &Pubkey,
+    new_page: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 11],
+        Vec::new(),
+        &[*project, *storage, *page, *new_page],
+    )
+}
+
+/// Moves the last account in place of the removed one, sorted storages keep the order
+pub fn swap_remove_from_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    account: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 5],
+        Vec::new(),
+        &[*project, *storage, *account],
+    )
+}
+
+pub fn set_sorted(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    sorted: bool,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 6],
+        sorted.try_to_vec().unwrap(),
+        &[*project, *storage],
+    )
+}
+
+pub fn transfer_between_storages(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    from: &Pubkey,
+    to: &Pubkey,
+    account: &Pubkey,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 7],
+        Vec::new(),
+        &[*project, *from, *to, *account],
+    )
+}
+
+/// Adds the account at `index`, storages in sorted mode reject it
+pub fn insert_into_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    account: &Pubkey,
+    index: u32,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 8],
+        index.try_to_vec().unwrap(),
+        &[*project, *storage, *account],
+    )
+}
+
+/// Moves the account at `from` to `to` shifting the accounts in between
+pub fn move_in_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    from: u32,
+    to: u32,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 9],
+        (from, to).try_to_vec().unwrap(),
+        &[*project, *storage],
+    )
+}
+
+pub fn swap_in_storage(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    project: &Pubkey,
+    storage: &Pubkey,
+    a: u32,
+    b: u32,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[STORAGE, 10],
+        (a, b).try_to_vec().unwrap(),
+        &[*project, *storage],
+    )
+}
+
+/// Writes raw bytes at `offset` of the account data, header included
+pub fn write_raw(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    account: &Pubkey,
+    offset: u64,
+    data: &[u8],
+) -> Instruction {
+    let data = [&offset.to_le_bytes()[..], data].concat();
+    engine_instruction(program_id, signer, &[CRUD, 0], data, &[*account])
+}
+
+pub fn migrate(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    record: &Pubkey,
+    kind: RecordKind,
+) -> Instruction {
+    engine_instruction(
+        program_id,
+        signer,
+        &[MIGRATE],
+        kind.try_to_vec().unwrap(),
+        &[*record],
+    )
+}
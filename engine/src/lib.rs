@@ -1 +1,3 @@
 pub mod entrypoint;
+pub mod instruction;
+pub mod migrations;
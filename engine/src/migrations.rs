@@ -0,0 +1,26 @@
+use solcery_crud::{self as crud, MigrationStep, RecordKind};
+
+/// Registered record upgrades, each step moves a record of the kind one version up.
+/// Version 2 added the kind to the header, so every step starts with `crud::insert_kind`
+pub const MIGRATIONS: &[MigrationStep] = &[
+    MigrationStep {
+        kind: RecordKind::Project, // Id counter moved in front of the name
+        from: 1,
+        migrate: solcery_project::migrate_v1,
+    },
+    MigrationStep {
+        kind: RecordKind::Template, // New template fields are appended and read as zeros
+        from: 1,
+        migrate: crud::insert_kind,
+    },
+    MigrationStep {
+        kind: RecordKind::Storage, // Sorted flag after the template
+        from: 1,
+        migrate: solcery_storage::migrate_v1,
+    },
+    MigrationStep {
+        kind: RecordKind::Object, // Template version after the template
+        from: 1,
+        migrate: solcery_object::migrate_v1,
+    },
+];
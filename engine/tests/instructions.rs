@@ -0,0 +1,2402 @@
+//! Every engine and module instruction goes through `process_instruction` against an
+//! in-memory bank. Instructions run atomically like on a cluster: accounts are only updated
+//! when the instruction succeeds, so failures are checked to leave the state untouched.
+
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{
+        hash::hashv, instruction::Instruction, program_error::ProgramError, pubkey::Pubkey,
+    },
+    solcery_bank::Bank,
+    solcery_crud::{error::CrudError, parse_event, Change, RecordData, RecordKind},
+    solcery_engine::{
+        entrypoint::{process_instruction, super_admins},
+        instruction::{self, engine_instruction},
+    },
+    solcery_game::{
+        brick::{Action, Value},
+        error::GameError,
+        log::{GameEvent, LogEntry, LogHeader},
+        random::{self, Random},
+        Game, GameState,
+    },
+    solcery_object::{error::ObjectError, index::FieldIndex, Object, ObjectData, ObjectFieldData},
+    solcery_project::{error::ProjectError, Project},
+    solcery_storage::{
+        error::StorageError,
+        paged::{PagedStorage, StoragePage},
+        AccountStorage,
+    },
+    solcery_template::{
+        error::TemplateError, FieldDefault, FieldParams, LinkRule, OnDelete, SolceryType,
+        TemplateData,
+    },
+};
+
+const SPACE: usize = 1024;
+
+/// Project with one template and its storage, created by the first engine admin
+struct Setup {
+    bank: Bank,
+    admin: Pubkey,
+    owner: Pubkey,
+    project: Pubkey,
+    template_storage: Pubkey,
+    template: Pubkey,
+    storage: Pubkey,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let mut bank = Bank::new(Pubkey::new_unique(), process_instruction);
+        let admin = super_admins()[0];
+        let owner = Pubkey::new_unique();
+        let (project, template_storage) = (bank.alloc(SPACE), bank.alloc(SPACE));
+        let (template, storage) = (bank.alloc(SPACE), bank.alloc(SPACE));
+        let program_id = bank.program_id;
+        bank.process(&instruction::create_project(
+            &program_id,
+            &admin,
+            &project,
+            &template_storage,
+            &owner,
+        ))
+        .unwrap();
+        bank.process(&instruction::create_template(
+            &program_id,
+            &admin,
+            &project,
+            &template,
+            &storage,
+            &template_storage,
+        ))
+        .unwrap();
+        Setup {
+            bank,
+            admin,
+            owner,
+            project,
+            template_storage,
+            template,
+            storage,
+        }
+    }
+
+    fn program_id(&self) -> Pubkey {
+        self.bank.program_id
+    }
+
+    fn add_field(&mut self, template: &Pubkey, code: &str, field_type: SolceryType) {
+        let params = FieldParams {
+            field_type,
+            name: code.to_string(),
+            code: code.to_string(),
+            construct_client: false,
+            construct_server: false,
+        };
+        let instruction = instruction::add_field(
+            &self.program_id(),
+            &self.admin,
+            &self.project,
+            template,
+            &params,
+        );
+        self.bank.process(&instruction).unwrap();
+    }
+
+    /// Object with the defaults of the template, its indexes are passed along
+    fn create_object(&mut self, template: &Pubkey, storage: &Pubkey) -> Pubkey {
+        let object = self.bank.alloc(SPACE);
+        let instruction = instruction::create_object(
+            &self.program_id(),
+            &self.admin,
+            &self.project,
+            template,
+            storage,
+            &object,
+            None,
+            &self.template_data(template).indexes,
+        );
+        self.bank.process(&instruction).unwrap();
+        object
+    }
+
+    /// Index of the field built in one step, `extra` are the linked template, the storages
+    /// of the template and every object in them
+    fn create_index(&mut self, template: &Pubkey, field_id: u32, extra: &[Pubkey]) -> Pubkey {
+        let (program_id, index) = (self.program_id(), self.bank.alloc(SPACE));
+        let instructions = [
+            instruction::create_index(
+                &program_id,
+                &self.admin,
+                &self.project,
+                template,
+                &index,
+                field_id,
+                extra,
+            ),
+            instruction::build_index(
+                &program_id,
+                &self.admin,
+                &self.project,
+                template,
+                &index,
+                None,
+                extra,
+            ),
+        ];
+        for instruction in &instructions {
+            self.bank.process(instruction).unwrap();
+        }
+        index
+    }
+
+    /// Another template of the project with its storage
+    fn create_template(&mut self) -> (Pubkey, Pubkey) {
+        let (template, storage) = (self.bank.alloc(SPACE), self.bank.alloc(SPACE));
+        let instruction = instruction::create_template(
+            &self.program_id(),
+            &self.admin,
+            &self.project,
+            &template,
+            &storage,
+            &self.template_storage,
+        );
+        self.bank.process(&instruction).unwrap();
+        (template, storage)
+    }
+
+    fn template_data(&self, template: &Pubkey) -> TemplateData {
+        self.bank.record(template, RecordKind::Template)
+    }
+
+    fn storage_accounts(&self, storage: &Pubkey) -> Vec<Pubkey> {
+        self.bank
+            .record::<AccountStorage>(storage, RecordKind::Storage)
+            .accounts
+    }
+
+    /// Changes the last instruction logged for the record, every event belongs to the project
+    fn events(&self, key: &Pubkey) -> Vec<(RecordKind, Change)> {
+        self.bank
+            .logs
+            .iter()
+            .filter_map(|log| parse_event(log))
+            .filter(|event| event.key == *key)
+            .map(|event| {
+                assert_eq!(event.project, self.project);
+                (event.kind, event.change)
+            })
+            .collect()
+    }
+
+    /// Game instruction with the project in front of `accounts`
+    fn game(&self, signer: &Pubkey, tag: u8, data: Vec<u8>, accounts: &[Pubkey]) -> Instruction {
+        let accounts = [&[self.project], accounts].concat();
+        engine_instruction(
+            &self.program_id(),
+            signer,
+            &[instruction::GAME, tag],
+            data,
+            &accounts,
+        )
+    }
+
+    /// Session in the lobby with places Deck and Hand and entities 0 and 1 in the Deck
+    fn create_game(&mut self, max_players: u32) -> Pubkey {
+        let (owner, template) = (self.owner, self.template);
+        let game = self.bank.alloc(SPACE);
+        let data = max_players.try_to_vec().unwrap();
+        self.bank
+            .process(&self.game(&owner, 0, data, &[game]))
+            .unwrap();
+        let values = vec!["Deck".to_string(), "Hand".to_string()];
+        self.add_field(&template, "place", SolceryType::SEnum { values });
+        let field_id = self.template_data(&template).fields.last().unwrap().id;
+        let set_places = self.game(&owner, 5, field_id.try_to_vec().unwrap(), &[game, template]);
+        self.bank.process(&set_places).unwrap();
+        for _ in 0..2 {
+            let object = self.create_object(&template, &self.storage.clone());
+            let add_entity = self.game(&owner, 6, 0u32.try_to_vec().unwrap(), &[game, object]);
+            self.bank.process(&add_entity).unwrap();
+        }
+        game
+    }
+
+    /// Session of `players` created by `create_game` and started
+    fn start_game(&mut self, players: &[Pubkey]) -> Pubkey {
+        let game = self.create_game(4);
+        for player in players {
+            let join = self.game(player, 1, Vec::new(), &[game]);
+            self.bank.process(&join).unwrap();
+        }
+        let start = self.game(&self.owner, 3, Vec::new(), &[game]);
+        self.bank.process(&start).unwrap();
+        game
+    }
+
+    fn session(&self, game: &Pubkey) -> Game {
+        self.bank.record(game, RecordKind::Game)
+    }
+}
+
+/// Object data with the values laid out one after another
+fn object_data(values: &[(u32, Vec<u8>)]) -> ObjectData {
+    let mut data = ObjectData {
+        field_offsets: Vec::new(),
+        field_data: Vec::new(),
+    };
+    for (field_id, value) in values {
+        data.field_offsets.push(ObjectFieldData {
+            field_id: *field_id,
+            start_offset: data.field_data.len(),
+            end_offset: data.field_data.len() + value.len(),
+        });
+        data.field_data.extend_from_slice(value);
+    }
+    data
+}
+
+#[test]
+fn project_create() {
+    let mut setup = Setup::new();
+    let project: Project = setup.bank.record(&setup.project, RecordKind::Project);
+    assert_eq!(project.owner, setup.owner);
+    assert_eq!(project.template_storage, setup.template_storage);
+    assert_eq!(project.uniq_id, 3); // Template and its default fields
+    assert_eq!(
+        &setup.bank.data(&setup.project)[1..33],
+        setup.project.as_ref()
+    );
+    assert_eq!(
+        setup.storage_accounts(&setup.template_storage),
+        vec![setup.template]
+    );
+
+    let instruction = instruction::create_project(
+        &setup.program_id(),
+        &setup.admin,
+        &setup.project,
+        &setup.template_storage,
+        &setup.owner,
+    );
+    setup
+        .bank
+        .process_err(&instruction, ProgramError::AccountAlreadyInitialized);
+}
+
+#[test]
+fn non_admin_signer() {
+    let mut setup = Setup::new();
+    let (program_id, stranger) = (setup.program_id(), setup.owner);
+    let (project, template, storage) = (setup.project, setup.template, setup.storage);
+    let (new_project, new_storage) = (setup.bank.alloc(SPACE), setup.bank.alloc(SPACE));
+    let object = setup.create_object(&template, &storage);
+    let instructions = [
+        instruction::create_project(
+            &program_id,
+            &stranger,
+            &new_project,
+            &new_storage,
+            &stranger,
+        ),
+        instruction::create_template(
+            &program_id,
+            &stranger,
+            &project,
+            &new_project,
+            &new_storage,
+            &setup.template_storage,
+        ),
+        instruction::publish_template(&program_id, &stranger, &project, &template, &object),
+        instruction::clone_object(
+            &program_id,
+            &stranger,
+            &project,
+            &object,
+            &object,
+            &template,
+            &[],
+        ),
+        instruction::remove_from_storage(&program_id, &stranger, &project, &storage, &object),
+        instruction::write_raw(&program_id, &stranger, &object, 0, &[0]),
+        instruction::migrate(&program_id, &stranger, &object, RecordKind::Object),
+        engine_instruction(&program_id, &stranger, &[7], Vec::new(), &[]),
+    ];
+    for instruction in &instructions {
+        setup
+            .bank
+            .process_err(instruction, ProgramError::InvalidAccountData);
+    }
+
+    let unknown = engine_instruction(&program_id, &setup.admin, &[7], Vec::new(), &[]);
+    setup
+        .bank
+        .process_err(&unknown, ProgramError::InvalidAccountData);
+}
+
+#[test]
+fn template_create_and_update() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let template = setup.template_data(&setup.template);
+    assert_eq!(template, TemplateData::new(0, setup.storage));
+    let storage: AccountStorage = setup.bank.record(&setup.storage, RecordKind::Storage);
+    assert_eq!(storage.template, setup.template);
+    assert!(storage.accounts.is_empty());
+
+    let template_key = setup.template;
+    setup.add_field(&template_key, "power", SolceryType::SInt);
+    let template = setup.template_data(&setup.template);
+    let field = template.fields.last().unwrap();
+    assert_eq!(field.params.code, "power");
+    assert_eq!(field.params.field_type, SolceryType::SInt);
+    assert_eq!(template.max_field_index, field.id);
+
+    let mut draft = template.clone();
+    draft.name = "Creature".to_string();
+    draft.code = "creature".to_string();
+    draft.fields.retain(|x| x.params.code != "power");
+    let update =
+        instruction::update_template(&program_id, &admin, &project, &setup.template, &draft, &[]);
+    setup.bank.process(&update).unwrap();
+    let updated = setup.template_data(&setup.template);
+    assert_eq!(updated.name, "Creature");
+    assert_eq!(updated.code, "creature");
+    assert_eq!(
+        setup.events(&setup.template),
+        vec![(RecordKind::Template, Change::Update)]
+    );
+    assert!(updated.fields.iter().all(|x| x.params.code != "power"));
+
+    let extra_storage = setup.bank.alloc(SPACE);
+    let attach = instruction::attach_storage(
+        &program_id,
+        &admin,
+        &project,
+        &setup.template,
+        &extra_storage,
+    );
+    setup.bank.process(&attach).unwrap();
+    assert_eq!(
+        setup.template_data(&setup.template).storages,
+        vec![setup.storage, extra_storage]
+    );
+
+    // What other instructions maintain stays whatever the update carries
+    let before = setup.template_data(&setup.template);
+    let mut stale = draft.clone();
+    stale.id += 1;
+    stale.storages.clear();
+    stale.links.push(LinkRule {
+        field_id: draft.fields[0].id,
+        on_delete: OnDelete::Cascade,
+    });
+    stale.defaults.push(FieldDefault {
+        field_id: draft.fields[0].id,
+        value: vec![1],
+    });
+    let stale_update =
+        instruction::update_template(&program_id, &admin, &project, &setup.template, &stale, &[]);
+    setup.bank.process(&stale_update).unwrap();
+    let kept = setup.template_data(&setup.template);
+    assert_eq!(
+        (kept.id, kept.storages, kept.links, kept.defaults),
+        (before.id, before.storages, before.links, before.defaults)
+    );
+
+    let snapshot = setup.bank.alloc(SPACE);
+    let publish =
+        instruction::publish_template(&program_id, &admin, &project, &setup.template, &snapshot);
+    setup.bank.process(&publish).unwrap();
+    let published = setup.template_data(&setup.template);
+    assert!(published.published);
+    assert_eq!(published.versions, vec![snapshot]);
+    setup
+        .bank
+        .process_err(&update, TemplateError::Published.into());
+    setup
+        .bank
+        .process_err(&publish, TemplateError::Published.into());
+
+    let new_draft = instruction::new_draft(&program_id, &admin, &project, &setup.template);
+    setup.bank.process(&new_draft).unwrap();
+    let template = setup.template_data(&setup.template);
+    assert_eq!((template.version, template.published), (1, false));
+    setup
+        .bank
+        .process_err(&new_draft, TemplateError::Draft.into());
+    setup.bank.process(&update).unwrap();
+
+    // The published version stays as it was
+    let mut frozen: TemplateData = setup.bank.record(&snapshot, RecordKind::TemplateVersion);
+    assert_eq!((frozen.version, frozen.published), (0, true));
+    frozen.versions = vec![snapshot];
+    assert_eq!(frozen, published);
+}
+
+#[test]
+fn template_published_snapshot() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let (parent, _) = setup.create_template();
+    let snapshot = setup.bank.alloc(SPACE);
+    let publish =
+        instruction::publish_template(&program_id, &admin, &project, &template, &snapshot);
+    setup.bank.process(&publish).unwrap();
+    let data = setup.template_data(&template);
+    let field = data.fields[0].clone();
+
+    // Every change of the schema, tags 1 to 11, fails on published versions
+    let (new_storage, params) = (setup.bank.alloc(SPACE), field.params.clone());
+    let changes: Vec<(u8, Vec<u8>, Vec<Pubkey>)> = vec![
+        (1, params.try_to_vec().unwrap(), vec![]),
+        (2, field.id.try_to_vec().unwrap(), vec![]),
+        (3, "Name".to_string().try_to_vec().unwrap(), vec![]),
+        (4, "code".to_string().try_to_vec().unwrap(), vec![]),
+        (5, data.try_to_vec().unwrap(), vec![]),
+        (6, vec![], vec![new_storage]),
+        (7, vec![], vec![new_storage]),
+        (8, vec![], vec![storage]),
+        (9, vec![field.id as u8, 0, 0, 0, 1], vec![]),
+        (10, vec![], vec![parent]),
+        (11, vec![field.id as u8, 0, 0, 0, 0, 0, 0, 0], vec![]),
+    ];
+    for (tag, data, extra) in &changes {
+        let targets: [(Pubkey, ProgramError); 2] = [
+            (template, TemplateError::Published.into()),
+            (snapshot, CrudError::InvalidKind.into()),
+        ];
+        for (target, error) in targets.iter() {
+            let accounts = [&[project, *target], &extra[..]].concat();
+            let change = engine_instruction(
+                &program_id,
+                &admin,
+                &[instruction::TEMPLATE, *tag],
+                data.clone(),
+                &accounts,
+            );
+            setup.bank.process_err(&change, error.clone());
+        }
+    }
+    setup
+        .bank
+        .process_err(&publish, TemplateError::Published.into());
+}
+
+#[test]
+fn template_deprecated_changes() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let change = |tag: u8, data: Vec<u8>, accounts: &[Pubkey]| {
+        let accounts = [&[project], accounts].concat();
+        engine_instruction(
+            &program_id,
+            &admin,
+            &[instruction::TEMPLATE, tag],
+            data,
+            &accounts,
+        )
+    };
+    let field = setup.template_data(&template).fields[0].id;
+
+    let delete_field = change(2, field.try_to_vec().unwrap(), &[template]);
+    setup.bank.process(&delete_field).unwrap();
+    let data = setup.template_data(&template);
+    assert!(data.fields.iter().all(|x| x.id != field));
+    setup
+        .bank
+        .process_err(&delete_field, ProgramError::InvalidAccountData);
+
+    // Name and code go to templates only
+    for (tag, value) in [(3, "Creature"), (4, "creature")].iter() {
+        let value = value.to_string().try_to_vec().unwrap();
+        setup.bank.process_err(
+            &change(*tag, value.clone(), &[storage]),
+            CrudError::InvalidKind.into(),
+        );
+        setup
+            .bank
+            .process(&change(*tag, value, &[template]))
+            .unwrap();
+    }
+    let data = setup.template_data(&template);
+    assert_eq!(
+        (data.name.as_str(), data.code.as_str()),
+        ("Creature", "creature")
+    );
+
+    // Storage replaces the ones of the template and has to be a fresh account
+    setup.bank.process_err(
+        &change(6, Vec::new(), &[template, storage]),
+        ProgramError::AccountAlreadyInitialized,
+    );
+    let fresh = setup.bank.alloc(SPACE);
+    setup
+        .bank
+        .process(&change(6, Vec::new(), &[template, fresh]))
+        .unwrap();
+    assert_eq!(setup.template_data(&template).storages, vec![fresh]);
+    let assigned: AccountStorage = setup.bank.record(&fresh, RecordKind::Storage);
+    assert_eq!(assigned.template, template);
+}
+
+#[test]
+fn template_detach_storage() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let spare = setup.bank.alloc(SPACE);
+    let attach = instruction::attach_storage(&program_id, &admin, &project, &template, &spare);
+    setup.bank.process(&attach).unwrap();
+    setup.create_object(&template, &storage);
+    let detach = |storage: &Pubkey| {
+        instruction::detach_storage(&program_id, &admin, &project, &template, storage)
+    };
+
+    // Only empty storages of the template can go
+    setup
+        .bank
+        .process_err(&detach(&storage), ProgramError::InvalidArgument);
+    setup.bank.process(&detach(&spare)).unwrap();
+    assert_eq!(setup.template_data(&template).storages, vec![storage]);
+    setup
+        .bank
+        .process_err(&detach(&spare), ProgramError::InvalidArgument);
+}
+
+#[test]
+fn template_link_rules() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let template = setup.template;
+    setup.add_field(&template, "friend", SolceryType::SLink { template });
+    let friend = setup.template_data(&template).max_field_index;
+    let power = setup.template_data(&template).fields[0].id;
+    let set_rule = |field_id: u32, on_delete: OnDelete| {
+        let rule = LinkRule {
+            field_id,
+            on_delete,
+        };
+        instruction::set_link_rule(&program_id, &admin, &project, &template, &rule)
+    };
+
+    // A rule per link field, setting it again replaces the old one
+    setup.bank.process_err(
+        &set_rule(power, OnDelete::Cascade),
+        ProgramError::InvalidArgument,
+    );
+    setup.bank.process_err(
+        &set_rule(friend + 1, OnDelete::Cascade),
+        ProgramError::InvalidArgument,
+    );
+    setup
+        .bank
+        .process(&set_rule(friend, OnDelete::Cascade))
+        .unwrap();
+    setup
+        .bank
+        .process(&set_rule(friend, OnDelete::Nullify))
+        .unwrap();
+    let links = setup.template_data(&template).links;
+    assert_eq!(
+        links,
+        vec![LinkRule {
+            field_id: friend,
+            on_delete: OnDelete::Nullify
+        }]
+    );
+}
+
+#[test]
+fn template_parent_and_defaults() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let parent = setup.template;
+    let (child, _) = setup.create_template();
+    let set_parent = |template: &Pubkey, parent: &Pubkey, ancestors: &[Pubkey]| {
+        instruction::set_parent(&program_id, &admin, &project, template, parent, ancestors)
+    };
+
+    // Parents come from the same project
+    let (other_project, other_templates) = (setup.bank.alloc(SPACE), setup.bank.alloc(SPACE));
+    let (stranger, stranger_storage) = (setup.bank.alloc(SPACE), setup.bank.alloc(SPACE));
+    let instructions = [
+        instruction::create_project(
+            &program_id,
+            &admin,
+            &other_project,
+            &other_templates,
+            &setup.owner,
+        ),
+        instruction::create_template(
+            &program_id,
+            &admin,
+            &other_project,
+            &stranger,
+            &stranger_storage,
+            &other_templates,
+        ),
+    ];
+    for instruction in &instructions {
+        setup.bank.process(instruction).unwrap();
+    }
+    setup.bank.process_err(
+        &set_parent(&child, &stranger, &[]),
+        ProgramError::InvalidAccountData,
+    );
+
+    // Templates can't inherit from themselves or their descendants
+    setup.bank.process_err(
+        &set_parent(&parent, &parent, &[]),
+        ProgramError::InvalidArgument,
+    );
+    setup
+        .bank
+        .process(&set_parent(&child, &parent, &[]))
+        .unwrap();
+    setup.bank.process_err(
+        &set_parent(&parent, &child, &[parent]),
+        ProgramError::InvalidArgument,
+    );
+    assert_eq!(setup.template_data(&parent).parent, None);
+
+    // Defaults can be set for inherited fields when the ancestors are passed
+    setup.add_field(&parent, "power", SolceryType::SInt);
+    let power = setup.template_data(&parent).max_field_index;
+    let default = FieldDefault {
+        field_id: power,
+        value: 3i32.try_to_vec().unwrap(),
+    };
+    let set_default = |ancestors: &[Pubkey]| {
+        instruction::set_default(&program_id, &admin, &project, &child, &default, ancestors)
+    };
+    setup
+        .bank
+        .process_err(&set_default(&[]), ProgramError::NotEnoughAccountKeys);
+    setup.bank.process(&set_default(&[parent])).unwrap();
+    assert_eq!(setup.template_data(&child).defaults, vec![default.clone()]);
+}
+
+#[test]
+fn template_parent_field_ids() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let parent = setup.template;
+    let (child, child_storage) = setup.create_template();
+
+    // Fresh templates take their fields from the project counter, so they fit together
+    let set_parent = instruction::set_parent(&program_id, &admin, &project, &child, &parent, &[]);
+    setup.bank.process(&set_parent).unwrap();
+    assert_eq!(setup.template_data(&child).parent, Some(parent));
+    setup.add_field(&child, "power", SolceryType::SInt);
+    setup.add_field(&parent, "rank", SolceryType::SInt);
+    let child_data = setup.template_data(&child);
+    let parent_data = setup.template_data(&parent);
+    assert!(child_data
+        .fields
+        .iter()
+        .all(|x| parent_data.fields.iter().all(|y| y.id != x.id)));
+    let create = instruction::create_object(
+        &program_id,
+        &admin,
+        &project,
+        &child,
+        &child_storage,
+        &setup.bank.alloc(SPACE),
+        None,
+        &[parent],
+    );
+    setup.bank.process(&create).unwrap();
+
+    // Updates can't take ids given out before or drop the checks of set_parent
+    let next_id = setup
+        .bank
+        .record::<Project>(&project, RecordKind::Project)
+        .uniq_id;
+    let mut taken = child_data.clone();
+    taken.fields.push(parent_data.fields[0].clone());
+    let update = |data: &TemplateData, ancestors: &[Pubkey]| {
+        instruction::update_template(&program_id, &admin, &project, &child, data, ancestors)
+    };
+    setup
+        .bank
+        .process_err(&update(&taken, &[parent]), ProgramError::InvalidArgument);
+    setup.bank.process_err(
+        &update(&child_data, &[]),
+        ProgramError::NotEnoughAccountKeys,
+    );
+    let mut cycle = parent_data.clone();
+    cycle.parent = Some(child);
+    let cycle = instruction::update_template(
+        &program_id,
+        &admin,
+        &project,
+        &parent,
+        &cycle,
+        &[child, parent],
+    );
+    setup
+        .bank
+        .process_err(&cycle, ProgramError::InvalidArgument);
+
+    let mut fresh = child_data.clone();
+    let mut field = fresh.fields[0].clone();
+    field.id = next_id + 5;
+    fresh.fields.push(field);
+    setup.bank.process(&update(&fresh, &[parent])).unwrap();
+    assert_eq!(setup.template_data(&child).max_field_index, next_id + 5);
+    let project_data: Project = setup.bank.record(&project, RecordKind::Project);
+    assert_eq!(project_data.uniq_id, next_id + 6);
+}
+
+#[test]
+fn object_create_update_clone() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    setup.add_field(&template, "power", SolceryType::SInt);
+    setup.add_field(&template, "friend", SolceryType::SLink { template });
+    let fields = setup.template_data(&template).fields;
+    let (power, friend) = (fields[fields.len() - 2].id, fields[fields.len() - 1].id);
+    let links = setup.create_index(&template, friend, &[storage]);
+
+    let object = setup.create_object(&template, &storage);
+    let created: Object = setup.bank.record(&object, RecordKind::Object);
+    assert_eq!(created.template, template);
+    assert_eq!(created.template_version, 0);
+    assert_eq!(setup.storage_accounts(&storage), vec![object]);
+    assert_eq!(
+        setup.events(&object),
+        vec![(RecordKind::Object, Change::Create)]
+    );
+    assert_eq!(
+        setup.events(&storage),
+        vec![(RecordKind::Storage, Change::Update)]
+    );
+
+    let other = setup.create_object(&template, &storage);
+    let data = object_data(&[
+        (power, 42i32.try_to_vec().unwrap()),
+        (friend, other.to_bytes().to_vec()),
+    ]);
+    let update = instruction::update_object(
+        &program_id,
+        &admin,
+        &project,
+        &object,
+        &template,
+        &data,
+        &[other, links],
+    );
+    setup.bank.process(&update).unwrap();
+    let updated: Object = setup.bank.record(&object, RecordKind::Object);
+    assert_eq!(updated.id, created.id);
+    assert_eq!(updated.data, data);
+    assert_eq!(
+        setup.events(&object),
+        vec![(RecordKind::Object, Change::Update)]
+    );
+
+    let copy = setup.create_object(&template, &storage);
+    let clone = instruction::clone_object(
+        &program_id,
+        &admin,
+        &project,
+        &copy,
+        &object,
+        &template,
+        &[other, links],
+    );
+    setup.bank.process(&clone).unwrap();
+    let cloned: Object = setup.bank.record(&copy, RecordKind::Object);
+    assert_eq!(cloned.data, data);
+    assert_ne!(cloned.id, updated.id);
+    assert_eq!(
+        setup.events(&copy),
+        vec![(RecordKind::Object, Change::Update)]
+    );
+    assert!(setup.events(&object).is_empty());
+
+    // Link target has to be passed and belong to the linked template
+    let missing_link = instruction::update_object(
+        &program_id,
+        &admin,
+        &project,
+        &other,
+        &template,
+        &data,
+        &[links],
+    );
+    setup
+        .bank
+        .process_err(&missing_link, ObjectError::InvalidLink.into());
+
+    let unknown_field = object_data(&[(power + 100, vec![1, 0, 0, 0])]);
+    let update = instruction::update_object(
+        &program_id,
+        &admin,
+        &project,
+        &object,
+        &template,
+        &unknown_field,
+        &[],
+    );
+    setup
+        .bank
+        .process_err(&update, ObjectError::UnknownField.into());
+
+    let mut out_of_bounds = object_data(&[(power, 1i32.try_to_vec().unwrap())]);
+    out_of_bounds.field_offsets[0].end_offset = 8;
+    let update = instruction::update_object(
+        &program_id,
+        &admin,
+        &project,
+        &object,
+        &template,
+        &out_of_bounds,
+        &[],
+    );
+    setup
+        .bank
+        .process_err(&update, ObjectError::InvalidFieldData.into());
+
+    let other_template = setup.bank.alloc(SPACE);
+    let other_storage = setup.bank.alloc(SPACE);
+    let create_template = instruction::create_template(
+        &program_id,
+        &admin,
+        &project,
+        &other_template,
+        &other_storage,
+        &setup.template_storage,
+    );
+    setup.bank.process(&create_template).unwrap();
+    let update = instruction::update_object(
+        &program_id,
+        &admin,
+        &project,
+        &object,
+        &other_template,
+        &data,
+        &[other],
+    );
+    setup
+        .bank
+        .process_err(&update, ObjectError::InvalidTemplate.into());
+}
+
+#[test]
+fn object_template_version() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let object = setup.create_object(&template, &storage);
+    let publish = |snapshot: &Pubkey| {
+        instruction::publish_template(&program_id, &admin, &project, &template, snapshot)
+    };
+    let new_draft = instruction::new_draft(&program_id, &admin, &project, &template);
+    let data = object_data(&[]);
+    let update =
+        instruction::update_object(&program_id, &admin, &project, &object, &template, &data, &[]);
+    let version = |setup: &Setup| {
+        let object: Object = setup.bank.record(&object, RecordKind::Object);
+        object.template_version
+    };
+
+    // Changes under a draft keep the version, published ones move the object to it
+    let snapshots = [setup.bank.alloc(SPACE), setup.bank.alloc(SPACE)];
+    setup.bank.process(&publish(&snapshots[0])).unwrap();
+    setup.bank.process(&new_draft).unwrap();
+    setup.bank.process(&update).unwrap();
+    assert_eq!(version(&setup), 0);
+    setup.bank.process(&publish(&snapshots[1])).unwrap();
+    setup.bank.process(&update).unwrap();
+    assert_eq!(version(&setup), 1);
+}
+
+#[test]
+fn object_load_from_source() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    setup.add_field(&template, "power", SolceryType::SInt);
+    let power = setup.template_data(&template).max_field_index;
+    let object = setup.create_object(&template, &storage);
+
+    // Source holds the object data as it goes after the object header
+    let header = RecordData::WRITABLE_START_INDEX + 40; // Id, template and its version
+    let source = |setup: &mut Setup, data: &ObjectData| {
+        let mut source = data.try_to_vec().unwrap();
+        source.resize(SPACE - header, 0);
+        setup.bank.alloc_data(source)
+    };
+    let load = |source: &Pubkey| {
+        engine_instruction(
+            &program_id,
+            &admin,
+            &[instruction::OBJECT, 2],
+            Vec::new(),
+            &[project, object, *source, template],
+        )
+    };
+    let unknown = source(&mut setup, &object_data(&[(power + 1, vec![1])]));
+    setup
+        .bank
+        .process_err(&load(&unknown), ObjectError::UnknownField.into());
+    let data = object_data(&[(power, 8i32.try_to_vec().unwrap())]);
+    let valid = source(&mut setup, &data);
+    setup.bank.process(&load(&valid)).unwrap();
+    let loaded: Object = setup.bank.record(&object, RecordKind::Object);
+    assert_eq!(loaded.data, data);
+}
+
+#[test]
+fn object_create_routed() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let small = setup
+        .bank
+        .alloc_data(vec![0; AccountStorage::KEYS_OFFSET + 32]);
+    let attach = instruction::attach_storage(&program_id, &admin, &project, &template, &small);
+    setup.bank.process(&attach).unwrap();
+    let (_, other_storage) = setup.create_template();
+    let create = |object: &Pubkey, storages: &[Pubkey]| {
+        instruction::create_object_routed(
+            &program_id,
+            &admin,
+            &project,
+            &template,
+            object,
+            storages,
+        )
+    };
+
+    // Storages of other templates are skipped, full ones too
+    let first = setup.bank.alloc(SPACE);
+    setup
+        .bank
+        .process(&create(&first, &[other_storage, small, storage]))
+        .unwrap();
+    assert_eq!(setup.storage_accounts(&small), vec![first]);
+    let second = setup.bank.alloc(SPACE);
+    setup.bank.process_err(
+        &create(&second, &[small]),
+        ProgramError::AccountDataTooSmall,
+    );
+    setup
+        .bank
+        .process(&create(&second, &[small, storage]))
+        .unwrap();
+    assert_eq!(setup.storage_accounts(&storage), vec![second]);
+    let created: Object = setup.bank.record(&second, RecordKind::Object);
+    assert_eq!(created.template, template);
+}
+
+#[test]
+fn object_delete() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let object = setup.create_object(&template, &storage);
+    let (other_template, other_storage) = setup.create_template();
+    let delete = |template: &Pubkey, storage: &Pubkey| {
+        instruction::delete_object(
+            &program_id,
+            &admin,
+            &project,
+            template,
+            storage,
+            &object,
+            &[],
+        )
+    };
+
+    setup.bank.process_err(
+        &delete(&other_template, &other_storage),
+        ObjectError::InvalidTemplate.into(),
+    );
+    setup.bank.process_err(
+        &delete(&template, &other_storage),
+        ProgramError::InvalidArgument,
+    );
+    setup.bank.process(&delete(&template, &storage)).unwrap();
+    assert_eq!(setup.storage_accounts(&storage), Vec::new());
+    assert_eq!(
+        setup.events(&object),
+        vec![(RecordKind::Object, Change::Delete)]
+    );
+    let account = &setup.bank.accounts[&object];
+    assert_eq!(account.lamports, 0);
+    assert!(account.data.iter().all(|x| *x == 0));
+    setup.bank.process_err(
+        &delete(&template, &storage),
+        ProgramError::UninitializedAccount,
+    );
+}
+
+#[test]
+fn object_create_batch() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    setup.add_field(&template, "power", SolceryType::SInt);
+    let power = setup.template_data(&template).max_field_index;
+    let default = FieldDefault {
+        field_id: power,
+        value: 5i32.try_to_vec().unwrap(),
+    };
+    let set_default =
+        instruction::set_default(&program_id, &admin, &project, &template, &default, &[]);
+    setup.bank.process(&set_default).unwrap();
+
+    let objects = [setup.bank.alloc(SPACE), setup.bank.alloc(SPACE)];
+    let data = [
+        object_data(&[(power, 9i32.try_to_vec().unwrap())]),
+        object_data(&[]),
+    ];
+    let create = |objects: &[Pubkey]| {
+        instruction::create_objects(
+            &program_id,
+            &admin,
+            &project,
+            &template,
+            &storage,
+            objects,
+            &data,
+            &[],
+        )
+    };
+    setup
+        .bank
+        .process_err(&create(&objects[..1]), ProgramError::NotEnoughAccountKeys);
+    setup.bank.process(&create(&objects)).unwrap();
+    let first: Object = setup.bank.record(&objects[0], RecordKind::Object);
+    let second: Object = setup.bank.record(&objects[1], RecordKind::Object);
+    assert_eq!(first.data, data[0]);
+    assert_eq!(second.data, object_data(&[(power, default.value)]));
+    assert_eq!(second.id, first.id + 1);
+    assert_eq!(setup.storage_accounts(&storage), objects.to_vec());
+}
+
+#[test]
+fn link_defaults() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    setup.add_field(&template, "friend", SolceryType::SLink { template });
+    let friend = setup.template_data(&template).max_field_index;
+    let links = setup.create_index(&template, friend, &[storage]);
+    let target = setup.create_object(&template, &storage);
+    let set_default = |value: &Pubkey, extra: &[Pubkey]| {
+        let default = FieldDefault {
+            field_id: friend,
+            value: value.to_bytes().to_vec(),
+        };
+        instruction::set_default(&program_id, &admin, &project, &template, &default, extra)
+    };
+
+    // The linked object has to be passed and belong to the linked template
+    setup.bank.process_err(
+        &set_default(&target, &[]),
+        TemplateError::InvalidDefault.into(),
+    );
+    setup.bank.process_err(
+        &set_default(&storage, &[storage]),
+        TemplateError::InvalidDefault.into(),
+    );
+    setup
+        .bank
+        .process(&set_default(&target, &[target]))
+        .unwrap();
+
+    // and again on create, the object might be gone by then
+    let object = setup.bank.alloc(SPACE);
+    let create = |extra: &[Pubkey]| {
+        instruction::create_object(
+            &program_id,
+            &admin,
+            &project,
+            &template,
+            &storage,
+            &object,
+            None,
+            extra,
+        )
+    };
+    setup
+        .bank
+        .process_err(&create(&[links]), ObjectError::InvalidLink.into());
+    setup.bank.process(&create(&[target, links])).unwrap();
+    let created: Object = setup.bank.record(&object, RecordKind::Object);
+    assert_eq!(
+        created.data,
+        object_data(&[(friend, target.to_bytes().to_vec())])
+    );
+}
+
+#[test]
+fn object_build_index() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    setup.add_field(&template, "power", SolceryType::SInt);
+    let power = setup.template_data(&template).max_field_index;
+    let mut objects: Vec<Pubkey> = (0..3)
+        .map(|_| setup.create_object(&template, &storage))
+        .collect();
+    objects.sort();
+    let set_power = |object: &Pubkey, value: i32, extra: &[Pubkey]| {
+        let data = object_data(&[(power, value.try_to_vec().unwrap())]);
+        instruction::update_object(&program_id, &admin, &project, object, &template, &data, extra)
+    };
+    for (object, value) in objects.iter().zip([1, 2, 3]) {
+        setup.bank.process(&set_power(object, value, &[])).unwrap();
+    }
+
+    // The index starts out empty and is registered right away
+    let index = setup.bank.alloc(SPACE);
+    let create =
+        instruction::create_index(&program_id, &admin, &project, &template, &index, power, &[]);
+    setup.bank.process(&create).unwrap();
+    let created: FieldIndex = setup.bank.record(&index, RecordKind::Index);
+    assert_eq!(
+        (created.entries.len(), created.cursor),
+        (0, Some(Pubkey::default()))
+    );
+    assert_eq!(setup.template_data(&template).indexes, vec![index]);
+
+    // Every object of the range has to be passed, objects past the range are left alone
+    let build = |end: Option<&Pubkey>, extra: &[Pubkey]| {
+        instruction::build_index(&program_id, &admin, &project, &template, &index, end, extra)
+    };
+    setup.bank.process_err(
+        &build(Some(&objects[1]), &[objects[0]]),
+        ProgramError::NotEnoughAccountKeys,
+    );
+    setup.bank.process_err(
+        &build(Some(&objects[1]), &[storage]),
+        ObjectError::IncompleteIndex.into(),
+    );
+    setup
+        .bank
+        .process(&build(Some(&objects[1]), &[storage, objects[0], objects[2]]))
+        .unwrap();
+    let built: FieldIndex = setup.bank.record(&index, RecordKind::Index);
+    assert_eq!(built.cursor, Some(objects[1]));
+    assert_eq!(built.lookup(&1i32.try_to_vec().unwrap()), vec![objects[0]]);
+    assert_eq!(built.entries.len(), 1);
+    setup.bank.process_err(
+        &build(Some(&objects[1]), &[storage]),
+        ProgramError::InvalidArgument,
+    );
+
+    // Updates below the cursor go to the index, the rest is read by the next step
+    setup
+        .bank
+        .process(&set_power(&objects[0], 4, &[index]))
+        .unwrap();
+    setup
+        .bank
+        .process(&set_power(&objects[2], 5, &[index]))
+        .unwrap();
+    let built: FieldIndex = setup.bank.record(&index, RecordKind::Index);
+    assert_eq!(built.lookup(&4i32.try_to_vec().unwrap()), vec![objects[0]]);
+    assert_eq!(built.entries.len(), 1);
+
+    // Objects gone from the storages can't be indexed
+    let remove =
+        instruction::remove_from_storage(&program_id, &admin, &project, &storage, &objects[1]);
+    setup.bank.process(&remove).unwrap();
+    setup.bank.process_err(
+        &build(None, &[storage, objects[1], objects[2]]),
+        ObjectError::IncompleteIndex.into(),
+    );
+    setup
+        .bank
+        .process(&build(None, &[storage, objects[2]]))
+        .unwrap();
+    let built: FieldIndex = setup.bank.record(&index, RecordKind::Index);
+    assert_eq!(built.cursor, None);
+    assert_eq!(built.lookup(&5i32.try_to_vec().unwrap()), vec![objects[2]]);
+    assert_eq!(built.entries.len(), 2);
+    setup
+        .bank
+        .process_err(&build(None, &[storage]), ObjectError::IndexBuilt.into());
+}
+
+#[test]
+fn object_index_upkeep() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    setup.add_field(&template, "power", SolceryType::SInt);
+    let power = setup.template_data(&template).max_field_index;
+    let default = FieldDefault {
+        field_id: power,
+        value: 1i32.try_to_vec().unwrap(),
+    };
+    let set_default =
+        instruction::set_default(&program_id, &admin, &project, &template, &default, &[]);
+    setup.bank.process(&set_default).unwrap();
+    let index = setup.bank.alloc(SPACE);
+    let create =
+        instruction::create_index(&program_id, &admin, &project, &template, &index, power, &[]);
+    setup.bank.process(&create).unwrap();
+    let build = instruction::build_index(
+        &program_id,
+        &admin,
+        &project,
+        &template,
+        &index,
+        None,
+        &[storage],
+    );
+    setup.bank.process(&build).unwrap();
+    let lookup = |setup: &Setup, value: i32| {
+        let index: FieldIndex = setup.bank.record(&index, RecordKind::Index);
+        index.lookup(&value.try_to_vec().unwrap())
+    };
+
+    // Created objects come in with their default
+    let object = setup.bank.alloc(SPACE);
+    let create = |extra: &[Pubkey]| {
+        instruction::create_object(
+            &program_id,
+            &admin,
+            &project,
+            &template,
+            &storage,
+            &object,
+            None,
+            extra,
+        )
+    };
+    setup
+        .bank
+        .process_err(&create(&[]), ObjectError::MissingIndex.into());
+    setup.bank.process(&create(&[index])).unwrap();
+    assert_eq!(lookup(&setup, 1), vec![object]);
+
+    // Updates move the entry to the new value
+    let data = object_data(&[(power, 2i32.try_to_vec().unwrap())]);
+    let update = |extra: &[Pubkey]| {
+        instruction::update_object(&program_id, &admin, &project, &object, &template, &data, extra)
+    };
+    setup
+        .bank
+        .process_err(&update(&[]), ObjectError::MissingIndex.into());
+    setup.bank.process(&update(&[index])).unwrap();
+    assert_eq!(lookup(&setup, 1), Vec::new());
+    assert_eq!(lookup(&setup, 2), vec![object]);
+
+    // Deleted objects leave the index
+    let delete = |extra: &[Pubkey]| {
+        instruction::delete_object(
+            &program_id,
+            &admin,
+            &project,
+            &template,
+            &storage,
+            &object,
+            extra,
+        )
+    };
+    setup
+        .bank
+        .process_err(&delete(&[]), ObjectError::MissingIndex.into());
+    setup.bank.process(&delete(&[index])).unwrap();
+    let index: FieldIndex = setup.bank.record(&index, RecordKind::Index);
+    assert_eq!(index.entries, Vec::new());
+}
+
+#[test]
+fn object_link_release() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (target_template, target_storage) = (setup.template, setup.storage);
+    let (template, storage) = setup.create_template();
+    let link = SolceryType::SLink {
+        template: target_template,
+    };
+    setup.add_field(&template, "target", link.clone());
+    setup.add_field(&template, "spare", link);
+    let fields = setup.template_data(&template).fields;
+    let (target, spare) = (fields[fields.len() - 2].id, fields[fields.len() - 1].id);
+
+    // The index of a link field is a backreference index of the linked template
+    let links = setup.create_index(&template, target, &[target_template, storage]);
+    assert_eq!(setup.template_data(&template).indexes, vec![links]);
+    assert_eq!(setup.template_data(&target_template).backrefs, vec![links]);
+
+    let targets: Vec<Pubkey> = (0..4)
+        .map(|_| setup.create_object(&target_template, &target_storage))
+        .collect();
+    let referrers: Vec<Pubkey> = (0..2)
+        .map(|_| setup.create_object(&template, &storage))
+        .collect();
+    let set_link = |object: &Pubkey, field_id: u32, target: &Pubkey| {
+        let data = object_data(&[(field_id, target.to_bytes().to_vec())]);
+        instruction::update_object(
+            &program_id,
+            &admin,
+            &project,
+            object,
+            &template,
+            &data,
+            &[*target, links],
+        )
+    };
+    let backrefs = |setup: &Setup, target: &Pubkey| {
+        let index: FieldIndex = setup.bank.record(&links, RecordKind::Index);
+        index.lookup(target.as_ref())
+    };
+
+    // Links are only written to indexed fields, relinking moves the backreference
+    setup.bank.process_err(
+        &set_link(&referrers[0], spare, &targets[0]),
+        ObjectError::UnindexedLink.into(),
+    );
+    setup
+        .bank
+        .process(&set_link(&referrers[0], target, &targets[3]))
+        .unwrap();
+    assert_eq!(backrefs(&setup, &targets[3]), vec![referrers[0]]);
+    setup
+        .bank
+        .process(&set_link(&referrers[0], target, &targets[0]))
+        .unwrap();
+    setup
+        .bank
+        .process(&set_link(&referrers[1], target, &targets[1]))
+        .unwrap();
+    assert_eq!(backrefs(&setup, &targets[3]), Vec::new());
+    assert_eq!(backrefs(&setup, &targets[0]), vec![referrers[0]]);
+
+    let delete = |object: &Pubkey, extra: &[Pubkey]| {
+        instruction::delete_object(
+            &program_id,
+            &admin,
+            &project,
+            &target_template,
+            &target_storage,
+            object,
+            extra,
+        )
+    };
+    let set_rule = |on_delete: OnDelete| {
+        let rule = LinkRule {
+            field_id: target,
+            on_delete,
+        };
+        instruction::set_link_rule(&program_id, &admin, &project, &template, &rule)
+    };
+    let extra = [links, template, storage, referrers[0], referrers[1]];
+
+    // Restrict is the rule of link fields without one
+    setup
+        .bank
+        .process_err(&delete(&targets[0], &extra), ObjectError::Referenced.into());
+
+    // Nullify drops the field from the referrer, which stays in its storage
+    setup.bank.process(&set_rule(OnDelete::Nullify)).unwrap();
+    setup.bank.process(&delete(&targets[0], &extra)).unwrap();
+    let nullified: Object = setup.bank.record(&referrers[0], RecordKind::Object);
+    assert_eq!(nullified.data.field_offsets, Vec::new());
+    assert_eq!(backrefs(&setup, &targets[0]), Vec::new());
+    assert_eq!(setup.storage_accounts(&storage), referrers);
+
+    // Cascade deletes the referrer with its backreference
+    setup.bank.process(&set_rule(OnDelete::Cascade)).unwrap();
+    setup.bank.process(&delete(&targets[1], &extra)).unwrap();
+    assert_eq!(setup.bank.accounts[&referrers[1]].lamports, 0);
+    assert_eq!(setup.storage_accounts(&storage), vec![referrers[0]]);
+    let index: FieldIndex = setup.bank.record(&links, RecordKind::Index);
+    assert_eq!(index.entries, Vec::new());
+
+    // Deletes wait for backreference indexes that are still being built
+    let building = setup.bank.alloc(SPACE);
+    let create = instruction::create_index(
+        &program_id,
+        &admin,
+        &project,
+        &template,
+        &building,
+        spare,
+        &[target_template],
+    );
+    setup.bank.process(&create).unwrap();
+    setup.bank.process_err(
+        &delete(&targets[2], &[links, building, template]),
+        ObjectError::IndexNotBuilt.into(),
+    );
+}
+
+#[test]
+fn storage_add_remove() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let first = setup.create_object(&template, &storage);
+    let second = setup.create_object(&template, &storage);
+    assert_eq!(setup.storage_accounts(&storage), vec![first, second]);
+
+    let remove = instruction::remove_from_storage(&program_id, &admin, &project, &storage, &first);
+    setup.bank.process(&remove).unwrap();
+    assert_eq!(setup.storage_accounts(&storage), vec![second]);
+    setup
+        .bank
+        .process_err(&remove, StorageError::NotInStorage.into());
+
+    let add = instruction::add_to_storage(&program_id, &admin, &project, &storage, &first);
+    setup.bank.process(&add).unwrap();
+    assert_eq!(setup.storage_accounts(&storage), vec![second, first]);
+    setup
+        .bank
+        .process_err(&add, StorageError::AlreadyInStorage.into());
+
+    // Storages only take objects of their template
+    let add_template =
+        instruction::add_to_storage(&program_id, &admin, &project, &storage, &template);
+    setup
+        .bank
+        .process_err(&add_template, StorageError::TemplateMismatch.into());
+
+    let sort = instruction::set_sorted(&program_id, &admin, &project, &storage, true);
+    setup.bank.process(&sort).unwrap();
+    let mut sorted = vec![first, second];
+    sorted.sort();
+    assert_eq!(setup.storage_accounts(&storage), sorted);
+}
+
+#[test]
+fn paged_storage_kinds() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let object = setup.create_object(&template, &storage);
+    let (paged, page) = (setup.bank.alloc(SPACE), setup.bank.alloc(SPACE));
+    let create =
+        instruction::create_paged_storage(&program_id, &admin, &project, &paged, &template, 16);
+    setup.bank.process(&create).unwrap();
+    let add = instruction::add_to_page(&program_id, &admin, &project, &paged, &page, &object);
+    setup.bank.process(&add).unwrap();
+    let head: PagedStorage = setup.bank.record(&paged, RecordKind::PagedStorage);
+    assert_eq!((head.len, head.pages, head.last_page), (1, 1, Some(page)));
+    let page_data: StoragePage = setup.bank.record(&page, RecordKind::StoragePage);
+    assert_eq!(page_data.accounts, vec![object]);
+
+    // Flat storage instructions reject heads and pages, paged ones reject flat storages
+    for account in [paged, page].iter() {
+        let add = instruction::add_to_storage(&program_id, &admin, &project, account, &object);
+        setup.bank.process_err(&add, CrudError::InvalidKind.into());
+    }
+    let other = setup.create_object(&template, &storage);
+    let add = instruction::add_to_page(&program_id, &admin, &project, &storage, &page, &other);
+    setup.bank.process_err(&add, CrudError::InvalidKind.into());
+}
+
+#[test]
+fn paged_storage_duplicates() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let template = setup.template;
+    let (paged, first_page, second_page) = (
+        setup.bank.alloc(SPACE),
+        setup.bank.alloc(SPACE),
+        setup.bank.alloc(SPACE),
+    );
+    let attach =
+        instruction::attach_paged_storage(&program_id, &admin, &project, &template, &paged, 16);
+    setup.bank.process(&attach).unwrap();
+    assert!(setup.template_data(&template).storages.contains(&paged));
+
+    // The page goes right after the object, whatever else the template needs
+    let object = setup.bank.alloc(SPACE);
+    let create = |object: &Pubkey, page: Option<&Pubkey>| {
+        instruction::create_object(
+            &program_id,
+            &admin,
+            &project,
+            &template,
+            &paged,
+            object,
+            page,
+            &[],
+        )
+    };
+    setup
+        .bank
+        .process(&create(&object, Some(&first_page)))
+        .unwrap();
+    let page_data: StoragePage = setup.bank.record(&first_page, RecordKind::StoragePage);
+    assert_eq!(page_data.accounts, vec![object]);
+    let mut bad_flag = create(&setup.bank.alloc(SPACE), Some(&first_page));
+    bad_flag.data[2] = 2;
+    setup
+        .bank
+        .process_err(&bad_flag, ProgramError::InvalidInstructionData);
+    let flat = setup.bank.alloc(SPACE);
+    setup
+        .bank
+        .process_err(&create(&flat, None), CrudError::InvalidKind.into());
+
+    // The page of the key is the only place it can be, further pages come from splits
+    let add = |page: &Pubkey| {
+        instruction::add_to_page(&program_id, &admin, &project, &paged, page, &object)
+    };
+    setup
+        .bank
+        .process_err(&add(&first_page), StorageError::AlreadyInStorage.into());
+    setup
+        .bank
+        .process_err(&add(&second_page), ProgramError::UninitializedAccount);
+}
+
+#[test]
+fn paged_storage_add_remove() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let paged = setup.bank.alloc(SPACE);
+    let create = |capacity: u32| {
+        instruction::create_paged_storage(
+            &program_id,
+            &admin,
+            &project,
+            &paged,
+            &template,
+            capacity,
+        )
+    };
+    setup
+        .bank
+        .process_err(&create(1), ProgramError::InvalidArgument);
+    setup.bank.process(&create(2)).unwrap();
+    let head: PagedStorage = setup.bank.record(&paged, RecordKind::PagedStorage);
+    assert_eq!((head.template, head.page_capacity), (template, 2));
+
+    // The first page takes every key up to the capacity
+    let mut objects: Vec<Pubkey> = (0..3)
+        .map(|_| setup.create_object(&template, &storage))
+        .collect();
+    let (first_page, second_page) = (setup.bank.alloc(SPACE), setup.bank.alloc(SPACE));
+    let add = |page: &Pubkey, account: &Pubkey| {
+        instruction::add_to_page(&program_id, &admin, &project, &paged, page, account)
+    };
+    setup.bank.process(&add(&first_page, &objects[0])).unwrap();
+    setup.bank.process(&add(&first_page, &objects[1])).unwrap();
+    setup.bank.process_err(
+        &add(&first_page, &objects[2]),
+        ProgramError::AccountDataTooSmall,
+    );
+
+    // Splitting moves the lower keys to the new page, which goes before the old one
+    let split = |page: &Pubkey, new_page: &Pubkey| {
+        instruction::split_page(&program_id, &admin, &project, &paged, page, new_page)
+    };
+    setup.bank.process_err(
+        &split(&first_page, &first_page),
+        ProgramError::AccountAlreadyInitialized,
+    );
+    setup
+        .bank
+        .process(&split(&first_page, &second_page))
+        .unwrap();
+    let head: PagedStorage = setup.bank.record(&paged, RecordKind::PagedStorage);
+    assert_eq!(
+        (head.len, head.pages, head.last_page),
+        (2, 2, Some(first_page))
+    );
+    let mut stored = objects[..2].to_vec();
+    stored.sort();
+    let lower: StoragePage = setup.bank.record(&second_page, RecordKind::StoragePage);
+    let upper: StoragePage = setup.bank.record(&first_page, RecordKind::StoragePage);
+    assert_eq!(
+        (lower.prev, lower.start, lower.end, lower.accounts),
+        (None, Pubkey::default(), Some(stored[1]), vec![stored[0]])
+    );
+    assert_eq!(
+        (upper.prev, upper.start, upper.end, upper.accounts),
+        (Some(second_page), stored[1], None, vec![stored[1]])
+    );
+    let third_page = setup.bank.alloc(SPACE);
+    setup.bank.process_err(
+        &split(&second_page, &third_page),
+        ProgramError::InvalidArgument,
+    );
+
+    // Keys only go to the page of their range
+    let (covering, other) = if objects[2] < stored[1] {
+        (second_page, first_page)
+    } else {
+        (first_page, second_page)
+    };
+    setup.bank.process_err(
+        &add(&other, &objects[2]),
+        StorageError::OutOfPageRange.into(),
+    );
+    setup.bank.process(&add(&covering, &objects[2])).unwrap();
+    let head: PagedStorage = setup.bank.record(&paged, RecordKind::PagedStorage);
+    assert_eq!((head.len, head.pages), (3, 2));
+
+    let remove = |page: &Pubkey, account: &Pubkey| {
+        instruction::remove_from_page(&program_id, &admin, &project, &paged, page, account)
+    };
+    setup.bank.process_err(
+        &remove(&other, &objects[2]),
+        StorageError::NotInStorage.into(),
+    );
+    setup.bank.process(&remove(&covering, &objects[2])).unwrap();
+    let page: StoragePage = setup.bank.record(&covering, RecordKind::StoragePage);
+    assert!(!page.accounts.contains(&objects[2]));
+    let head: PagedStorage = setup.bank.record(&paged, RecordKind::PagedStorage);
+    assert_eq!((head.len, head.pages), (2, 2));
+
+    // Walking the pages from the last one gives back every key
+    objects.truncate(2);
+    objects.sort();
+    let mut accounts = paged_accounts(&setup, &paged);
+    accounts.sort();
+    assert_eq!(accounts, objects);
+}
+
+/// Accounts of every page of a paged storage following `prev` from the last page
+fn paged_accounts(setup: &Setup, paged: &Pubkey) -> Vec<Pubkey> {
+    let head: PagedStorage = setup.bank.record(paged, RecordKind::PagedStorage);
+    let mut accounts = Vec::new();
+    let mut next = head.last_page;
+    while let Some(page) = next {
+        let page: StoragePage = setup.bank.record(&page, RecordKind::StoragePage);
+        accounts.extend(page.accounts);
+        next = page.prev;
+    }
+    accounts
+}
+
+#[test]
+fn paged_storage_routing() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let (paged, page) = (setup.bank.alloc(SPACE), setup.bank.alloc(SPACE));
+    let attach =
+        instruction::attach_paged_storage(&program_id, &admin, &project, &template, &paged, 2);
+    setup.bank.process(&attach).unwrap();
+    let first = setup.bank.alloc(SPACE);
+    let create = instruction::create_object(
+        &program_id,
+        &admin,
+        &project,
+        &template,
+        &paged,
+        &first,
+        Some(&page),
+        &[],
+    );
+    setup.bank.process(&create).unwrap();
+
+    // Paged storages are never full, the object goes to a given page of its range
+    let route = |object: &Pubkey, storages: &[Pubkey]| {
+        instruction::create_object_routed(
+            &program_id,
+            &admin,
+            &project,
+            &template,
+            object,
+            storages,
+        )
+    };
+    let second = setup.bank.alloc(SPACE);
+    setup.bank.process(&route(&second, &[paged, page])).unwrap();
+    let page_data: StoragePage = setup.bank.record(&page, RecordKind::StoragePage);
+    assert_eq!(page_data.accounts, vec![first, second]);
+
+    // A full page is passed over for the next storage
+    let third = setup.bank.alloc(SPACE);
+    setup
+        .bank
+        .process(&route(&third, &[paged, page, storage]))
+        .unwrap();
+    assert_eq!(setup.storage_accounts(&storage), vec![third]);
+    let fourth = setup.bank.alloc(SPACE);
+    setup.bank.process_err(
+        &route(&fourth, &[paged, page]),
+        ProgramError::AccountDataTooSmall,
+    );
+
+    // Detaching takes an empty storage
+    let detach = instruction::detach_storage(&program_id, &admin, &project, &template, &paged);
+    setup
+        .bank
+        .process_err(&detach, ProgramError::InvalidArgument);
+    for object in [first, second] {
+        let remove =
+            instruction::remove_from_page(&program_id, &admin, &project, &paged, &page, &object);
+        setup.bank.process(&remove).unwrap();
+    }
+    setup.bank.process(&detach).unwrap();
+    assert!(!setup.template_data(&template).storages.contains(&paged));
+}
+
+#[test]
+fn storage_swap_remove() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let objects: Vec<Pubkey> = (0..3)
+        .map(|_| setup.create_object(&template, &storage))
+        .collect();
+    let swap_remove = |account: &Pubkey| {
+        instruction::swap_remove_from_storage(&program_id, &admin, &project, &storage, account)
+    };
+
+    // The last account takes the place of the removed one
+    setup.bank.process(&swap_remove(&objects[0])).unwrap();
+    assert_eq!(
+        setup.storage_accounts(&storage),
+        vec![objects[2], objects[1]]
+    );
+    setup
+        .bank
+        .process_err(&swap_remove(&objects[0]), StorageError::NotInStorage.into());
+
+    // Sorted storages keep the order
+    let add = instruction::add_to_storage(&program_id, &admin, &project, &storage, &objects[0]);
+    setup.bank.process(&add).unwrap();
+    let sort = instruction::set_sorted(&program_id, &admin, &project, &storage, true);
+    setup.bank.process(&sort).unwrap();
+    let mut sorted = objects.clone();
+    sorted.sort();
+    setup.bank.process(&swap_remove(&sorted[0])).unwrap();
+    assert_eq!(setup.storage_accounts(&storage), sorted[1..].to_vec());
+}
+
+#[test]
+fn storage_order() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let (a, b) = (
+        setup.create_object(&template, &storage),
+        setup.create_object(&template, &storage),
+    );
+    let c = setup.create_object(&template, &storage);
+    let remove = instruction::remove_from_storage(&program_id, &admin, &project, &storage, &c);
+    setup.bank.process(&remove).unwrap();
+
+    let insert = |index: u32| {
+        instruction::insert_into_storage(&program_id, &admin, &project, &storage, &c, index)
+    };
+    setup
+        .bank
+        .process_err(&insert(3), ProgramError::InvalidArgument);
+    setup.bank.process(&insert(0)).unwrap();
+    assert_eq!(setup.storage_accounts(&storage), vec![c, a, b]);
+    setup
+        .bank
+        .process_err(&insert(1), StorageError::AlreadyInStorage.into());
+
+    let move_key = |from: u32, to: u32| {
+        instruction::move_in_storage(&program_id, &admin, &project, &storage, from, to)
+    };
+    setup.bank.process(&move_key(0, 2)).unwrap();
+    assert_eq!(setup.storage_accounts(&storage), vec![a, b, c]);
+    setup
+        .bank
+        .process_err(&move_key(1, 3), ProgramError::InvalidArgument);
+
+    let swap = |x: u32, y: u32| {
+        instruction::swap_in_storage(&program_id, &admin, &project, &storage, x, y)
+    };
+    setup.bank.process(&swap(2, 0)).unwrap();
+    assert_eq!(setup.storage_accounts(&storage), vec![c, b, a]);
+    setup
+        .bank
+        .process_err(&swap(0, 3), ProgramError::InvalidArgument);
+
+    // Sorted mode sorts once and rules out explicit positions
+    let set_sorted = |sorted: bool, storage: &Pubkey| {
+        instruction::set_sorted(&program_id, &admin, &project, storage, sorted)
+    };
+    setup.bank.process(&set_sorted(true, &storage)).unwrap();
+    let mut sorted = vec![a, b, c];
+    sorted.sort();
+    assert_eq!(setup.storage_accounts(&storage), sorted);
+    setup
+        .bank
+        .process_err(&move_key(0, 1), StorageError::SortedStorage.into());
+    setup
+        .bank
+        .process_err(&swap(0, 1), StorageError::SortedStorage.into());
+    setup.bank.process(&remove).unwrap();
+    setup
+        .bank
+        .process_err(&insert(0), StorageError::SortedStorage.into());
+    setup.bank.process(&set_sorted(false, &storage)).unwrap();
+    setup.bank.process(&insert(0)).unwrap();
+    assert_eq!(setup.storage_accounts(&storage)[0], c);
+
+    let paged = setup.bank.alloc(SPACE);
+    let create =
+        instruction::create_paged_storage(&program_id, &admin, &project, &paged, &template, 4);
+    setup.bank.process(&create).unwrap();
+    setup
+        .bank
+        .process_err(&set_sorted(true, &paged), CrudError::InvalidKind.into());
+}
+
+#[test]
+fn storage_transfer() {
+    let mut setup = Setup::new();
+    let (program_id, admin, project) = (setup.program_id(), setup.admin, setup.project);
+    let (template, storage) = (setup.template, setup.storage);
+    let object = setup.create_object(&template, &storage);
+    let spare = setup.bank.alloc(SPACE);
+    let attach = instruction::attach_storage(&program_id, &admin, &project, &template, &spare);
+    setup.bank.process(&attach).unwrap();
+    let (_, other_storage) = setup.create_template();
+    let transfer = |from: &Pubkey, to: &Pubkey| {
+        instruction::transfer_between_storages(&program_id, &admin, &project, from, to, &object)
+    };
+
+    // Target storage checks the template, the removal is undone with the rest
+    setup.bank.process_err(
+        &transfer(&storage, &other_storage),
+        StorageError::TemplateMismatch.into(),
+    );
+    setup.bank.process(&transfer(&storage, &spare)).unwrap();
+    assert_eq!(setup.storage_accounts(&storage), Vec::new());
+    assert_eq!(setup.storage_accounts(&spare), vec![object]);
+    setup.bank.process_err(
+        &transfer(&storage, &spare),
+        StorageError::NotInStorage.into(),
+    );
+}
+
+#[test]
+fn crud_write_raw() {
+    let mut setup = Setup::new();
+    let program_id = setup.program_id();
+    let account = setup.bank.alloc(SPACE);
+    let write = instruction::write_raw(&program_id, &setup.admin, &account, 10, &[1, 2, 3]);
+    setup.bank.process(&write).unwrap();
+    assert_eq!(&setup.bank.data(&account)[8..15], &[0, 0, 1, 2, 3, 0, 0]);
+
+    let past_end = instruction::write_raw(
+        &program_id,
+        &setup.admin,
+        &account,
+        SPACE as u64 - 2,
+        &[1, 2, 3],
+    );
+    setup
+        .bank
+        .process_err(&past_end, ProgramError::AccountDataTooSmall);
+}
+
+#[test]
+fn migrate() {
+    let mut setup = Setup::new();
+    let (program_id, admin) = (setup.program_id(), setup.admin);
+    let (template, storage) = (setup.template, setup.storage);
+    let object = setup.create_object(&template, &storage);
+    let current = setup.bank.data(&object).to_vec();
+
+    // Legacy records had no kind in the header and objects had no template version
+    let start = RecordData::WRITABLE_START_INDEX;
+    let legacy = [
+        &[RecordData::LEGACY_VERSION][..],
+        &current[1..RecordData::KIND_INDEX],
+        &current[start..start + 36],
+        &current[start + 40..],
+        &[0; 5],
+    ]
+    .concat();
+    setup
+        .bank
+        .process(&instruction::write_raw(
+            &program_id,
+            &admin,
+            &object,
+            0,
+            &legacy,
+        ))
+        .unwrap();
+    let migrate = instruction::migrate(&program_id, &admin, &object, RecordKind::Object);
+    setup.bank.process(&migrate).unwrap();
+    assert_eq!(setup.bank.data(&object), &current[..]);
+    setup
+        .bank
+        .process_err(&migrate, CrudError::UnsupportedVersion.into());
+}
+
+#[test]
+fn game_without_admin() {
+    let mut setup = Setup::new();
+    let (program_id, owner, project) = (setup.program_id(), setup.owner, setup.project);
+    let game = setup.bank.alloc(SPACE);
+    let create = |signer: &Pubkey| {
+        engine_instruction(
+            &program_id,
+            signer,
+            &[instruction::GAME, 0],
+            2u32.try_to_vec().unwrap(),
+            &[project, game],
+        )
+    };
+    let stranger = Pubkey::new_unique();
+    setup
+        .bank
+        .process_err(&create(&stranger), GameError::AccessDenied.into());
+    setup.bank.process(&create(&owner)).unwrap();
+
+    let join = engine_instruction(
+        &program_id,
+        &stranger,
+        &[instruction::GAME, 1],
+        Vec::new(),
+        &[project, game],
+    );
+    setup.bank.process(&join).unwrap();
+    let game: Game = setup.bank.record(&game, RecordKind::Game);
+    assert_eq!(game.state, GameState::Lobby);
+    assert_eq!(game.players, vec![stranger]);
+}
+
+#[test]
+fn game_missing_tag() {
+    let mut setup = Setup::new();
+    let stranger = Pubkey::new_unique();
+    let program_id = setup.program_id();
+    for tag in [&[][..], &[instruction::GAME]].iter() {
+        let instruction = engine_instruction(&program_id, &stranger, tag, Vec::new(), &[]);
+        setup
+            .bank
+            .process_err(&instruction, ProgramError::InvalidInstructionData);
+    }
+}
+
+#[test]
+fn game_join_leave() {
+    let mut setup = Setup::new();
+    let game = setup.create_game(2);
+    let players = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let stranger = Pubkey::new_unique();
+    let join = |setup: &Setup, player: &Pubkey| setup.game(player, 1, Vec::new(), &[game]);
+    let leave = |setup: &Setup, player: &Pubkey| setup.game(player, 2, Vec::new(), &[game]);
+
+    // Lobby seats players up to the limit, leaving frees the seat
+    setup.bank.process(&join(&setup, &players[0])).unwrap();
+    setup
+        .bank
+        .process_err(&join(&setup, &players[0]), GameError::AlreadySeated.into());
+    setup.bank.process(&join(&setup, &players[1])).unwrap();
+    setup
+        .bank
+        .process_err(&join(&setup, &stranger), GameError::SessionFull.into());
+    setup
+        .bank
+        .process_err(&leave(&setup, &stranger), GameError::NotSeated.into());
+    setup.bank.process(&leave(&setup, &players[0])).unwrap();
+    assert_eq!(setup.session(&game).players, vec![players[1]]);
+    assert_eq!(setup.session(&game).state, GameState::Lobby);
+    setup.bank.process(&join(&setup, &players[0])).unwrap();
+    assert_eq!(setup.session(&game).players, vec![players[1], players[0]]);
+
+    // Started session only lets players leave, the last one finishes it
+    let start = setup.game(&setup.owner, 3, Vec::new(), &[game]);
+    setup.bank.process(&start).unwrap();
+    setup.bank.process(&leave(&setup, &players[0])).unwrap();
+    setup
+        .bank
+        .process_err(&join(&setup, &players[0]), GameError::InvalidState.into());
+    assert_eq!(setup.session(&game).state, GameState::Started);
+    setup.bank.process(&leave(&setup, &players[1])).unwrap();
+    let session = setup.session(&game);
+    assert_eq!(session.state, GameState::Finished);
+    assert_eq!(session.players, Vec::new());
+    assert_eq!(session.current_player(), None);
+    setup
+        .bank
+        .process_err(&join(&setup, &players[1]), GameError::InvalidState.into());
+    setup
+        .bank
+        .process_err(&leave(&setup, &players[1]), GameError::InvalidState.into());
+}
+
+#[test]
+fn game_start() {
+    let mut setup = Setup::new();
+    let game = setup.create_game(2);
+    let player = Pubkey::new_unique();
+    let start = |signer: &Pubkey| setup.game(signer, 3, Vec::new(), &[game]);
+    let (start_owner, start_player) = (start(&setup.owner), start(&player));
+
+    // Session can't start without players
+    setup
+        .bank
+        .process_err(&start_owner, GameError::InvalidState.into());
+    let join = setup.game(&player, 1, Vec::new(), &[game]);
+    setup.bank.process(&join).unwrap();
+    setup
+        .bank
+        .process_err(&start_player, GameError::AccessDenied.into());
+    setup.bank.process(&start_owner).unwrap();
+    let session = setup.session(&game);
+    assert_eq!(session.state, GameState::Started);
+    assert_eq!((session.active_player, session.turn), (0, 1));
+    assert_eq!(session.current_player(), Some(&player));
+    setup
+        .bank
+        .process_err(&start_owner, GameError::InvalidState.into());
+}
+
+#[test]
+fn game_end_turn() {
+    let mut setup = Setup::new();
+    let players = [
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    ];
+    let game = setup.start_game(&players);
+    let end_turn = |setup: &Setup, seat: usize| setup.game(&players[seat], 4, Vec::new(), &[game]);
+    let leave = |setup: &Setup, seat: usize| setup.game(&players[seat], 2, Vec::new(), &[game]);
+    let current = |setup: &Setup| {
+        let session = setup.session(&game);
+        (session.current_player().copied(), session.turn)
+    };
+
+    // Turns go around the seats, only the active player ends them
+    setup
+        .bank
+        .process_err(&end_turn(&setup, 1), GameError::NotActivePlayer.into());
+    for (seat, turn) in [(1, 2), (2, 3), (0, 4), (1, 5)].iter() {
+        setup
+            .bank
+            .process(&end_turn(&setup, (seat + 2) % 3))
+            .unwrap();
+        assert_eq!(current(&setup), (Some(players[*seat]), *turn));
+    }
+
+    // Seat before the active one leaves, the active player keeps the turn
+    setup.bank.process(&leave(&setup, 0)).unwrap();
+    assert_eq!(current(&setup), (Some(players[1]), 5));
+    setup.bank.process(&end_turn(&setup, 1)).unwrap();
+    assert_eq!(current(&setup), (Some(players[2]), 6));
+
+    // Active player in the last seat leaves, the turn goes to the first seat
+    setup.bank.process(&leave(&setup, 2)).unwrap();
+    assert_eq!(current(&setup), (Some(players[1]), 6));
+    setup.bank.process(&end_turn(&setup, 1)).unwrap();
+    assert_eq!(current(&setup), (Some(players[1]), 7));
+    setup
+        .bank
+        .process_err(&end_turn(&setup, 0), GameError::NotActivePlayer.into());
+}
+
+#[test]
+fn game_places_entities() {
+    let mut setup = Setup::new();
+    let (owner, template, storage) = (setup.owner, setup.template, setup.storage);
+    let game = setup.bank.alloc(SPACE);
+    let create = setup.game(&owner, 0, 2u32.try_to_vec().unwrap(), &[game]);
+    setup.bank.process(&create).unwrap();
+    let values = vec!["Deck".to_string(), "Hand".to_string()];
+    setup.add_field(&template, "place", SolceryType::SEnum { values });
+    let place = setup.template_data(&template).max_field_index;
+    let power = setup.template_data(&template).fields[0].id;
+    let set_places = |setup: &Setup, signer: &Pubkey, field_id: u32| {
+        setup.game(signer, 5, field_id.try_to_vec().unwrap(), &[game, template])
+    };
+
+    // Places come from an enum field of a template of the project
+    let stranger = Pubkey::new_unique();
+    setup.bank.process_err(
+        &set_places(&setup, &stranger, place),
+        GameError::AccessDenied.into(),
+    );
+    setup.bank.process_err(
+        &set_places(&setup, &owner, power),
+        ProgramError::InvalidArgument,
+    );
+    setup
+        .bank
+        .process(&set_places(&setup, &owner, place))
+        .unwrap();
+    assert_eq!(setup.session(&game).places, vec!["Deck", "Hand"]);
+
+    let object = setup.create_object(&template, &storage);
+    let add_entity = |setup: &Setup, place: u32| {
+        setup.game(&owner, 6, place.try_to_vec().unwrap(), &[game, object])
+    };
+    setup
+        .bank
+        .process_err(&add_entity(&setup, 2), GameError::InvalidPlace.into());
+    setup.bank.process(&add_entity(&setup, 1)).unwrap();
+    let entity = &setup.session(&game).entities[0];
+    assert_eq!((entity.id, entity.object, entity.place), (0, object, 1));
+
+    // Places are fixed once entities are in them, entities once the session starts
+    setup.bank.process_err(
+        &set_places(&setup, &owner, place),
+        GameError::InvalidState.into(),
+    );
+    let player = Pubkey::new_unique();
+    setup
+        .bank
+        .process(&setup.game(&player, 1, Vec::new(), &[game]))
+        .unwrap();
+    setup
+        .bank
+        .process(&setup.game(&owner, 3, Vec::new(), &[game]))
+        .unwrap();
+    setup
+        .bank
+        .process_err(&add_entity(&setup, 0), GameError::InvalidState.into());
+}
+
+#[test]
+fn game_act() {
+    let mut setup = Setup::new();
+    let players = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let game = setup.start_game(&players);
+    let act = |setup: &Setup, seat: usize, action: &Action| {
+        setup.game(&players[seat], 7, action.try_to_vec().unwrap(), &[game])
+    };
+    let move_to = |place: i32| Action::MoveTo {
+        entity: Value::Const(1),
+        place: Value::Const(place),
+    };
+
+    // Actions without randomness run right away on behalf of the active player
+    setup.bank.process_err(
+        &act(&setup, 1, &move_to(1)),
+        GameError::NotActivePlayer.into(),
+    );
+    setup
+        .bank
+        .process_err(&act(&setup, 0, &move_to(2)), GameError::InvalidPlace.into());
+    setup.bank.process(&act(&setup, 0, &move_to(1))).unwrap();
+    let session = setup.session(&game);
+    assert_eq!(session.entities[1].place, 1);
+    assert_eq!(session.pending, None);
+}
+
+#[test]
+fn game_commit() {
+    let mut setup = Setup::new();
+    let players = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let game = setup.start_game(&players);
+    let hash = random::commitment(&players[0], &[1; 32]).to_bytes();
+    let commit =
+        |setup: &Setup, player: &Pubkey| setup.game(player, 8, hash.try_to_vec().unwrap(), &[game]);
+
+    let stranger = Pubkey::new_unique();
+    setup
+        .bank
+        .process_err(&commit(&setup, &stranger), GameError::NotSeated.into());
+    setup.bank.process(&commit(&setup, &players[0])).unwrap();
+    let commits = setup.session(&game).random.commits;
+    assert_eq!(commits.len(), 1);
+    assert_eq!((commits[0].player, commits[0].hash), (players[0], hash));
+    setup.bank.process_err(
+        &commit(&setup, &players[0]),
+        GameError::AlreadyCommitted.into(),
+    );
+}
+
+#[test]
+fn game_log() {
+    let mut setup = Setup::new();
+    let owner = setup.owner;
+    let game = setup.create_game(2);
+    let one_slot = RecordData::WRITABLE_START_INDEX + LogHeader::SIZE + LogHeader::SLOT_SIZE;
+    let small = setup.bank.alloc_data(vec![0; one_slot - 1]);
+    let log = setup.bank.alloc(SPACE);
+    let create_log = |setup: &Setup, signer: &Pubkey, log: &Pubkey| {
+        setup.game(signer, 10, Vec::new(), &[game, *log])
+    };
+
+    // Log takes as many slots as the account fits, at least one
+    setup.bank.process_err(
+        &create_log(&setup, &Pubkey::new_unique(), &log),
+        GameError::AccessDenied.into(),
+    );
+    setup.bank.process_err(
+        &create_log(&setup, &owner, &small),
+        ProgramError::AccountDataTooSmall,
+    );
+    setup
+        .bank
+        .process(&create_log(&setup, &owner, &log))
+        .unwrap();
+    let header: LogHeader = setup.bank.record(&log, RecordKind::Log);
+    let capacity =
+        (SPACE - RecordData::WRITABLE_START_INDEX - LogHeader::SIZE) / LogHeader::SLOT_SIZE;
+    assert_eq!((header.game, header.sequence), (game, 0));
+    assert_eq!(header.capacity as usize, capacity);
+    assert_eq!(setup.session(&game).log, Some(log));
+    setup.bank.process_err(
+        &create_log(&setup, &owner, &small),
+        GameError::InvalidState.into(),
+    );
+
+    // From now on every change of the session records its events
+    let player = Pubkey::new_unique();
+    setup.bank.process_err(
+        &setup.game(&player, 1, Vec::new(), &[game]),
+        GameError::InvalidLog.into(),
+    );
+    setup
+        .bank
+        .process(&setup.game(&player, 1, Vec::new(), &[game, log]))
+        .unwrap();
+    let header: LogHeader = setup.bank.record(&log, RecordKind::Log);
+    assert_eq!(header.sequence, 1);
+    let start = RecordData::WRITABLE_START_INDEX + header.slot_offset(0);
+    let entry = LogEntry::deserialize(&mut &setup.bank.data(&log)[start..]).unwrap();
+    assert_eq!(entry.event, GameEvent::PlayerJoined { player });
+}
+
+#[test]
+fn game_random_rounds() {
+    let mut setup = Setup::new();
+    let players = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let seeds = [[1; 32], [2; 32]];
+    let game = setup.start_game(&players);
+    let commit = |setup: &Setup, seat: usize| {
+        let hash = random::commitment(&players[seat], &seeds[seat]).to_bytes();
+        setup.game(&players[seat], 8, hash.try_to_vec().unwrap(), &[game])
+    };
+    let reveal = |setup: &Setup, seat: usize| {
+        setup.game(
+            &players[seat],
+            9,
+            seeds[seat].try_to_vec().unwrap(),
+            &[game],
+        )
+    };
+    let roll = Action::SetAttr {
+        entity: Value::Const(0),
+        attr: Value::Const(1),
+        value: Value::Random {
+            from: Box::new(Value::Const(1)),
+            to: Box::new(Value::Const(6)),
+        },
+    };
+    let act = |setup: &Setup, action: &Action| {
+        setup.game(&players[0], 7, action.try_to_vec().unwrap(), &[game])
+    };
+
+    // Seeds can't be revealed before the action is submitted
+    for seat in 0..2 {
+        setup.bank.process(&commit(&setup, seat)).unwrap();
+    }
+    setup
+        .bank
+        .process_err(&reveal(&setup, 0), GameError::InvalidState.into());
+
+    // Random action waits for the reveals, the turn can't go on meanwhile
+    setup.bank.process(&act(&setup, &roll)).unwrap();
+    let session: Game = setup.bank.record(&game, RecordKind::Game);
+    assert_eq!(session.pending, Some(roll.clone()));
+    assert!(session.entities[0].attrs.is_empty());
+    setup
+        .bank
+        .process_err(&act(&setup, &Action::Void), GameError::InvalidState.into());
+    let end_turn = setup.game(&players[0], 4, Vec::new(), &[game]);
+    setup
+        .bank
+        .process_err(&end_turn, GameError::InvalidState.into());
+
+    setup.bank.process(&reveal(&setup, 1)).unwrap();
+    setup.bank.process(&reveal(&setup, 0)).unwrap();
+    let mut expected = Random {
+        seed: Some(hashv(&[&seeds[0], &seeds[1]]).to_bytes()),
+        ..Random::default()
+    };
+    let session: Game = setup.bank.record(&game, RecordKind::Game);
+    assert_eq!(
+        session.attr(0, 1),
+        Ok(expected.next_in_range(1, 6).unwrap())
+    );
+    assert_eq!(session.pending, None);
+    assert_eq!(session.random.commits, Vec::new());
+    assert_eq!(session.random.seed, None);
+
+    // Each random action takes a round of its own
+    let shuffle = Action::Shuffle {
+        place: Value::Const(0),
+    };
+    setup.bank.process(&act(&setup, &shuffle)).unwrap();
+    setup
+        .bank
+        .process_err(&reveal(&setup, 0), GameError::RandomnessNotReady.into());
+    setup
+        .bank
+        .process_err(&end_turn, GameError::InvalidState.into());
+}
+
+#[test]
+fn game_invalid_brick() {
+    let mut setup = Setup::new();
+    let player = Pubkey::new_unique();
+    let game = setup.start_game(&[player]);
+    let mut too_deep = vec![5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    too_deep.extend_from_slice(&[1; 64]);
+    for data in [vec![6], too_deep].iter() {
+        let act = setup.game(&player, 7, data.clone(), &[game]);
+        setup.bank.process_err(&act, GameError::InvalidBrick.into());
+    }
+}
+
+#[test]
+fn error_codes() {
+    // Every module reports codes in its own range, 0x100 times the module tag
+    let codes = [
+        (
+            instruction::TEMPLATE,
+            TemplateError::Published.into(),
+            TemplateError::Draft.into(),
+        ),
+        (
+            instruction::OBJECT,
+            ObjectError::UnknownField.into(),
+            ObjectError::UnindexedLink.into(),
+        ),
+        (
+            instruction::STORAGE,
+            StorageError::AlreadyInStorage.into(),
+            StorageError::SortedStorage.into(),
+        ),
+        (
+            instruction::CRUD,
+            CrudError::OutdatedVersion.into(),
+            CrudError::InvalidKind.into(),
+        ),
+        (
+            instruction::PROJECT,
+            ProjectError::IdOverflow.into(),
+            ProjectError::IdOverflow.into(),
+        ),
+        (
+            instruction::GAME,
+            GameError::AccessDenied.into(),
+            GameError::InvalidBrick.into(),
+        ),
+    ];
+    for (tag, first, last) in codes.iter() {
+        let range = (*tag as u32) << 8..(*tag as u32 + 1) << 8;
+        for error in [first, last].iter() {
+            match error {
+                ProgramError::Custom(code) => assert!(range.contains(code), "{:?}", error),
+                _ => panic!("{:?} is not a custom error", error),
+            }
+        }
+    }
+}
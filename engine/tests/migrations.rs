@@ -0,0 +1,210 @@
+//! Records of the legacy layout come out of `MIGRATIONS` as current records
+
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey},
+    solcery_crud::{self as crud, error::CrudError, RecordData, RecordKind},
+    solcery_engine::migrations::MIGRATIONS,
+    solcery_object::{Object, ObjectData, ObjectFieldData},
+    solcery_project::Project,
+    solcery_storage::AccountStorage,
+    solcery_template::{Field, FieldParams, SolceryType, TemplateData},
+    std::fmt::Debug,
+};
+
+#[derive(BorshSerialize)]
+struct LegacyProject {
+    name: String,
+    owner: Pubkey,
+    template_storage: Pubkey,
+    uniq_id: u32,
+}
+
+#[derive(BorshSerialize)]
+struct LegacyTemplate {
+    id: u32,
+    name: String,
+    code: String,
+    storages: Vec<Pubkey>,
+    max_field_index: u32,
+    fields: Vec<Field>,
+    custom_data: String,
+}
+
+#[derive(BorshSerialize)]
+struct LegacyStorage {
+    template: Pubkey,
+    accounts: Vec<Pubkey>,
+}
+
+#[derive(BorshSerialize)]
+struct LegacyObject {
+    id: u32,
+    template: Pubkey,
+    data: ObjectData,
+}
+
+/// Runs one migration over an account with the legacy header and `record` right after it
+fn migrate<L: BorshSerialize>(
+    kind: RecordKind,
+    project: &Pubkey,
+    record: &L,
+) -> Result<Vec<u8>, ProgramError> {
+    let (key, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut data = [
+        &[RecordData::LEGACY_VERSION][..],
+        project.as_ref(),
+        &record.try_to_vec().unwrap(),
+    ]
+    .concat();
+    data.resize(256, 0);
+    let mut lamports = 0;
+    let account_info = AccountInfo::new(
+        &key,
+        false,
+        true,
+        &mut lamports,
+        &mut data,
+        &owner,
+        false,
+        0,
+    );
+    crud::migrate(&account_info, kind, MIGRATIONS)?;
+    assert_eq!(
+        crud::migrate(&account_info, kind, MIGRATIONS),
+        Err(CrudError::UnsupportedVersion.into())
+    );
+    drop(account_info);
+    Ok(data)
+}
+
+fn check<L, T>(kind: RecordKind, legacy: L, current: T)
+where
+    L: BorshSerialize,
+    T: BorshDeserialize + PartialEq + Debug,
+{
+    let project = Pubkey::new_unique();
+    let data = migrate(kind, &project, &legacy).unwrap();
+    assert_eq!(data[0], RecordData::CURRENT_VERSION);
+    assert_eq!(&data[1..RecordData::KIND_INDEX], project.as_ref());
+    assert_eq!(crud::decode_record::<T>(&data, kind).unwrap(), current);
+}
+
+#[test]
+fn project() {
+    let (owner, template_storage) = (Pubkey::new_unique(), Pubkey::new_unique());
+    check(
+        RecordKind::Project,
+        LegacyProject {
+            name: "Project".to_string(),
+            owner,
+            template_storage,
+            uniq_id: 12,
+        },
+        Project {
+            uniq_id: 12,
+            name: "Project".to_string(),
+            owner,
+            template_storage,
+        },
+    );
+}
+
+#[test]
+fn template() {
+    let storage = Pubkey::new_unique();
+    let fields = vec![Field {
+        id: 11,
+        params: FieldParams {
+            field_type: SolceryType::SInt,
+            name: "Power".to_string(),
+            code: "power".to_string(),
+            construct_client: true,
+            construct_server: false,
+        },
+    }];
+    let mut current = TemplateData::new(3, storage);
+    current.fields = fields.clone();
+    current.max_field_index = 11;
+    current.custom_data = "{}".to_string();
+    check(
+        RecordKind::Template,
+        LegacyTemplate {
+            id: 3,
+            name: current.name.clone(),
+            code: current.code.clone(),
+            storages: vec![storage],
+            max_field_index: 11,
+            fields,
+            custom_data: "{}".to_string(),
+        },
+        current,
+    );
+}
+
+#[test]
+fn storage() {
+    let (template, accounts) = (
+        Pubkey::new_unique(),
+        vec![Pubkey::new_unique(), Pubkey::new_unique()],
+    );
+    check(
+        RecordKind::Storage,
+        LegacyStorage {
+            template,
+            accounts: accounts.clone(),
+        },
+        AccountStorage {
+            template,
+            sorted: false,
+            accounts,
+        },
+    );
+}
+
+#[test]
+fn object() {
+    let template = Pubkey::new_unique();
+    let data = ObjectData {
+        field_offsets: vec![ObjectFieldData {
+            field_id: 11,
+            start_offset: 0,
+            end_offset: 4,
+        }],
+        field_data: vec![7, 0, 0, 0],
+    };
+    check(
+        RecordKind::Object,
+        LegacyObject {
+            id: 5,
+            template,
+            data: data.clone(),
+        },
+        Object {
+            id: 5,
+            template,
+            template_version: 0,
+            data,
+        },
+    );
+}
+
+#[test]
+fn kinds_without_legacy_records() {
+    for kind in [
+        RecordKind::Raw,
+        RecordKind::Index,
+        RecordKind::Game,
+        RecordKind::Log,
+        RecordKind::PagedStorage,
+        RecordKind::StoragePage,
+        RecordKind::TemplateVersion,
+    ]
+    .iter()
+    {
+        assert_eq!(
+            migrate(*kind, &Pubkey::new_unique(), &0u32),
+            Err(CrudError::UnknownMigration.into())
+        );
+    }
+}
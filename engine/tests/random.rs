@@ -0,0 +1,289 @@
+//! Commit-reveal randomness going through the program-test runtime, where transactions are
+//! signed for real and players can only commit and reveal for themselves.
+//!
+//! The project is set up in `solcery_bank::Bank` because it takes an engine admin, whose key
+//! can't sign here, and its accounts are loaded into the program-test bank.
+
+use {
+    borsh::BorshSerialize,
+    solana_program::{hash::hashv, instruction::Instruction, pubkey::Pubkey, rent::Rent},
+    solana_program_test::{processor, ProgramTest, ProgramTestContext},
+    solana_sdk::{
+        account::Account,
+        instruction::InstructionError,
+        signature::{Keypair, Signer},
+        transaction::{Transaction, TransactionError},
+        transport::TransportError,
+    },
+    solcery_bank::Bank,
+    solcery_crud::{decode_record, RecordKind},
+    solcery_engine::{
+        entrypoint::{process_instruction, super_admins},
+        instruction::{self, engine_instruction},
+    },
+    solcery_game::{
+        brick::{Action, Value},
+        error::GameError,
+        random::{self, Random},
+        Game,
+    },
+    solcery_template::{FieldParams, SolceryType, TemplateData},
+};
+
+const SPACE: usize = 1024;
+
+/// Project whose template has a place field and two objects to make entities of
+struct Project {
+    bank: Bank,
+    project: Pubkey,
+    template: Pubkey,
+    place_field: u32,
+    objects: Vec<Pubkey>,
+}
+
+fn create_project(owner: &Pubkey) -> Project {
+    let mut bank = Bank::new(Pubkey::new_unique(), process_instruction);
+    let (program_id, admin) = (bank.program_id, super_admins()[0]);
+    let (project, template_storage) = (bank.alloc(SPACE), bank.alloc(SPACE));
+    let (template, storage) = (bank.alloc(SPACE), bank.alloc(SPACE));
+    bank.process(&instruction::create_project(
+        &program_id,
+        &admin,
+        &project,
+        &template_storage,
+        owner,
+    ))
+    .unwrap();
+    bank.process(&instruction::create_template(
+        &program_id,
+        &admin,
+        &project,
+        &template,
+        &storage,
+        &template_storage,
+    ))
+    .unwrap();
+    let params = FieldParams {
+        field_type: SolceryType::SEnum {
+            values: vec!["Deck".to_string(), "Hand".to_string()],
+        },
+        name: "place".to_string(),
+        code: "place".to_string(),
+        construct_client: false,
+        construct_server: false,
+    };
+    bank.process(&instruction::add_field(
+        &program_id,
+        &admin,
+        &project,
+        &template,
+        &params,
+    ))
+    .unwrap();
+    let place_field = bank
+        .record::<TemplateData>(&template, RecordKind::Template)
+        .fields
+        .last()
+        .unwrap()
+        .id;
+    let objects = (0..2)
+        .map(|_| {
+            let object = bank.alloc(SPACE);
+            bank.process(&instruction::create_object(
+                &program_id,
+                &admin,
+                &project,
+                &template,
+                &storage,
+                &object,
+                None,
+                &[],
+            ))
+            .unwrap();
+            object
+        })
+        .collect();
+    Project {
+        bank,
+        project,
+        template,
+        place_field,
+        objects,
+    }
+}
+
+fn account(program_id: &Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: *program_id,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// Game instruction with the project in front of `accounts`
+fn game(
+    setup: &Project,
+    signer: &Pubkey,
+    tag: u8,
+    data: Vec<u8>,
+    accounts: &[Pubkey],
+) -> Instruction {
+    let accounts = [&[setup.project], accounts].concat();
+    engine_instruction(
+        &setup.bank.program_id,
+        signer,
+        &[instruction::GAME, tag],
+        data,
+        &accounts,
+    )
+}
+
+async fn send(
+    context: &mut ProgramTestContext,
+    signer: &Keypair,
+    instruction: Instruction,
+) -> Result<(), TransportError> {
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, signer],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await
+}
+
+fn custom(error: TransportError) -> u32 {
+    match error {
+        TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(code),
+        )) => code,
+        error => panic!("unexpected error {:?}", error),
+    }
+}
+
+async fn session(context: &mut ProgramTestContext, game: &Pubkey) -> Game {
+    let account = context.banks_client.get_account(*game).await.unwrap();
+    decode_record(&account.unwrap().data, RecordKind::Game).unwrap()
+}
+
+#[tokio::test]
+async fn random_roll() {
+    let owner = Keypair::new();
+    let players = [Keypair::new(), Keypair::new()];
+    let seeds = [[1; 32], [2; 32]];
+    let setup = create_project(&owner.pubkey());
+    let program_id = setup.bank.program_id;
+    let mut program_test = ProgramTest::new(
+        "solcery_engine",
+        program_id,
+        processor!(process_instruction),
+    );
+    for (key, data) in setup.bank.dump() {
+        program_test.add_account(key, account(&program_id, data));
+    }
+    let game_key = Pubkey::new_unique();
+    program_test.add_account(game_key, account(&program_id, vec![0; SPACE]));
+    let mut context = program_test.start_with_context().await;
+
+    // Lobby with both objects in the Deck, then both players take a seat
+    let lobby = [
+        game(
+            &setup,
+            &owner.pubkey(),
+            0,
+            4u32.try_to_vec().unwrap(),
+            &[game_key],
+        ),
+        game(
+            &setup,
+            &owner.pubkey(),
+            5,
+            setup.place_field.try_to_vec().unwrap(),
+            &[game_key, setup.template],
+        ),
+    ];
+    for instruction in lobby {
+        send(&mut context, &owner, instruction).await.unwrap();
+    }
+    for object in &setup.objects {
+        let add_entity = game(
+            &setup,
+            &owner.pubkey(),
+            6,
+            0u32.try_to_vec().unwrap(),
+            &[game_key, *object],
+        );
+        send(&mut context, &owner, add_entity).await.unwrap();
+    }
+    for player in &players {
+        let join = game(&setup, &player.pubkey(), 1, Vec::new(), &[game_key]);
+        send(&mut context, player, join).await.unwrap();
+    }
+    let start = game(&setup, &owner.pubkey(), 3, Vec::new(), &[game_key]);
+    send(&mut context, &owner, start).await.unwrap();
+
+    for (player, seed) in players.iter().zip(&seeds) {
+        let hash = random::commitment(&player.pubkey(), seed).to_bytes();
+        let commit = game(
+            &setup,
+            &player.pubkey(),
+            8,
+            hash.try_to_vec().unwrap(),
+            &[game_key],
+        );
+        send(&mut context, player, commit).await.unwrap();
+    }
+    let roll = Action::SetAttr {
+        entity: Value::Const(0),
+        attr: Value::Const(1),
+        value: Value::Random {
+            from: Box::new(Value::Const(1)),
+            to: Box::new(Value::Const(6)),
+        },
+    };
+    let act = game(
+        &setup,
+        &players[0].pubkey(),
+        7,
+        roll.try_to_vec().unwrap(),
+        &[game_key],
+    );
+    send(&mut context, &players[0], act).await.unwrap();
+
+    // A seed that doesn't match the commitment is refused
+    let forged = game(
+        &setup,
+        &players[1].pubkey(),
+        9,
+        [3u8; 32].try_to_vec().unwrap(),
+        &[game_key],
+    );
+    let error = send(&mut context, &players[1], forged).await.unwrap_err();
+    assert_eq!(custom(error), GameError::InvalidReveal as u32);
+
+    for (player, seed) in players.iter().zip(&seeds).rev() {
+        let reveal = game(
+            &setup,
+            &player.pubkey(),
+            9,
+            seed.try_to_vec().unwrap(),
+            &[game_key],
+        );
+        send(&mut context, player, reveal).await.unwrap();
+    }
+    let mut expected = Random {
+        seed: Some(hashv(&[&seeds[0], &seeds[1]]).to_bytes()),
+        ..Random::default()
+    };
+    let session = session(&mut context, &game_key).await;
+    assert_eq!(
+        session.attr(0, 1),
+        Ok(expected.next_in_range(1, 6).unwrap())
+    );
+    assert_eq!(session.pending, None);
+    assert_eq!(session.random.commits, Vec::new());
+    assert_eq!(session.random.seed, None);
+}
@@ -0,0 +1,242 @@
+//! Every record type goes through `write_record` and comes back unchanged from `read_record`
+
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey},
+    solcery_crud::{self as crud, RecordKind},
+    solcery_game::{
+        brick::{Action, Value},
+        log::LogHeader,
+        place::{Entity, EntityAttr},
+        random::Random,
+        Game, GameState,
+    },
+    solcery_object::{
+        index::{FieldIndex, IndexEntry},
+        Object, ObjectData, ObjectFieldData,
+    },
+    solcery_project::Project,
+    solcery_storage::{
+        paged::{PagedStorage, StoragePage},
+        AccountStorage,
+    },
+    solcery_template::{
+        Field, FieldDefault, FieldParams, LinkRule, OnDelete, SolceryNestedType, SolceryType,
+        TemplateData,
+    },
+    std::fmt::Debug,
+};
+
+fn round_trip<T>(kind: RecordKind, record: T)
+where
+    T: BorshSerialize + BorshDeserialize + PartialEq + Debug,
+{
+    let (key, owner, project) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let (mut lamports, mut data) = (0, vec![0; 1024]);
+    let (mut project_lamports, mut project_data) = (0, []);
+    let account_info = AccountInfo::new(
+        &key,
+        false,
+        true,
+        &mut lamports,
+        &mut data,
+        &owner,
+        false,
+        0,
+    );
+    let project_info = AccountInfo::new(
+        &project,
+        false,
+        false,
+        &mut project_lamports,
+        &mut project_data,
+        &owner,
+        false,
+        0,
+    );
+    crud::initialize(&project_info, &account_info, kind).unwrap();
+    crud::write_record(&account_info, kind, &record).unwrap();
+    assert_eq!(crud::read_record::<T>(&account_info, kind).unwrap(), record);
+    assert_eq!(crud::get_project(&account_info).unwrap(), project);
+
+    let other_kind = match kind {
+        RecordKind::Raw => RecordKind::Object,
+        _ => RecordKind::Raw,
+    };
+    assert_eq!(
+        crud::read_record::<T>(&account_info, other_kind),
+        Err(ProgramError::from(crud::error::CrudError::InvalidKind))
+    );
+}
+
+#[test]
+fn project() {
+    round_trip(
+        RecordKind::Project,
+        Project {
+            uniq_id: 12,
+            name: "Project".to_string(),
+            owner: Pubkey::new_unique(),
+            template_storage: Pubkey::new_unique(),
+        },
+    );
+}
+
+#[test]
+fn template() {
+    let template = TemplateData {
+        id: 3,
+        name: "Creature".to_string(),
+        code: "creature".to_string(),
+        storages: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        max_field_index: 4,
+        fields: vec![
+            Field {
+                id: 3,
+                params: FieldParams {
+                    field_type: SolceryType::SLink {
+                        template: Pubkey::new_unique(),
+                    },
+                    name: "Owner".to_string(),
+                    code: "owner".to_string(),
+                    construct_client: true,
+                    construct_server: false,
+                },
+            },
+            Field {
+                id: 4,
+                params: FieldParams {
+                    field_type: SolceryType::SMap {
+                        key_type: SolceryNestedType::SString,
+                        value_type: SolceryNestedType::SEnum {
+                            values: vec!["Hand".to_string(), "Deck".to_string()],
+                        },
+                    },
+                    name: "Places".to_string(),
+                    code: "places".to_string(),
+                    construct_client: false,
+                    construct_server: true,
+                },
+            },
+        ],
+        custom_data: "{}".to_string(),
+        indexes: vec![Pubkey::new_unique()],
+        backrefs: vec![Pubkey::new_unique()],
+        links: vec![LinkRule {
+            field_id: 3,
+            on_delete: OnDelete::Cascade,
+        }],
+        parent: Some(Pubkey::new_unique()),
+        defaults: vec![FieldDefault {
+            field_id: 4,
+            value: vec![1, 2, 3],
+        }],
+        version: 2,
+        published: true,
+        versions: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+    };
+    round_trip(RecordKind::Template, template.clone());
+    round_trip(RecordKind::TemplateVersion, template);
+}
+
+#[test]
+fn storage() {
+    round_trip(
+        RecordKind::Storage,
+        AccountStorage {
+            template: Pubkey::new_unique(),
+            sorted: true,
+            accounts: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        },
+    );
+    round_trip(
+        RecordKind::PagedStorage,
+        PagedStorage {
+            template: Pubkey::new_unique(),
+            page_capacity: 8,
+            len: 9,
+            pages: 2,
+            last_page: Some(Pubkey::new_unique()),
+        },
+    );
+    round_trip(
+        RecordKind::StoragePage,
+        StoragePage {
+            storage: Pubkey::new_unique(),
+            prev: None,
+            start: Pubkey::new_unique(),
+            end: Some(Pubkey::new_unique()),
+            accounts: vec![Pubkey::new_unique()],
+        },
+    );
+}
+
+#[test]
+fn object() {
+    round_trip(
+        RecordKind::Object,
+        Object {
+            id: 5,
+            template: Pubkey::new_unique(),
+            template_version: 1,
+            data: ObjectData {
+                field_offsets: vec![ObjectFieldData {
+                    field_id: 1,
+                    start_offset: 0,
+                    end_offset: 4,
+                }],
+                field_data: vec![1, 0, 0, 0],
+            },
+        },
+    );
+    round_trip(
+        RecordKind::Index,
+        FieldIndex {
+            template: Pubkey::new_unique(),
+            field_id: 1,
+            entries: vec![IndexEntry {
+                value: vec![1, 0, 0, 0],
+                object: Pubkey::new_unique(),
+            }],
+            cursor: Some(Pubkey::new_unique()),
+        },
+    );
+}
+
+#[test]
+fn game() {
+    round_trip(
+        RecordKind::Game,
+        Game {
+            state: GameState::Started,
+            max_players: 2,
+            players: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            active_player: 1,
+            turn: 3,
+            places: vec!["Deck".to_string(), "Hand".to_string()],
+            entities: vec![Entity {
+                id: 1,
+                object: Pubkey::new_unique(),
+                place: 1,
+                attrs: vec![EntityAttr { id: 2, value: -1 }],
+            }],
+            random: Random::default(),
+            log: Some(Pubkey::new_unique()),
+            pending: Some(Action::Shuffle {
+                place: Value::Const(0),
+            }),
+        },
+    );
+    round_trip(
+        RecordKind::Log,
+        LogHeader {
+            game: Pubkey::new_unique(),
+            sequence: 10,
+            capacity: 4,
+        },
+    );
+}
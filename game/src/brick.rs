@@ -0,0 +1,520 @@
+use {
+    crate::{log::GameEvent, Game},
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::program_error::ProgramError,
+    std::io::{Error, ErrorKind, Write},
+};
+
+// Bricks are recursive, borsh derive can't handle it, so they are (de)serialized by hand
+// in the same format the derive would produce: u8 variant index followed by the fields.
+// Deserialization stops at MAX_DEPTH nested bricks, so a crafted instruction can't exhaust
+// the program stack
+
+pub const MAX_DEPTH: usize = 32;
+
+fn invalid_brick(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Void,
+    Sequence(Vec<Action>),
+    MoveTo {
+        entity: Value,
+        place: Value,
+    },
+    Shuffle {
+        place: Value,
+    },
+    Draw {
+        from: Value,
+        to: Value,
+        amount: Value,
+    },
+    SetAttr {
+        entity: Value,
+        attr: Value,
+        value: Value,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Const(i32),
+    Count {
+        place: Box<Value>,
+    },
+    Random {
+        from: Box<Value>,
+        to: Box<Value>,
+    },
+    Attr {
+        entity: Box<Value>,
+        attr: Box<Value>,
+    },
+}
+
+pub struct Context<'a> {
+    pub game: &'a mut Game,
+    pub events: Vec<GameEvent>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(game: &'a mut Game) -> Self {
+        Context {
+            game,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl Action {
+    /// Actions using the session randomness wait for a commit-reveal round, see `Game::resolve`
+    pub fn uses_random(&self) -> bool {
+        match self {
+            Action::Void => false,
+            Action::Sequence(actions) => actions.iter().any(Action::uses_random),
+            Action::MoveTo { entity, place } => entity.uses_random() || place.uses_random(),
+            Action::Shuffle { .. } => true,
+            Action::Draw { from, to, amount } => {
+                from.uses_random() || to.uses_random() || amount.uses_random()
+            }
+            Action::SetAttr {
+                entity,
+                attr,
+                value,
+            } => entity.uses_random() || attr.uses_random() || value.uses_random(),
+        }
+    }
+
+    pub fn run(&self, ctx: &mut Context) -> Result<(), ProgramError> {
+        match self {
+            Action::Void => Ok(()),
+            Action::Sequence(actions) => {
+                for action in actions {
+                    action.run(ctx)?;
+                }
+                Ok(())
+            }
+            Action::MoveTo { entity, place } => {
+                let entity = entity.eval_u32(ctx)?;
+                let place = place.eval_u32(ctx)?;
+                let from = ctx.game.move_to(entity, place)?;
+                ctx.events.push(GameEvent::EntityMoved {
+                    entity,
+                    from,
+                    to: place,
+                });
+                Ok(())
+            }
+            Action::Shuffle { place } => {
+                let place = place.eval_u32(ctx)?;
+                let seed = ctx.game.random.next_seed()?;
+                ctx.game.shuffle(place, &seed)?;
+                ctx.events.push(GameEvent::PlaceShuffled { place });
+                Ok(())
+            }
+            Action::Draw { from, to, amount } => {
+                let from = from.eval_u32(ctx)?;
+                let to = to.eval_u32(ctx)?;
+                let amount = amount.eval_u32(ctx)?;
+                for entity in ctx.game.draw(from, to, amount)? {
+                    ctx.events.push(GameEvent::EntityMoved { entity, from, to });
+                }
+                Ok(())
+            }
+            Action::SetAttr {
+                entity,
+                attr,
+                value,
+            } => {
+                let entity = entity.eval_u32(ctx)?;
+                let attr = attr.eval_u32(ctx)?;
+                let value = value.eval(ctx)?;
+                ctx.game.set_attr(entity, attr, value)?;
+                ctx.events.push(GameEvent::AttributeChanged {
+                    entity,
+                    attr,
+                    value,
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Value {
+    pub fn uses_random(&self) -> bool {
+        match self {
+            Value::Const(_) => false,
+            Value::Count { place } => place.uses_random(),
+            Value::Random { .. } => true,
+            Value::Attr { entity, attr } => entity.uses_random() || attr.uses_random(),
+        }
+    }
+
+    pub fn eval(&self, ctx: &mut Context) -> Result<i32, ProgramError> {
+        match self {
+            Value::Const(value) => Ok(*value),
+            Value::Count { place } => {
+                let place = place.eval_u32(ctx)?;
+                ctx.game.check_place(place)?;
+                Ok(ctx.game.count(place) as i32)
+            }
+            Value::Random { from, to } => {
+                let from = from.eval(ctx)?;
+                let to = to.eval(ctx)?;
+                ctx.game.random.next_in_range(from, to)
+            }
+            Value::Attr { entity, attr } => {
+                let entity = entity.eval_u32(ctx)?;
+                let attr = attr.eval_u32(ctx)?;
+                ctx.game.attr(entity, attr)
+            }
+        }
+    }
+
+    pub fn eval_u32(&self, ctx: &mut Context) -> Result<u32, ProgramError> {
+        let value = self.eval(ctx)?;
+        if value < 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(value as u32)
+    }
+}
+
+impl BorshSerialize for Action {
+    fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match self {
+            Action::Void => 0u8.serialize(writer),
+            Action::Sequence(actions) => {
+                1u8.serialize(writer)?;
+                actions.serialize(writer)
+            }
+            Action::MoveTo { entity, place } => {
+                2u8.serialize(writer)?;
+                entity.serialize(writer)?;
+                place.serialize(writer)
+            }
+            Action::Shuffle { place } => {
+                3u8.serialize(writer)?;
+                place.serialize(writer)
+            }
+            Action::Draw { from, to, amount } => {
+                4u8.serialize(writer)?;
+                from.serialize(writer)?;
+                to.serialize(writer)?;
+                amount.serialize(writer)
+            }
+            Action::SetAttr {
+                entity,
+                attr,
+                value,
+            } => {
+                5u8.serialize(writer)?;
+                entity.serialize(writer)?;
+                attr.serialize(writer)?;
+                value.serialize(writer)
+            }
+        }
+    }
+}
+
+impl Action {
+    fn deserialize_nested(buf: &mut &[u8], depth: usize) -> std::io::Result<Self> {
+        if depth >= MAX_DEPTH {
+            return Err(invalid_brick("Brick is nested too deep"));
+        }
+        let value = |buf: &mut &[u8]| Value::deserialize_nested(buf, depth + 1);
+        match u8::deserialize(buf)? {
+            0 => Ok(Action::Void),
+            1 => {
+                let len = u32::deserialize(buf)?;
+                let mut actions = Vec::new();
+                for _ in 0..len {
+                    actions.push(Action::deserialize_nested(buf, depth + 1)?);
+                }
+                Ok(Action::Sequence(actions))
+            }
+            2 => Ok(Action::MoveTo {
+                entity: value(buf)?,
+                place: value(buf)?,
+            }),
+            3 => Ok(Action::Shuffle { place: value(buf)? }),
+            4 => Ok(Action::Draw {
+                from: value(buf)?,
+                to: value(buf)?,
+                amount: value(buf)?,
+            }),
+            5 => Ok(Action::SetAttr {
+                entity: value(buf)?,
+                attr: value(buf)?,
+                value: value(buf)?,
+            }),
+            _ => Err(invalid_brick("Unexpected action brick")),
+        }
+    }
+}
+
+impl BorshDeserialize for Action {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        Action::deserialize_nested(buf, 0)
+    }
+}
+
+impl BorshSerialize for Value {
+    fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match self {
+            Value::Const(value) => {
+                0u8.serialize(writer)?;
+                value.serialize(writer)
+            }
+            Value::Count { place } => {
+                1u8.serialize(writer)?;
+                place.serialize(writer)
+            }
+            Value::Random { from, to } => {
+                2u8.serialize(writer)?;
+                from.serialize(writer)?;
+                to.serialize(writer)
+            }
+            Value::Attr { entity, attr } => {
+                3u8.serialize(writer)?;
+                entity.serialize(writer)?;
+                attr.serialize(writer)
+            }
+        }
+    }
+}
+
+impl Value {
+    fn deserialize_nested(buf: &mut &[u8], depth: usize) -> std::io::Result<Self> {
+        if depth >= MAX_DEPTH {
+            return Err(invalid_brick("Brick is nested too deep"));
+        }
+        let value = |buf: &mut &[u8]| Value::deserialize_nested(buf, depth + 1).map(Box::new);
+        match u8::deserialize(buf)? {
+            0 => Ok(Value::Const(i32::deserialize(buf)?)),
+            1 => Ok(Value::Count { place: value(buf)? }),
+            2 => Ok(Value::Random {
+                from: value(buf)?,
+                to: value(buf)?,
+            }),
+            3 => Ok(Value::Attr {
+                entity: value(buf)?,
+                attr: value(buf)?,
+            }),
+            _ => Err(invalid_brick("Unexpected value brick")),
+        }
+    }
+}
+
+impl BorshDeserialize for Value {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        Value::deserialize_nested(buf, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{error::GameError, place::tests::deck},
+        solana_program::hash::hashv,
+    };
+
+    fn place(place: i32) -> Value {
+        Value::Const(place)
+    }
+
+    fn count(place: Value) -> Value {
+        Value::Count {
+            place: Box::new(place),
+        }
+    }
+
+    /// Value brick `depth` bricks deep
+    fn nested(depth: usize) -> Value {
+        (1..depth).fold(Value::Const(0), |value, _| count(value))
+    }
+
+    #[test]
+    fn run() {
+        let mut game = deck(4);
+        let action = Action::Sequence(vec![
+            Action::Draw {
+                from: place(0),
+                to: place(1),
+                amount: Value::Const(2),
+            },
+            Action::MoveTo {
+                entity: Value::Const(0),
+                place: place(2),
+            },
+            Action::SetAttr {
+                entity: Value::Const(1),
+                attr: Value::Const(7),
+                value: count(place(1)),
+            },
+            Action::SetAttr {
+                entity: Value::Const(2),
+                attr: Value::Const(7),
+                value: Value::Attr {
+                    entity: Box::new(Value::Const(1)),
+                    attr: Box::new(Value::Const(7)),
+                },
+            },
+        ]);
+        let mut ctx = Context::new(&mut game);
+        action.run(&mut ctx).unwrap();
+        assert_eq!(
+            ctx.events,
+            vec![
+                GameEvent::EntityMoved {
+                    entity: 3,
+                    from: 0,
+                    to: 1
+                },
+                GameEvent::EntityMoved {
+                    entity: 2,
+                    from: 0,
+                    to: 1
+                },
+                GameEvent::EntityMoved {
+                    entity: 0,
+                    from: 0,
+                    to: 2
+                },
+                GameEvent::AttributeChanged {
+                    entity: 1,
+                    attr: 7,
+                    value: 2
+                },
+                GameEvent::AttributeChanged {
+                    entity: 2,
+                    attr: 7,
+                    value: 2
+                },
+            ]
+        );
+        assert_eq!(game.count(0), 1);
+        assert_eq!(game.count(1), 2);
+        assert_eq!(game.attr(2, 7), Ok(2));
+        assert_eq!(game.attr(3, 7), Ok(0));
+    }
+
+    #[test]
+    fn run_random() {
+        let mut game = deck(4);
+        let shuffle = Action::Shuffle { place: place(0) };
+        let mut ctx = Context::new(&mut game);
+        assert_eq!(
+            shuffle.run(&mut ctx),
+            Err(GameError::RandomnessNotReady.into())
+        );
+
+        game.random.seed = Some(hashv(&[b"seed"]).to_bytes());
+        let roll = Action::SetAttr {
+            entity: Value::Const(0),
+            attr: Value::Const(1),
+            value: Value::Random {
+                from: Box::new(Value::Const(1)),
+                to: Box::new(Value::Const(6)),
+            },
+        };
+        let mut ctx = Context::new(&mut game);
+        Action::Sequence(vec![shuffle.clone(), roll.clone()])
+            .run(&mut ctx)
+            .unwrap();
+        assert_eq!(ctx.events[0], GameEvent::PlaceShuffled { place: 0 });
+        assert!((1..=6).contains(&game.attr(0, 1).unwrap()));
+        assert_eq!(game.random.nonce, 2);
+
+        assert!(shuffle.uses_random());
+        assert!(roll.uses_random());
+        assert!(Action::Sequence(vec![Action::Void, roll]).uses_random());
+        assert!(!Action::MoveTo {
+            entity: count(place(0)),
+            place: place(1)
+        }
+        .uses_random());
+    }
+
+    #[test]
+    fn run_invalid() {
+        let mut game = deck(1);
+        let mut ctx = Context::new(&mut game);
+        let negative = Action::MoveTo {
+            entity: Value::Const(-1),
+            place: place(1),
+        };
+        assert_eq!(negative.run(&mut ctx), Err(ProgramError::InvalidArgument));
+        let unknown = Action::MoveTo {
+            entity: Value::Const(1),
+            place: place(1),
+        };
+        assert_eq!(unknown.run(&mut ctx), Err(GameError::UnknownEntity.into()));
+        let outside = Action::SetAttr {
+            entity: Value::Const(0),
+            attr: Value::Const(0),
+            value: count(place(3)),
+        };
+        assert_eq!(outside.run(&mut ctx), Err(GameError::InvalidPlace.into()));
+    }
+
+    #[test]
+    fn serialization() {
+        let action = Action::Sequence(vec![
+            Action::Void,
+            Action::MoveTo {
+                entity: Value::Attr {
+                    entity: Box::new(Value::Const(0)),
+                    attr: Box::new(Value::Const(1)),
+                },
+                place: place(1),
+            },
+            Action::Shuffle { place: place(0) },
+            Action::Draw {
+                from: place(0),
+                to: place(1),
+                amount: Value::Random {
+                    from: Box::new(Value::Const(1)),
+                    to: Box::new(count(place(0))),
+                },
+            },
+            Action::SetAttr {
+                entity: Value::Const(0),
+                attr: Value::Const(1),
+                value: Value::Const(-5),
+            },
+        ]);
+        let bytes = action.try_to_vec().unwrap();
+        assert_eq!(bytes[..6], [1, 5, 0, 0, 0, 0]);
+        assert_eq!(Action::try_from_slice(&bytes).unwrap(), action);
+        assert!(Action::try_from_slice(&[6]).is_err());
+        assert!(Value::try_from_slice(&[4]).is_err());
+    }
+
+    #[test]
+    fn max_depth() {
+        let set = |value| Action::SetAttr {
+            entity: Value::Const(0),
+            attr: Value::Const(0),
+            value,
+        };
+        let deepest = set(nested(MAX_DEPTH - 1)).try_to_vec().unwrap();
+        assert_eq!(
+            Action::try_from_slice(&deepest).unwrap(),
+            set(nested(MAX_DEPTH - 1))
+        );
+        let too_deep = set(nested(MAX_DEPTH)).try_to_vec().unwrap();
+        assert!(Action::try_from_slice(&too_deep).is_err());
+
+        let sequences = (1..=MAX_DEPTH).fold(Action::Void, |x, _| Action::Sequence(vec![x]));
+        assert!(Action::try_from_slice(&sequences.try_to_vec().unwrap()).is_err());
+
+        // Endless chain of Count bricks is rejected without running out of stack
+        assert!(Value::try_from_slice(&[1; 100_000]).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use {
+    solana_program::{decode_error::DecodeError, program_error::ProgramError},
+    thiserror::Error,
+};
+
+/// Errors that may be returned by game session instructions, codes 0x500..0x5ff
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum GameError {
+    /// Signer is not allowed to manage the session
+    #[error("Signer is not allowed to manage the session")]
+    AccessDenied = 0x500,
+
+    /// Instruction is not allowed in the current session state
+    #[error("Instruction is not allowed in the current session state")]
+    InvalidState,
+
+    /// All seats are taken
+    #[error("All seats are taken")]
+    SessionFull,
+
+    /// Player already has a seat
+    #[error("Player already has a seat")]
+    AlreadySeated,
+
+    /// Player has no seat in the session
+    #[error("Player has no seat in the session")]
+    NotSeated,
+
+    /// Only the active player can act
+    #[error("Only the active player can act")]
+    NotActivePlayer,
+
+    /// Place is not defined for the session
+    #[error("Place is not defined for the session")]
+    InvalidPlace,
+
+    /// Entity does not exist in the session
+    #[error("Entity does not exist in the session")]
+    UnknownEntity,
+
+    /// Player has already committed a seed
+    #[error("Player has already committed a seed")]
+    AlreadyCommitted,
+
+    /// Revealed seed doesn't match the commitment
+    #[error("Revealed seed doesn't match the commitment")]
+    InvalidReveal,
+
+    /// Session seed is not revealed yet
+    #[error("Session seed is not revealed yet")]
+    RandomnessNotReady,
+
+    /// Log account is missing or belongs to another session
+    #[error("Log account is missing or belongs to another session")]
+    InvalidLog,
+
+    /// Brick is malformed or nested too deep
+    #[error("Brick is malformed or nested too deep")]
+    InvalidBrick,
+}
+
+impl From<GameError> for ProgramError {
+    fn from(e: GameError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for GameError {
+    fn type_of() -> &'static str {
+        "Game Error"
+    }
+}
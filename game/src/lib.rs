@@ -0,0 +1,495 @@
+pub mod brick;
+pub mod error;
+pub mod log;
+pub mod place;
+pub mod random;
+
+use solcery_crud as crud;
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    brick::{Action, Context},
+    error::GameError,
+    log::GameEvent,
+    place::Entity,
+    random::Random,
+    solana_program::{
+        account_info::{next_account_info, AccountInfo},
+        entrypoint::ProgramResult,
+        msg,
+        program_error::ProgramError,
+        pubkey::Pubkey,
+    },
+    solcery_template::SolceryType,
+};
+
+#[derive(Clone, Copy, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub enum GameState {
+    Lobby,
+    Started,
+    Finished,
+}
+
+/// Game session, seats are stored in turn order
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct Game {
+    pub state: GameState,
+    pub max_players: u32,
+    pub players: Vec<Pubkey>,
+    pub active_player: u32, // Index in players
+    pub turn: u32,
+    pub places: Vec<String>, // Values of the project enum field
+    pub entities: Vec<Entity>,
+    pub random: Random,
+    pub log: Option<Pubkey>,
+    pub pending: Option<Action>, // Random action of the active player waiting for reveals
+}
+
+impl Game {
+    pub fn seat(&self, player: &Pubkey) -> Option<usize> {
+        self.players.iter().position(|x| x == player)
+    }
+
+    pub fn current_player(&self) -> Option<&Pubkey> {
+        match self.state {
+            GameState::Started => self.players.get(self.active_player as usize),
+            _ => None,
+        }
+    }
+
+    /// Runs the pending action once the round seed is combined and closes the round, so every
+    /// random action uses seeds revealed after it was submitted. An action failing on the
+    /// revealed seed is dropped, the round is spent either way
+    pub fn resolve(&mut self) -> Vec<GameEvent> {
+        if self.random.seed.is_none() {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        if let Some(action) = self.pending.take() {
+            let mut game = self.clone();
+            let mut ctx = Context::new(&mut game);
+            let result = action.run(&mut ctx);
+            let action_events = ctx.events;
+            match result {
+                Ok(()) => {
+                    events = action_events;
+                    *self = game;
+                }
+                Err(err) => msg!("Pending action failed: {:?}", err),
+            }
+        }
+        self.random.reset();
+        events
+    }
+}
+
+pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let (tag, data) = instruction_data
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let accounts_iter = &mut accounts.iter();
+    let signer_info = next_account_info(accounts_iter)?;
+    let project_info = next_account_info(accounts_iter)?;
+    match tag {
+        0 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let max_players = u32::deserialize(&mut &data[..])?;
+            create(signer_info, project_info, game_info, max_players)
+        }
+        1 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let log_info = next_account_info(accounts_iter).ok();
+            join(signer_info, project_info, game_info, log_info)
+        }
+        2 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let log_info = next_account_info(accounts_iter).ok();
+            leave(signer_info, project_info, game_info, log_info)
+        }
+        3 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let log_info = next_account_info(accounts_iter).ok();
+            start(signer_info, project_info, game_info, log_info)
+        }
+        4 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let log_info = next_account_info(accounts_iter).ok();
+            end_turn(signer_info, project_info, game_info, log_info)
+        }
+        5 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let template_info = next_account_info(accounts_iter)?;
+            let field_id = u32::deserialize(&mut &data[..])?;
+            set_places(
+                signer_info,
+                project_info,
+                game_info,
+                template_info,
+                field_id,
+            )
+        }
+        6 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let object_info = next_account_info(accounts_iter)?;
+            let log_info = next_account_info(accounts_iter).ok();
+            let place = u32::deserialize(&mut &data[..])?;
+            add_entity(
+                signer_info,
+                project_info,
+                game_info,
+                object_info,
+                log_info,
+                place,
+            )
+        }
+        7 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let log_info = next_account_info(accounts_iter).ok();
+            let action =
+                Action::deserialize(&mut &data[..]).map_err(|_| GameError::InvalidBrick)?;
+            act(signer_info, project_info, game_info, log_info, action)
+        }
+        8 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let hash = <[u8; 32]>::deserialize(&mut &data[..])?;
+            commit(signer_info, project_info, game_info, hash)
+        }
+        9 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let log_info = next_account_info(accounts_iter).ok();
+            let seed = <[u8; 32]>::deserialize(&mut &data[..])?;
+            reveal(signer_info, project_info, game_info, log_info, seed)
+        }
+        10 => {
+            let game_info = next_account_info(accounts_iter)?;
+            let log_info = next_account_info(accounts_iter)?;
+            create_log(signer_info, project_info, game_info, log_info)
+        }
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+pub fn create(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    max_players: u32,
+) -> ProgramResult {
+    msg!("Game/Create");
+    if !solcery_project::check_access(signer_info, project_info) {
+        return Err(GameError::AccessDenied.into());
+    }
+    if max_players == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let game = Game {
+        state: GameState::Lobby,
+        max_players,
+        players: Vec::new(),
+        active_player: 0,
+        turn: 0,
+        places: Vec::new(),
+        entities: Vec::new(),
+        random: Random::default(),
+        log: None,
+        pending: None,
+    };
+    crud::initialize(project_info, game_info, crud::RecordKind::Game)?;
+    crud::write_record(game_info, crud::RecordKind::Game, &game)
+}
+
+pub fn join(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    log_info: Option<&AccountInfo>,
+) -> ProgramResult {
+    msg!("Game/Join");
+    if !signer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut game = load(project_info, game_info)?;
+    if game.state != GameState::Lobby || game.random.is_revealing() {
+        return Err(GameError::InvalidState.into());
+    }
+    if game.seat(signer_info.key).is_some() {
+        return Err(GameError::AlreadySeated.into());
+    }
+    if game.players.len() >= game.max_players as usize {
+        return Err(GameError::SessionFull.into());
+    }
+    game.players.push(*signer_info.key);
+    let events = vec![GameEvent::PlayerJoined {
+        player: *signer_info.key,
+    }];
+    log::append(game_info, &game, log_info, events)?;
+    save(game_info, &game)
+}
+
+pub fn leave(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    log_info: Option<&AccountInfo>,
+) -> ProgramResult {
+    msg!("Game/Leave");
+    if !signer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut game = load(project_info, game_info)?;
+    if game.state == GameState::Finished {
+        return Err(GameError::InvalidState.into());
+    }
+    let seat = game.seat(signer_info.key).ok_or(GameError::NotSeated)?;
+    if game.current_player() == Some(signer_info.key) && game.pending.take().is_some() {
+        game.random.reset();
+    }
+    game.players.remove(seat);
+    game.random.forget(&game.players, signer_info.key);
+    let mut events = vec![GameEvent::PlayerLeft {
+        player: *signer_info.key,
+    }];
+    events.extend(game.resolve());
+    if game.state == GameState::Started {
+        let active = game.active_player as usize;
+        if seat < active {
+            game.active_player -= 1;
+        } else if seat == active && active >= game.players.len() {
+            game.active_player = 0;
+        }
+        if game.players.is_empty() {
+            game.state = GameState::Finished;
+            events.push(GameEvent::GameFinished);
+        }
+    }
+    log::append(game_info, &game, log_info, events)?;
+    save(game_info, &game)
+}
+
+pub fn start(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    log_info: Option<&AccountInfo>,
+) -> ProgramResult {
+    msg!("Game/Start");
+    if !solcery_project::check_access(signer_info, project_info) {
+        return Err(GameError::AccessDenied.into());
+    }
+    let mut game = load(project_info, game_info)?;
+    if game.state != GameState::Lobby || game.players.is_empty() {
+        return Err(GameError::InvalidState.into());
+    }
+    game.state = GameState::Started;
+    game.active_player = 0;
+    game.turn = 1;
+    log::append(game_info, &game, log_info, vec![GameEvent::GameStarted])?;
+    save(game_info, &game)
+}
+
+pub fn end_turn(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    log_info: Option<&AccountInfo>,
+) -> ProgramResult {
+    msg!("Game/EndTurn");
+    let mut game = load(project_info, game_info)?;
+    check_active_player(signer_info, &game)?;
+    if game.pending.is_some() {
+        return Err(GameError::InvalidState.into());
+    }
+    let events = vec![GameEvent::TurnEnded {
+        player: *signer_info.key,
+        turn: game.turn,
+    }];
+    game.active_player = (game.active_player + 1) % game.players.len() as u32;
+    game.turn += 1;
+    log::append(game_info, &game, log_info, events)?;
+    save(game_info, &game)
+}
+
+/// Takes place definitions from an enum field of a project template
+pub fn set_places(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    template_info: &AccountInfo,
+    field_id: u32,
+) -> ProgramResult {
+    msg!("Game/SetPlaces");
+    if !solcery_project::check_access(signer_info, project_info) {
+        return Err(GameError::AccessDenied.into());
+    }
+    let mut game = load(project_info, game_info)?;
+    if game.state != GameState::Lobby || !game.entities.is_empty() {
+        return Err(GameError::InvalidState.into());
+    }
+    if crud::get_project(template_info)? != *project_info.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let template = solcery_template::load(template_info)?;
+    let field = template
+        .fields
+        .iter()
+        .find(|x| x.id == field_id)
+        .ok_or(ProgramError::InvalidArgument)?;
+    match &field.params.field_type {
+        SolceryType::SEnum { values } => game.places = values.clone(),
+        _ => return Err(ProgramError::InvalidArgument),
+    }
+    save(game_info, &game)
+}
+
+pub fn add_entity(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    object_info: &AccountInfo,
+    log_info: Option<&AccountInfo>,
+    place: u32,
+) -> ProgramResult {
+    msg!("Game/AddEntity");
+    if !solcery_project::check_access(signer_info, project_info) {
+        return Err(GameError::AccessDenied.into());
+    }
+    let mut game = load(project_info, game_info)?;
+    if game.state != GameState::Lobby {
+        return Err(GameError::InvalidState.into());
+    }
+    if crud::get_project(object_info)? != *project_info.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    game.check_place(place)?;
+    let entity = Entity {
+        id: game.entities.len() as u32,
+        object: *object_info.key,
+        place,
+        attrs: Vec::new(),
+    };
+    let events = vec![GameEvent::EntityCreated {
+        entity: entity.id,
+        place,
+    }];
+    game.entities.push(entity);
+    log::append(game_info, &game, log_info, events)?;
+    save(game_info, &game)
+}
+
+/// Runs a brick action on behalf of the active player. Actions using randomness are kept
+/// pending until the players reveal their seeds for it
+pub fn act(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    log_info: Option<&AccountInfo>,
+    action: Action,
+) -> ProgramResult {
+    msg!("Game/Act");
+    let mut game = load(project_info, game_info)?;
+    check_active_player(signer_info, &game)?;
+    if game.pending.is_some() {
+        return Err(GameError::InvalidState.into());
+    }
+    if action.uses_random() {
+        if game.random.is_revealing() {
+            return Err(GameError::InvalidState.into());
+        }
+        game.pending = Some(action);
+        return save(game_info, &game);
+    }
+    let mut ctx = Context::new(&mut game);
+    action.run(&mut ctx)?;
+    let events = ctx.events;
+    log::append(game_info, &game, log_info, events)?;
+    save(game_info, &game)
+}
+
+/// Attaches a log account, after that every state change of the session must pass it
+pub fn create_log(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    log_info: &AccountInfo,
+) -> ProgramResult {
+    msg!("Game/CreateLog");
+    if !solcery_project::check_access(signer_info, project_info) {
+        return Err(GameError::AccessDenied.into());
+    }
+    let mut game = load(project_info, game_info)?;
+    if game.log.is_some() {
+        return Err(GameError::InvalidState.into());
+    }
+    log::create(project_info, game_info, log_info)?;
+    game.log = Some(*log_info.key);
+    save(game_info, &game)
+}
+
+/// Commits a hash of the player's secret seed, see `random::commitment`
+pub fn commit(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    hash: [u8; 32],
+) -> ProgramResult {
+    msg!("Game/Commit");
+    let mut game = load(project_info, game_info)?;
+    check_seated_player(signer_info, &game)?;
+    game.random.commit(signer_info.key, hash)?;
+    save(game_info, &game)
+}
+
+/// Seeds are revealed only for a pending action, the last reveal runs it
+pub fn reveal(
+    signer_info: &AccountInfo,
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    log_info: Option<&AccountInfo>,
+    seed: [u8; 32],
+) -> ProgramResult {
+    msg!("Game/Reveal");
+    let mut game = load(project_info, game_info)?;
+    check_seated_player(signer_info, &game)?;
+    if game.pending.is_none() {
+        return Err(GameError::InvalidState.into());
+    }
+    game.random.reveal(&game.players, signer_info.key, seed)?;
+    let events = game.resolve();
+    log::append(game_info, &game, log_info, events)?;
+    save(game_info, &game)
+}
+
+pub fn check_seated_player(signer_info: &AccountInfo, game: &Game) -> ProgramResult {
+    if !signer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if game.state == GameState::Finished {
+        return Err(GameError::InvalidState.into());
+    }
+    match game.seat(signer_info.key) {
+        Some(_) => Ok(()),
+        None => Err(GameError::NotSeated.into()),
+    }
+}
+
+/// Ensures that the signer is the player whose turn it is, every game action must pass it
+pub fn check_active_player(signer_info: &AccountInfo, game: &Game) -> ProgramResult {
+    if !signer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    match game.current_player() {
+        Some(player) if player == signer_info.key => Ok(()),
+        _ => Err(GameError::NotActivePlayer.into()),
+    }
+}
+
+pub fn load(project_info: &AccountInfo, game_info: &AccountInfo) -> Result<Game, ProgramError> {
+    let game = crud::read_record(game_info, crud::RecordKind::Game)?;
+    if crud::get_project(game_info)? != *project_info.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(game)
+}
+
+pub fn save(game_info: &AccountInfo, game: &Game) -> ProgramResult {
+    crud::write_record(game_info, crud::RecordKind::Game, game)
+}
@@ -0,0 +1,119 @@
+use solcery_crud as crud;
+use {
+    crate::{error::GameError, Game},
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey},
+};
+
+/// Event of a game session, clients replay them to animate state transitions
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub enum GameEvent {
+    PlayerJoined { player: Pubkey },
+    PlayerLeft { player: Pubkey },
+    GameStarted,
+    GameFinished,
+    EntityCreated { entity: u32, place: u32 },
+    EntityMoved { entity: u32, from: u32, to: u32 },
+    AttributeChanged { entity: u32, attr: u32, value: i32 },
+    PlaceShuffled { place: u32 },
+    TurnEnded { player: Pubkey, turn: u32 },
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct LogEntry {
+    pub sequence: u64,
+    pub event: GameEvent,
+}
+
+/// Log account starts with the header followed by `capacity` slots of `SLOT_SIZE` bytes.
+/// Entry with sequence number N lives in slot N % capacity, so the oldest entries get
+/// overwritten and clients detect the gap by sequence numbers
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct LogHeader {
+    pub game: Pubkey,
+    pub sequence: u64, // Sequence number of the next entry
+    pub capacity: u32,
+}
+
+impl LogHeader {
+    pub const SIZE: usize = 44;
+    pub const SLOT_SIZE: usize = 64;
+
+    pub fn slot_offset(&self, sequence: u64) -> usize {
+        Self::SIZE + (sequence % self.capacity as u64) as usize * Self::SLOT_SIZE
+    }
+}
+
+pub fn create(
+    project_info: &AccountInfo,
+    game_info: &AccountInfo,
+    log_info: &AccountInfo,
+) -> Result<(), ProgramError> {
+    let space = log_info.data_len();
+    if space < crud::RecordData::WRITABLE_START_INDEX + LogHeader::SIZE + LogHeader::SLOT_SIZE {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let header = LogHeader {
+        game: *game_info.key,
+        sequence: 0,
+        capacity: ((space - crud::RecordData::WRITABLE_START_INDEX - LogHeader::SIZE)
+            / LogHeader::SLOT_SIZE) as u32,
+    };
+    crud::initialize(project_info, log_info, crud::RecordKind::Log)?;
+    crud::write_record(log_info, crud::RecordKind::Log, &header)
+}
+
+pub fn load_header(log_info: &AccountInfo) -> Result<LogHeader, ProgramError> {
+    crud::read_record(log_info, crud::RecordKind::Log)
+}
+
+/// Reads the entry with the given sequence number if it was not overwritten yet
+pub fn read(log_info: &AccountInfo, sequence: u64) -> Result<Option<LogEntry>, ProgramError> {
+    let header = load_header(log_info)?;
+    if sequence >= header.sequence || header.sequence - sequence > header.capacity as u64 {
+        return Ok(None);
+    }
+    let start = crud::RecordData::WRITABLE_START_INDEX + header.slot_offset(sequence);
+    let slot_data = &log_info.data.borrow()[start..start + LogHeader::SLOT_SIZE];
+    Ok(Some(LogEntry::deserialize(&mut &*slot_data)?))
+}
+
+/// Appends events to the session log. Sessions without a log don't record anything
+pub fn append(
+    game_info: &AccountInfo,
+    game: &Game,
+    log_info: Option<&AccountInfo>,
+    events: Vec<GameEvent>,
+) -> Result<(), ProgramError> {
+    let log_key = match game.log {
+        Some(log_key) => log_key,
+        None => return Ok(()),
+    };
+    let log_info = log_info.ok_or(GameError::InvalidLog)?;
+    if *log_info.key != log_key {
+        return Err(GameError::InvalidLog.into());
+    }
+    let mut header = load_header(log_info)?;
+    if header.game != *game_info.key {
+        return Err(GameError::InvalidLog.into());
+    }
+    for event in events {
+        let entry = LogEntry {
+            sequence: header.sequence,
+            event,
+        };
+        let mut slot = entry.try_to_vec().unwrap();
+        if slot.len() > LogHeader::SLOT_SIZE {
+            return Err(ProgramError::InvalidArgument);
+        }
+        slot.resize(LogHeader::SLOT_SIZE, 0);
+        let offset = crud::RecordData::WRITABLE_START_INDEX + header.slot_offset(header.sequence);
+        crud::write_raw(log_info, offset as u64, slot)?;
+        header.sequence += 1;
+    }
+    crud::write_raw(
+        log_info,
+        crud::RecordData::WRITABLE_START_INDEX as u64,
+        header.try_to_vec().unwrap(),
+    )
+}
@@ -0,0 +1,208 @@
+use {
+    crate::{error::GameError, Game},
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{
+        hash::{hashv, Hash},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+    },
+};
+
+/// Runtime entity of a game session, created from a project object
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct Entity {
+    pub id: u32,
+    pub object: Pubkey,
+    pub place: u32, // Index in Game::places
+    pub attrs: Vec<EntityAttr>,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct EntityAttr {
+    pub id: u32,
+    pub value: i32,
+}
+
+/// Entities of a place are kept in Game::entities order, the last one is on top
+impl Game {
+    pub fn check_place(&self, place: u32) -> Result<(), ProgramError> {
+        if (place as usize) < self.places.len() {
+            Ok(())
+        } else {
+            Err(GameError::InvalidPlace.into())
+        }
+    }
+
+    pub fn entity_index(&self, entity: u32) -> Result<usize, ProgramError> {
+        self.entities
+            .iter()
+            .position(|x| x.id == entity)
+            .ok_or_else(|| GameError::UnknownEntity.into())
+    }
+
+    pub fn count(&self, place: u32) -> u32 {
+        self.entities.iter().filter(|x| x.place == place).count() as u32
+    }
+
+    /// Unset attributes are 0
+    pub fn attr(&self, entity: u32, attr: u32) -> Result<i32, ProgramError> {
+        let entity = &self.entities[self.entity_index(entity)?];
+        Ok(entity
+            .attrs
+            .iter()
+            .find(|x| x.id == attr)
+            .map_or(0, |x| x.value))
+    }
+
+    pub fn set_attr(&mut self, entity: u32, attr: u32, value: i32) -> Result<(), ProgramError> {
+        let index = self.entity_index(entity)?;
+        let attrs = &mut self.entities[index].attrs;
+        match attrs.iter_mut().find(|x| x.id == attr) {
+            Some(entity_attr) => entity_attr.value = value,
+            None => attrs.push(EntityAttr { id: attr, value }),
+        }
+        Ok(())
+    }
+
+    /// Puts entity on top of the place, returns the place it was taken from
+    pub fn move_to(&mut self, entity: u32, place: u32) -> Result<u32, ProgramError> {
+        self.check_place(place)?;
+        let index = self.entity_index(entity)?;
+        let mut entity = self.entities.remove(index);
+        let from = entity.place;
+        entity.place = place;
+        self.entities.push(entity);
+        Ok(from)
+    }
+
+    /// Moves up to `amount` entities from the top of one place to the top of another,
+    /// returns moved entities
+    pub fn draw(&mut self, from: u32, to: u32, amount: u32) -> Result<Vec<u32>, ProgramError> {
+        self.check_place(from)?;
+        self.check_place(to)?;
+        let mut drawn = Vec::new();
+        while drawn.len() < amount as usize {
+            let top = match self.entities.iter().rposition(|x| x.place == from) {
+                Some(index) => self.entities[index].id,
+                None => break,
+            };
+            self.move_to(top, to)?;
+            drawn.push(top);
+        }
+        Ok(drawn)
+    }
+
+    /// Reorders entities of the place, keeping positions of other entities intact
+    pub fn shuffle(&mut self, place: u32, seed: &Hash) -> Result<(), ProgramError> {
+        self.check_place(place)?;
+        let slots: Vec<usize> = (0..self.entities.len())
+            .filter(|i| self.entities[*i].place == place)
+            .collect();
+        for i in (1..slots.len()).rev() {
+            let roll = hashv(&[seed.as_ref(), &(i as u32).to_le_bytes()]).to_bytes();
+            let j = u32::from_le_bytes([roll[0], roll[1], roll[2], roll[3]]) as usize % (i + 1);
+            self.entities.swap(slots[i], slots[j]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        crate::{random::Random, GameState},
+    };
+
+    /// Started session with places Deck, Hand and Discard and `size` entities in the Deck,
+    /// entity N is the N-th from the bottom
+    pub(crate) fn deck(size: u32) -> Game {
+        Game {
+            state: GameState::Started,
+            max_players: 1,
+            players: vec![Pubkey::new_unique()],
+            active_player: 0,
+            turn: 1,
+            places: vec![
+                "Deck".to_string(),
+                "Hand".to_string(),
+                "Discard".to_string(),
+            ],
+            entities: (0..size)
+                .map(|id| Entity {
+                    id,
+                    object: Pubkey::new_unique(),
+                    place: 0,
+                    attrs: Vec::new(),
+                })
+                .collect(),
+            random: Random::default(),
+            log: None,
+            pending: None,
+        }
+    }
+
+    fn ids(game: &Game, place: u32) -> Vec<u32> {
+        let entities = game.entities.iter().filter(|x| x.place == place);
+        entities.map(|x| x.id).collect()
+    }
+
+    #[test]
+    fn move_to() {
+        let mut game = deck(3);
+        assert_eq!(game.move_to(0, 1), Ok(0));
+        assert_eq!(game.move_to(2, 1), Ok(0));
+        assert_eq!(ids(&game, 0), vec![1]);
+        assert_eq!(ids(&game, 1), vec![0, 2]);
+
+        // Moving within the place puts the entity on top
+        assert_eq!(game.move_to(0, 1), Ok(1));
+        assert_eq!(ids(&game, 1), vec![2, 0]);
+
+        assert_eq!(game.move_to(0, 3), Err(GameError::InvalidPlace.into()));
+        assert_eq!(game.move_to(3, 0), Err(GameError::UnknownEntity.into()));
+    }
+
+    #[test]
+    fn draw() {
+        let mut game = deck(3);
+        assert_eq!(game.draw(0, 1, 2), Ok(vec![2, 1]));
+        assert_eq!(ids(&game, 1), vec![2, 1]);
+        assert_eq!(game.draw(0, 1, 5), Ok(vec![0]));
+        assert_eq!(game.draw(0, 1, 1), Ok(Vec::new()));
+        assert_eq!(game.draw(1, 3, 1), Err(GameError::InvalidPlace.into()));
+        assert_eq!(game.draw(3, 1, 1), Err(GameError::InvalidPlace.into()));
+    }
+
+    #[test]
+    fn shuffle() {
+        let mut game = deck(8);
+        game.move_to(3, 1).unwrap();
+        let seed = hashv(&[b"seed"]);
+        let mut shuffled = game.clone();
+        shuffled.shuffle(0, &seed).unwrap();
+
+        // Same entities in another order, the other places keep their entities in place
+        let mut deck_ids = ids(&shuffled, 0);
+        assert_ne!(deck_ids, ids(&game, 0));
+        deck_ids.sort_unstable();
+        assert_eq!(deck_ids, ids(&game, 0));
+        assert_eq!(shuffled.entities[7], game.entities[7]);
+
+        // Same seed, same order
+        let mut again = game.clone();
+        again.shuffle(0, &seed).unwrap();
+        assert_eq!(again, shuffled);
+
+        assert_eq!(game.shuffle(3, &seed), Err(GameError::InvalidPlace.into()));
+    }
+
+    #[test]
+    fn count() {
+        let mut game = deck(3);
+        game.draw(0, 2, 2).unwrap();
+        assert_eq!(game.count(0), 1);
+        assert_eq!(game.count(1), 0);
+        assert_eq!(game.count(2), 2);
+    }
+}
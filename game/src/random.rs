@@ -0,0 +1,248 @@
+use {
+    crate::error::GameError,
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{
+        hash::{hashv, Hash},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+    },
+};
+
+/// Commit-reveal randomness of a session.
+///
+/// Every seated player commits `commitment(player, seed)` for a secret seed and reveals the seed
+/// once everybody has committed. Seeds combined in seat order feed a hash based PRNG, so no
+/// player can choose the result without knowing the others' seeds in advance.
+///
+/// A combined seed serves a single random action submitted before the reveals, then the round
+/// is reset, see `Game::resolve`. Nobody knows the values when choosing what to do with them.
+#[derive(Clone, Debug, Default, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct Random {
+    pub commits: Vec<Commit>,
+    pub seed: Option<[u8; 32]>,
+    pub nonce: u64,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct Commit {
+    pub player: Pubkey,
+    pub hash: [u8; 32],
+    pub seed: Option<[u8; 32]>,
+}
+
+pub fn commitment(player: &Pubkey, seed: &[u8; 32]) -> Hash {
+    hashv(&[player.as_ref(), seed])
+}
+
+impl Random {
+    pub fn is_revealing(&self) -> bool {
+        self.commits.iter().any(|x| x.seed.is_some())
+    }
+
+    pub fn commit(&mut self, player: &Pubkey, hash: [u8; 32]) -> Result<(), ProgramError> {
+        if self.seed.is_some() || self.is_revealing() {
+            return Err(GameError::InvalidState.into());
+        }
+        if self.commits.iter().any(|x| x.player == *player) {
+            return Err(GameError::AlreadyCommitted.into());
+        }
+        self.commits.push(Commit {
+            player: *player,
+            hash,
+            seed: None,
+        });
+        Ok(())
+    }
+
+    /// Reveals can start only when every seated player has committed.
+    /// The last reveal combines the seeds in seat order
+    pub fn reveal(
+        &mut self,
+        players: &[Pubkey],
+        player: &Pubkey,
+        seed: [u8; 32],
+    ) -> Result<(), ProgramError> {
+        if self.seed.is_some() {
+            return Err(GameError::InvalidState.into());
+        }
+        if players
+            .iter()
+            .any(|x| !self.commits.iter().any(|commit| commit.player == *x))
+        {
+            return Err(GameError::RandomnessNotReady.into());
+        }
+        let commit = self
+            .commits
+            .iter_mut()
+            .find(|x| x.player == *player)
+            .ok_or(GameError::NotSeated)?;
+        if commit.seed.is_some() || commitment(player, &seed).to_bytes() != commit.hash {
+            return Err(GameError::InvalidReveal.into());
+        }
+        commit.seed = Some(seed);
+        self.combine(players);
+        Ok(())
+    }
+
+    /// Drops the commit of a player leaving the session, the rest of the seeds may be complete
+    /// after that. Leaving in the middle of reveals is the usual commit-reveal weakness: the
+    /// leaver can only choose between two outcomes, not the outcome itself
+    pub fn forget(&mut self, players: &[Pubkey], player: &Pubkey) {
+        if self.seed.is_none() {
+            self.commits.retain(|x| x.player != *player);
+            self.combine(players);
+        }
+    }
+
+    /// Closes the round, the next random action needs new commits
+    pub fn reset(&mut self) {
+        self.commits.clear();
+        self.seed = None;
+    }
+
+    fn combine(&mut self, players: &[Pubkey]) {
+        if players.is_empty() {
+            return;
+        }
+        let mut seeds: Vec<&[u8]> = Vec::new();
+        for player in players {
+            let commit = self.commits.iter().find(|x| x.player == *player);
+            match commit.and_then(|x| x.seed.as_ref()) {
+                Some(seed) => seeds.push(seed),
+                None => return,
+            }
+        }
+        self.seed = Some(hashv(&seeds).to_bytes());
+    }
+
+    pub fn next_seed(&mut self) -> Result<Hash, ProgramError> {
+        let seed = self.seed.ok_or(GameError::RandomnessNotReady)?;
+        self.nonce += 1;
+        Ok(hashv(&[&seed, &self.nonce.to_le_bytes()]))
+    }
+
+    /// Uniform value in `from..=to`
+    pub fn next_in_range(&mut self, from: i32, to: i32) -> Result<i32, ProgramError> {
+        if from > to {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let roll = self.next_seed()?.to_bytes();
+        let roll = u64::from_le_bytes([
+            roll[0], roll[1], roll[2], roll[3], roll[4], roll[5], roll[6], roll[7],
+        ]);
+        let range = (to as i64 - from as i64 + 1) as u64;
+        Ok((from as i64 + (roll % range) as i64) as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn committed(players: &[Pubkey], seeds: &[[u8; 32]]) -> Random {
+        let mut random = Random::default();
+        for (player, seed) in players.iter().zip(seeds) {
+            let hash = commitment(player, seed).to_bytes();
+            random.commit(player, hash).unwrap();
+        }
+        random
+    }
+
+    #[test]
+    fn commit() {
+        let players = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut random = committed(&players[..1], &[[1; 32]]);
+        assert_eq!(
+            random.commit(&players[0], [0; 32]),
+            Err(GameError::AlreadyCommitted.into())
+        );
+        random.commit(&players[1], [0; 32]).unwrap();
+        assert_eq!(random.commits.len(), 2);
+        assert_eq!(
+            random.commits[0].hash,
+            commitment(&players[0], &[1; 32]).to_bytes()
+        );
+        assert!(!random.is_revealing());
+    }
+
+    #[test]
+    fn reveal() {
+        let players = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let seeds = [[1; 32], [2; 32]];
+        let mut random = committed(&players[..1], &seeds);
+        assert_eq!(
+            random.reveal(&players, &players[0], seeds[0]),
+            Err(GameError::RandomnessNotReady.into())
+        );
+        let hash = commitment(&players[1], &seeds[1]).to_bytes();
+        random.commit(&players[1], hash).unwrap();
+        assert_eq!(
+            random.reveal(&players, &players[0], seeds[1]),
+            Err(GameError::InvalidReveal.into())
+        );
+        random.reveal(&players, &players[0], seeds[0]).unwrap();
+        assert!(random.is_revealing());
+        assert_eq!(random.seed, None);
+        assert_eq!(
+            random.reveal(&players, &players[0], seeds[0]),
+            Err(GameError::InvalidReveal.into())
+        );
+        assert_eq!(
+            random.commit(&Pubkey::new_unique(), [0; 32]),
+            Err(GameError::InvalidState.into())
+        );
+        random.reveal(&players, &players[1], seeds[1]).unwrap();
+        assert!(random.seed.is_some());
+        assert_eq!(
+            random.reveal(&players, &players[1], seeds[1]),
+            Err(GameError::InvalidState.into())
+        );
+    }
+
+    #[test]
+    fn combine() {
+        // Seeds are combined in seat order, not in the order of reveals
+        let players = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let seeds = [[1; 32], [2; 32]];
+        let mut random = committed(&players, &seeds);
+        random.reveal(&players, &players[1], seeds[1]).unwrap();
+        random.reveal(&players, &players[0], seeds[0]).unwrap();
+        let seed = hashv(&[&seeds[0], &seeds[1]]).to_bytes();
+        assert_eq!(random.seed, Some(seed));
+
+        // Seed of a player who left doesn't count
+        let mut random = committed(&players, &seeds);
+        random.reveal(&players, &players[0], seeds[0]).unwrap();
+        random.forget(&players[..1], &players[1]);
+        assert_eq!(random.seed, Some(hashv(&[&seeds[0]]).to_bytes()));
+
+        random.reset();
+        assert_eq!(random, Random::default());
+    }
+
+    #[test]
+    fn next_seed() {
+        let players = [Pubkey::new_unique()];
+        let mut random = committed(&players, &[[1; 32]]);
+        assert_eq!(
+            random.next_seed(),
+            Err(GameError::RandomnessNotReady.into())
+        );
+        random.reveal(&players, &players[0], [1; 32]).unwrap();
+        let seed = random.seed.unwrap();
+        let first = random.next_seed().unwrap();
+        assert_eq!(first, hashv(&[&seed, &1u64.to_le_bytes()]));
+        assert_ne!(random.next_seed().unwrap(), first);
+        assert_eq!(random.nonce, 2);
+
+        for _ in 0..32 {
+            let value = random.next_in_range(-2, 3).unwrap();
+            assert!((-2..=3).contains(&value));
+        }
+        assert_eq!(random.next_in_range(5, 5), Ok(5));
+        assert_eq!(
+            random.next_in_range(1, 0),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+}
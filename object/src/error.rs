@@ -0,0 +1,68 @@
+use {
+    solana_program::{decode_error::DecodeError, program_error::ProgramError},
+    thiserror::Error,
+};
+
+/// Errors that may be returned by object instructions, codes 0x100..0x1ff
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ObjectError {
+    /// Template has no field with the given id
+    #[error("Template has no field with the given id")]
+    UnknownField = 0x100,
+
+    /// Field data is out of bounds of object data
+    #[error("Field data is out of bounds of object data")]
+    InvalidFieldData,
+
+    /// Only int, enum and link fields can be indexed
+    #[error("Only int, enum and link fields can be indexed")]
+    UnindexableField,
+
+    /// Index of the template is missing in the accounts
+    #[error("Index of the template is missing in the accounts")]
+    MissingIndex,
+
+    /// Index belongs to another template
+    #[error("Index belongs to another template")]
+    InvalidIndex,
+
+    /// Template doesn't match the template of the object
+    #[error("Template doesn't match the template of the object")]
+    InvalidTemplate,
+
+    /// Link field doesn't point to an object of the declared template
+    #[error("Link field doesn't point to an object of the declared template")]
+    InvalidLink,
+
+    /// Object is still referenced by a restricting link
+    #[error("Object is still referenced by a restricting link")]
+    Referenced,
+
+    /// Objects passed to an index build step don't match the storages of the template
+    #[error("Objects passed to an index build step don't match the storages of the template")]
+    IncompleteIndex,
+
+    /// Index has every object already
+    #[error("Index has every object already")]
+    IndexBuilt,
+
+    /// Index is still being built and can't be looked up
+    #[error("Index is still being built and can't be looked up")]
+    IndexNotBuilt,
+
+    /// Link fields can only be set when they are indexed
+    #[error("Link fields can only be set when they are indexed")]
+    UnindexedLink,
+}
+
+impl From<ObjectError> for ProgramError {
+    fn from(e: ObjectError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for ObjectError {
+    fn type_of() -> &'static str {
+        "Object Error"
+    }
+}
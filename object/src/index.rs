@@ -0,0 +1,237 @@
+use solcery_crud as crud;
+use {
+    crate::{error::ObjectError, Object},
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{
+        account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+        pubkey::Pubkey,
+    },
+    solcery_template::{SolceryType, TemplateData},
+};
+
+/// Secondary index of a template field: raw field values mapped to objects having them.
+/// Entries are sorted by value and then by object, so lookups are binary searches.
+/// The index is built over key ranges in several steps, objects below `cursor` are indexed
+/// and kept in line by object instructions, the rest waits for the next `build`
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct FieldIndex {
+    pub template: Pubkey,
+    pub field_id: u32,
+    pub entries: Vec<IndexEntry>,
+    pub cursor: Option<Pubkey>, // None once every object is indexed
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IndexEntry {
+    pub value: Vec<u8>,
+    pub object: Pubkey,
+}
+
+impl FieldIndex {
+    pub fn insert(&mut self, value: Vec<u8>, object: Pubkey) {
+        let entry = IndexEntry { value, object };
+        if let Err(index) = self.entries.binary_search(&entry) {
+            self.entries.insert(index, entry);
+        }
+    }
+
+    pub fn remove(&mut self, value: Vec<u8>, object: Pubkey) {
+        let entry = IndexEntry { value, object };
+        if let Ok(index) = self.entries.binary_search(&entry) {
+            self.entries.remove(index);
+        }
+    }
+
+    /// Objects with the given field value
+    pub fn lookup(&self, value: &[u8]) -> Vec<Pubkey> {
+        let start = self.entries.partition_point(|x| x.value.as_slice() < value);
+        self.entries[start..]
+            .iter()
+            .take_while(|x| x.value == value)
+            .map(|x| x.object)
+            .collect()
+    }
+
+    /// Whether the object is indexed already or is left to a later build step
+    pub fn covers(&self, object: &Pubkey) -> bool {
+        !matches!(self.cursor, Some(cursor) if *object >= cursor)
+    }
+}
+
+/// Creates an empty index for the template field, `build` fills it with already existing
+/// objects. Index of a link field is also a backreference index of the linked template,
+/// which has to be passed among `accounts` along with the ancestors
+pub fn create(
+    project_info: &AccountInfo,
+    template_info: &AccountInfo,
+    index_info: &AccountInfo,
+    field_id: u32,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    msg!("Object/CreateIndex");
+    let merged = solcery_template::load_merged(template_info, accounts)?;
+    let field = merged
+        .data
+        .fields
+        .iter()
+        .find(|x| x.id == field_id)
+        .ok_or(ObjectError::UnknownField)?;
+    let link = match field.params.field_type {
+        SolceryType::SInt | SolceryType::SEnum { .. } => None,
+        SolceryType::SLink { template } => Some(template),
+        _ => return Err(ObjectError::UnindexableField.into()),
+    };
+    let index = FieldIndex {
+        template: *template_info.key,
+        field_id,
+        entries: Vec::new(),
+        cursor: Some(Pubkey::default()),
+    };
+    crud::initialize(project_info, index_info, crud::RecordKind::Index)?;
+    save(index_info, &index, crud::Change::Create)?;
+    let mut template = solcery_template::load(template_info)?;
+    template.indexes.push(*index_info.key);
+    match link {
+        Some(linked) if linked == *template_info.key => template.backrefs.push(*index_info.key),
+        Some(linked) => {
+            let linked_info = crate::find(accounts, &linked)?;
+            let mut linked_template = solcery_template::load(linked_info)?;
+            linked_template.backrefs.push(*index_info.key);
+            solcery_template::write_template(linked_info, &linked_template)?;
+        }
+        None => {}
+    }
+    solcery_template::write_template(template_info, &template)
+}
+
+/// Indexes objects of the template with keys from the cursor up to `end`, which is out of
+/// the range, or up to the last key if there is no end. The ancestors, the storages with
+/// their pages and every object of the range have to be passed among `accounts`
+pub fn build(
+    template_info: &AccountInfo,
+    index_info: &AccountInfo,
+    end: Option<Pubkey>,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    msg!("Object/BuildIndex");
+    let mut index = load(index_info)?;
+    if index.template != *template_info.key {
+        return Err(ObjectError::InvalidIndex.into());
+    }
+    let start = index.cursor.ok_or(ObjectError::IndexBuilt)?;
+    if matches!(end, Some(end) if end <= start) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let in_range = |key: &Pubkey| *key >= start && !matches!(end, Some(end) if *key >= end);
+    let merged = solcery_template::load_merged(template_info, accounts)?;
+    let mut stored = stored_objects(&merged.data, accounts)?;
+    stored.retain(in_range);
+    let mut object_infos: Vec<&AccountInfo> = Vec::new();
+    for account_info in accounts {
+        if crud::header_kind(account_info) == crud::RecordKind::Object
+            && in_range(account_info.key)
+            && !object_infos.iter().any(|x| x.key == account_info.key)
+        {
+            object_infos.push(account_info);
+        }
+    }
+    if object_infos.len() != stored.len()
+        || object_infos.iter().any(|x| !stored.contains(x.key))
+    {
+        return Err(ObjectError::IncompleteIndex.into());
+    }
+    for object_info in object_infos {
+        let object = crate::load(object_info)?;
+        if object.template != *template_info.key {
+            return Err(ObjectError::InvalidTemplate.into());
+        }
+        if let Some(value) = field_value(&object, index.field_id) {
+            index.insert(value, *object_info.key);
+        }
+    }
+    index.cursor = end;
+    save(index_info, &index, crud::Change::Update)
+}
+
+/// Accounts in every storage of the template, flat or paged
+fn stored_objects(
+    template: &TemplateData,
+    accounts: &[AccountInfo],
+) -> Result<Vec<Pubkey>, ProgramError> {
+    let mut stored = Vec::new();
+    for storage_key in &template.storages {
+        let storage_info = crate::find(accounts, storage_key)?;
+        match crud::get_kind(storage_info)? {
+            crud::RecordKind::Storage => {
+                for i in 0..solcery_storage::len(storage_info)? {
+                    stored.push(solcery_storage::get(storage_info, i)?);
+                }
+            }
+            crud::RecordKind::PagedStorage => {
+                stored.extend(solcery_storage::paged::accounts(storage_info, accounts)?)
+            }
+            _ => return Err(crud::error::CrudError::InvalidKind.into()),
+        }
+    }
+    Ok(stored)
+}
+
+/// Raw bytes of the field in object data, None if the object has no value for it
+pub fn field_value(object: &Object, field_id: u32) -> Option<Vec<u8>> {
+    let field = object
+        .data
+        .field_offsets
+        .iter()
+        .find(|x| x.field_id == field_id)?;
+    object
+        .data
+        .field_data
+        .get(field.start_offset..field.end_offset)
+        .map(|x| x.to_vec())
+}
+
+/// Brings every index of the template in line with an object change.
+/// `old` is None for created objects and `new` is None for deleted ones
+pub fn sync(
+    template_info: &AccountInfo,
+    template: &TemplateData,
+    object_key: &Pubkey,
+    old: Option<&Object>,
+    new: Option<&Object>,
+    index_infos: &[AccountInfo],
+) -> ProgramResult {
+    for index_key in &template.indexes {
+        let index_info = crate::find(index_infos, index_key)
+            .map_err(|_| ObjectError::MissingIndex)?;
+        let mut index = load(index_info)?;
+        if index.template != *template_info.key {
+            return Err(ObjectError::InvalidIndex.into());
+        }
+        if !index.covers(object_key) {
+            continue; // The build step of its range reads the object as it is then
+        }
+        let old_value = old.and_then(|x| field_value(x, index.field_id));
+        let new_value = new.and_then(|x| field_value(x, index.field_id));
+        if old_value == new_value {
+            continue;
+        }
+        if let Some(value) = old_value {
+            index.remove(value, *object_key);
+        }
+        if let Some(value) = new_value {
+            index.insert(value, *object_key);
+        }
+        save(index_info, &index, crud::Change::Update)?;
+    }
+    Ok(())
+}
+
+pub fn load(index_info: &AccountInfo) -> Result<FieldIndex, ProgramError> {
+    crud::read_record(index_info, crud::RecordKind::Index)
+}
+
+fn save(index_info: &AccountInfo, index: &FieldIndex, change: crud::Change) -> ProgramResult {
+    crud::write_record(index_info, crud::RecordKind::Index, index)?;
+    crud::emit(crud::RecordKind::Index, index_info, change);
+    Ok(())
+}
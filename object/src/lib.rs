@@ -2,22 +2,28 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
-    msg,
     program_error::ProgramError,
     pubkey::Pubkey,
 };
 use solcery_crud as crud;
+use solcery_template::TemplateData;
+
+pub mod error;
+pub mod index;
+pub mod link;
 
 /// Struct wrapping data and providing metadata
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
 pub struct Object {
     pub id: u32,
     pub template: Pubkey,
+    pub template_version: u32, // Version of the template the data conforms to
     pub data: ObjectData,
 }
 
-const OBJECT_METADATA_SIZE: usize = 36;
-const CRUD_METADATA_SIZE: usize = 33;
+const OBJECT_METADATA_SIZE: usize = 40;
+const TEMPLATE_VERSION_OFFSET: usize = 36;
+const CRUD_METADATA_SIZE: usize = crud::RecordData::WRITABLE_START_INDEX;
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
 pub struct ObjectData {
@@ -37,56 +43,259 @@ pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) ->
     let accounts_iter = &mut accounts.iter();
     let signer_info = next_account_info(accounts_iter)?;
     let project_info = next_account_info(accounts_iter)?;
-    match (tag) {
+    match tag {
     	0 => {
             let template_info = next_account_info(accounts_iter)?;
     		let storage_info = next_account_info(accounts_iter)?;
     		let object_info = next_account_info(accounts_iter)?;
-    		create(project_info, template_info, storage_info, object_info)
+            // Flag byte tells whether the page of a paged storage follows the object
+            let page_info = match data.first() {
+                None | Some(0) => None,
+                Some(1) => Some(next_account_info(accounts_iter)?),
+                _ => return Err(ProgramError::InvalidInstructionData),
+            };
+            let extra_infos = accounts_iter.as_slice(); // Ancestors and indexes
+    		create(project_info, template_info, storage_info, object_info, page_info, extra_infos)
     	}
     	1 => {
     		let object_info = next_account_info(accounts_iter)?;
-    		update(object_info, data.to_vec())
+            let template_info = next_account_info(accounts_iter)?;
+            let index_infos = accounts_iter.as_slice(); // Indexes and linked objects
+            with_indexes(object_info, template_info, index_infos, || {
+                update(object_info, data.to_vec())
+            })
     	}
         2 => {
             let object_info = next_account_info(accounts_iter)?;
             let src_info = next_account_info(accounts_iter)?;
-            load_data_from_src(object_info, src_info, 0, 0)
+            let template_info = next_account_info(accounts_iter)?;
+            let index_infos = accounts_iter.as_slice();
+            with_indexes(object_info, template_info, index_infos, || {
+                load_data_from_src(object_info, src_info, 0, 0)
+            })
         }
         3 => {
             let object_info = next_account_info(accounts_iter)?;
             let src_info = next_account_info(accounts_iter)?;
-            clone(object_info, src_info)
+            let template_info = next_account_info(accounts_iter)?;
+            let index_infos = accounts_iter.as_slice();
+            with_indexes(object_info, template_info, index_infos, || {
+                clone(object_info, src_info)
+            })
+        }
+        4 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let object_info = next_account_info(accounts_iter)?;
+            // Candidate storages and pages, then as for create
+            let storage_infos = accounts_iter.as_slice();
+            create_routed(project_info, template_info, object_info, storage_infos)
+        }
+        5 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let storage_info = next_account_info(accounts_iter)?;
+            let object_info = next_account_info(accounts_iter)?;
+            let index_infos = accounts_iter.as_slice(); // Indexes and linking objects
+            delete(signer_info, template_info, storage_info, object_info, index_infos)
+        }
+        6 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let index_info = next_account_info(accounts_iter)?;
+            let extra_infos = accounts_iter.as_slice(); // Ancestors, linked template
+            let field_id = u32::deserialize(&mut &data[..])?;
+            index::create(project_info, template_info, index_info, field_id, extra_infos)
+        }
+        7 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let storage_info = next_account_info(accounts_iter)?;
+            let objects = Vec::<ObjectData>::deserialize(&mut &data[..])?;
+            let (object_infos, extra_infos) = match accounts_iter.as_slice() {
+                rest if rest.len() >= objects.len() => rest.split_at(objects.len()),
+                _ => return Err(ProgramError::NotEnoughAccountKeys),
+            }; // Extra accounts are ancestors, indexes and linked objects
+            create_batch(
+                project_info,
+                template_info,
+                storage_info,
+                object_infos,
+                objects,
+                extra_infos,
+            )
         }
-        _ => return Err(ProgramError::InvalidAccountData)
+        8 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let index_info = next_account_info(accounts_iter)?;
+            let object_infos = accounts_iter.as_slice(); // Storages, their objects in the range
+            let end = Option::<Pubkey>::deserialize(&mut &data[..])?;
+            index::build(template_info, index_info, end, object_infos)
+        }
+        _ => Err(ProgramError::InvalidAccountData),
     }
 }
 
-pub fn create(
+pub fn create<'a>(
     project_info: &AccountInfo,
     template_info: &AccountInfo,
     storage_info: &AccountInfo,
     object_info: &AccountInfo,
+    page_info: Option<&AccountInfo<'a>>,
+    accounts: &[AccountInfo<'a>],
 ) -> ProgramResult {
-    crud::initialize(&project_info, &object_info)?;
+    let template = solcery_template::load_merged(template_info, accounts)?;
+    if !template.data.storages.contains(storage_info.key) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    crud::initialize(project_info, object_info, crud::RecordKind::Object)?;
+    let data = ObjectData {
+        field_offsets: Vec::new(),
+        field_data: Vec::new(),
+    };
+    let data = with_defaults(&template.data, data);
     let object_data = Object {
-        id: solcery_project::get_uniq_id(project_info),
+        id: solcery_project::get_uniq_id(project_info)?,
         template: *template_info.key,
-        data: ObjectData {
-            field_offsets: Vec::new(),
-            field_data: Vec::new(),
-        },
+        template_version: template.data.version,
+        data,
     };
-    crud::write(object_info, 0, object_data.try_to_vec().unwrap());
-    solcery_storage::add(storage_info, object_info)?;
+    // Objects linked by defaults may be gone since the defaults were set
+    link::validate(&template.data, None, &object_data, accounts)?;
+    crud::write_record(object_info, crud::RecordKind::Object, &object_data)?;
+    crud::emit(crud::RecordKind::Object, object_info, crud::Change::Create);
+    index::sync(
+        template_info,
+        &template.data,
+        object_info.key,
+        None,
+        Some(&object_data),
+        accounts,
+    )?;
+    match page_info {
+        Some(page_info) => {
+            solcery_storage::paged::add(project_info, storage_info, page_info, object_info)?
+        }
+        None => solcery_storage::add(storage_info, object_info)?,
+    }
     Ok(())
 }
 
+/// Appends template defaults for the fields the data has no value for
+fn with_defaults(template: &TemplateData, mut data: ObjectData) -> ObjectData {
+    for default in &template.defaults {
+        if data.field_offsets.iter().any(|x| x.field_id == default.field_id) {
+            continue;
+        }
+        data.field_offsets.push(ObjectFieldData {
+            field_id: default.field_id,
+            start_offset: data.field_data.len(),
+            end_offset: data.field_data.len() + default.value.len(),
+        });
+        data.field_data.extend_from_slice(&default.value);
+    }
+    data
+}
+
+/// Creates objects with the given data in pre-allocated accounts, ids are reserved at once
+/// and the objects are appended to the storage in one pass
+pub fn create_batch(
+    project_info: &AccountInfo,
+    template_info: &AccountInfo,
+    storage_info: &AccountInfo,
+    object_infos: &[AccountInfo],
+    objects: Vec<ObjectData>,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let template = solcery_template::load_merged(template_info, accounts)?.data;
+    if !template.storages.contains(storage_info.key) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let first_id = solcery_project::reserve_ids(project_info, objects.len() as u32)?;
+    for ((object_info, data), id) in object_infos.iter().zip(objects).zip(first_id..) {
+        crud::initialize(project_info, object_info, crud::RecordKind::Object)?;
+        let object = Object {
+            id,
+            template: *template_info.key,
+            template_version: template.version,
+            data: with_defaults(&template, data),
+        };
+        check_fields(&template, &object)?;
+        link::validate(&template, None, &object, accounts)?;
+        crud::write_record(object_info, crud::RecordKind::Object, &object)?;
+        crud::emit(crud::RecordKind::Object, object_info, crud::Change::Create);
+        index::sync(template_info, &template, object_info.key, None, Some(&object), accounts)?;
+    }
+    solcery_storage::add_many(storage_info, object_infos)
+}
+
+/// Creates the object in the first of the given storages that is attached to the template
+/// and has room for one more account, the other accounts are passed on to `create`.
+/// A paged storage takes the object on one of the given pages that can take its key
+pub fn create_routed<'a>(
+    project_info: &AccountInfo,
+    template_info: &AccountInfo,
+    object_info: &AccountInfo,
+    storage_infos: &[AccountInfo<'a>],
+) -> ProgramResult {
+    let template = solcery_template::load_merged(template_info, storage_infos)?;
+    for storage_info in storage_infos {
+        if !template.data.storages.contains(storage_info.key)
+            || solcery_storage::is_full(storage_info)?
+        {
+            continue;
+        }
+        let page_info = match crud::header_kind(storage_info) {
+            crud::RecordKind::PagedStorage => {
+                let key = object_info.key;
+                match solcery_storage::paged::find_page(storage_info, storage_infos, key)? {
+                    Some(page_info) => Some(page_info),
+                    None => continue,
+                }
+            }
+            _ => None,
+        };
+        return create(
+            project_info,
+            template_info,
+            storage_info,
+            object_info,
+            page_info,
+            storage_infos,
+        );
+    }
+    Err(ProgramError::AccountDataTooSmall)
+}
+
+/// Deletes the object from its storage and indexes applying delete rules of linking objects,
+/// the account goes back to the receiver
+pub fn delete(
+    receiver_info: &AccountInfo,
+    template_info: &AccountInfo,
+    storage_info: &AccountInfo,
+    object_info: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let object = load(object_info)?;
+    if object.template != *template_info.key {
+        return Err(error::ObjectError::InvalidTemplate.into());
+    }
+    let template = solcery_template::load(template_info)?;
+    if !template.storages.contains(storage_info.key) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    solcery_storage::remove(storage_info, object_info)?;
+    index::sync(template_info, &template, object_info.key, Some(&object), None, accounts)?;
+    crud::emit(crud::RecordKind::Object, object_info, crud::Change::Delete);
+    object_info.data.borrow_mut().fill(0);
+    let lamports = object_info.lamports();
+    **object_info.lamports.borrow_mut() = 0;
+    **receiver_info.lamports.borrow_mut() += lamports;
+    link::release(receiver_info, &template, object_info.key, accounts)
+}
+
 pub fn update(
     object_info: &AccountInfo,
     data: Vec<u8>,
 ) -> ProgramResult {
-    crud::write(object_info, 36, data);
+    crud::write(object_info, OBJECT_METADATA_SIZE as u64, data)?;
+    crud::emit(crud::RecordKind::Object, object_info, crud::Change::Update);
     Ok(())
 }
 
@@ -98,6 +307,7 @@ pub fn load_data_from_src(
 ) -> ProgramResult {
     let offset = OBJECT_METADATA_SIZE + CRUD_METADATA_SIZE + object_offset;
     object_info.data.borrow_mut()[offset..].copy_from_slice(&src_info.data.borrow()[data_offset..]);
+    crud::emit(crud::RecordKind::Object, object_info, crud::Change::Update);
     Ok(())
 }
 
@@ -106,5 +316,86 @@ pub fn clone(
     src_info: &AccountInfo,
 ) -> ProgramResult { 
     object_info.data.borrow_mut()[CRUD_METADATA_SIZE + OBJECT_METADATA_SIZE..].copy_from_slice(&src_info.data.borrow()[CRUD_METADATA_SIZE + OBJECT_METADATA_SIZE..]);
+    crud::emit(crud::RecordKind::Object, object_info, crud::Change::Update);
+    Ok(())
+}
+
+pub fn load(object_info: &AccountInfo) -> Result<Object, ProgramError> {
+    crud::read_record(object_info, crud::RecordKind::Object)
+}
+
+/// Legacy objects had no template version, it goes between the template and the data
+pub fn migrate_v1(object_info: &AccountInfo) -> ProgramResult {
+    crud::insert_kind(object_info)?;
+    let mut object_data = object_info.data.borrow_mut();
+    let start = CRUD_METADATA_SIZE + TEMPLATE_VERSION_OFFSET;
+    let end = object_data.len();
+    if end < start + 4 || object_data[end - 4..].iter().any(|x| *x != 0) {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    object_data.copy_within(start..end - 4, start + 4);
+    object_data[start..start + 4].fill(0);
+    Ok(())
+}
+
+/// Finds the account with the given key among additional accounts of an instruction
+pub fn find<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    key: &Pubkey,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    accounts
+        .iter()
+        .find(|x| x.key == key)
+        .ok_or(ProgramError::NotEnoughAccountKeys)
+}
+
+/// Runs a change of object data validating links and keeping indexes of its template up to date.
+/// Changed objects move to the version of the template once it is published, changes under
+/// a draft keep the version the object had
+fn with_indexes<F>(
+    object_info: &AccountInfo,
+    template_info: &AccountInfo,
+    accounts: &[AccountInfo],
+    change: F,
+) -> ProgramResult
+where
+    F: FnOnce() -> ProgramResult,
+{
+    let old = load(object_info)?;
+    if old.template != *template_info.key {
+        return Err(error::ObjectError::InvalidTemplate.into());
+    }
+    change()?;
+    let new = load(object_info)?;
+    let template = solcery_template::load_merged(template_info, accounts)?.data;
+    check_fields(&template, &new)?;
+    link::validate(&template, Some(&old), &new, accounts)?;
+    let version = if template.published {
+        template.version
+    } else {
+        old.template_version
+    };
+    if new.template_version != version {
+        crud::write_raw(
+            object_info,
+            (CRUD_METADATA_SIZE + TEMPLATE_VERSION_OFFSET) as u64,
+            version.to_le_bytes().to_vec(),
+        )?;
+    }
+    index::sync(template_info, &template, object_info.key, Some(&old), Some(&new), accounts)
+}
+
+/// Object data has to follow the merged schema of its template
+fn check_fields(template: &TemplateData, object: &Object) -> ProgramResult {
+    for field in &object.data.field_offsets {
+        if !template.fields.iter().any(|x| x.id == field.field_id) {
+            return Err(error::ObjectError::UnknownField.into());
+        }
+        if field.start_offset > field.end_offset
+            || field.end_offset > object.data.field_data.len()
+        {
+            return Err(error::ObjectError::InvalidFieldData.into());
+        }
+    }
     Ok(())
 }
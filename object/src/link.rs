@@ -0,0 +1,134 @@
+use solcery_crud as crud;
+use {
+    crate::{error::ObjectError, find, index, Object},
+    solana_program::{
+        account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+        pubkey::Pubkey,
+    },
+    solcery_template::{OnDelete, SolceryType, TemplateData},
+};
+
+/// Checks that changed link fields point to objects of the declared templates and are
+/// indexed, so that `release` finds every link. Linked objects and the indexes of the
+/// template have to be passed among `accounts`
+pub fn validate(
+    template: &TemplateData,
+    old: Option<&Object>,
+    new: &Object,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let mut indexed_fields = Vec::new();
+    for field in &template.fields {
+        let linked_template = match field.params.field_type {
+            SolceryType::SLink { template } => template,
+            _ => continue,
+        };
+        let value = match index::field_value(new, field.id) {
+            Some(value) => value,
+            None => continue,
+        };
+        if old.and_then(|x| index::field_value(x, field.id)).as_ref() == Some(&value) {
+            continue;
+        }
+        if value.len() != 32 {
+            return Err(ObjectError::InvalidLink.into());
+        }
+        if indexed_fields.is_empty() {
+            for index_key in &template.indexes {
+                let index_info = find(accounts, index_key).map_err(|_| ObjectError::MissingIndex)?;
+                indexed_fields.push(index::load(index_info)?.field_id);
+            }
+        }
+        if !indexed_fields.contains(&field.id) {
+            return Err(ObjectError::UnindexedLink.into());
+        }
+        let linked_info =
+            find(accounts, &Pubkey::new(&value)).map_err(|_| ObjectError::InvalidLink)?;
+        if crate::load(linked_info)?.template != linked_template {
+            return Err(ObjectError::InvalidLink.into());
+        }
+    }
+    Ok(())
+}
+
+/// Applies delete rules of every object linking to the deleted one. Links are only written
+/// to indexed fields, so the backreference indexes of the template have every link.
+/// Backreference indexes, templates with ancestors, objects, storages and indexes of linking
+/// objects have to be passed among `accounts`
+pub fn release(
+    receiver_info: &AccountInfo,
+    template: &TemplateData,
+    object_key: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    for backref_key in &template.backrefs {
+        let backref_info = find(accounts, backref_key)?;
+        let (referrer_template_key, field_id) = {
+            let backref = index::load(backref_info)?;
+            if backref.cursor.is_some() {
+                return Err(ObjectError::IndexNotBuilt.into());
+            }
+            (backref.template, backref.field_id)
+        };
+        let referrer_template_info = find(accounts, &referrer_template_key)?;
+        let referrer_template =
+            solcery_template::load_merged(referrer_template_info, accounts)?.data;
+        let on_delete = referrer_template.on_delete(field_id);
+        // Both rules drop the referrer from the backreference index, so the loop ends
+        while let Some(referrer_key) = index::load(backref_info)?
+            .lookup(object_key.as_ref())
+            .first()
+            .copied()
+        {
+            let referrer_info = find(accounts, &referrer_key)?;
+            match on_delete {
+                OnDelete::Restrict => return Err(ObjectError::Referenced.into()),
+                OnDelete::Nullify => nullify(
+                    referrer_template_info,
+                    &referrer_template,
+                    referrer_info,
+                    field_id,
+                    accounts,
+                )?,
+                OnDelete::Cascade => {
+                    let storage_info = referrer_template
+                        .storages
+                        .iter()
+                        .filter_map(|x| find(accounts, x).ok())
+                        .find(|x| solcery_storage::contains(x, &referrer_key).unwrap_or(false))
+                        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                    crate::delete(
+                        receiver_info,
+                        referrer_template_info,
+                        storage_info,
+                        referrer_info,
+                        accounts,
+                    )?
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn nullify(
+    template_info: &AccountInfo,
+    template: &TemplateData,
+    object_info: &AccountInfo,
+    field_id: u32,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let old = crate::load(object_info)?;
+    let mut new = old.clone();
+    new.data.field_offsets.retain(|x| x.field_id != field_id);
+    crud::write_record(object_info, crud::RecordKind::Object, &new)?;
+    crud::emit(crud::RecordKind::Object, object_info, crud::Change::Update);
+    index::sync(
+        template_info,
+        template,
+        object_info.key,
+        Some(&old),
+        Some(&new),
+        accounts,
+    )
+}
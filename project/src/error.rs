@@ -0,0 +1,24 @@
+use {
+    solana_program::{decode_error::DecodeError, program_error::ProgramError},
+    thiserror::Error,
+};
+
+/// Errors that may be returned by project instructions, codes 0x400..0x4ff
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ProjectError {
+    /// Project ran out of ids
+    #[error("Project ran out of ids")]
+    IdOverflow = 0x400,
+}
+
+impl From<ProjectError> for ProgramError {
+    fn from(e: ProjectError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for ProjectError {
+    fn type_of() -> &'static str {
+        "Project Error"
+    }
+}
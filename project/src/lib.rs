@@ -1,4 +1,7 @@
+pub mod error;
+
 use {
+    crate::error::ProjectError,
     borsh::{BorshDeserialize, BorshSchema, BorshSerialize},
     solana_program::{
         account_info::{next_account_info, AccountInfo},
@@ -12,32 +15,36 @@ use {
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
 pub struct Project {
+    pub uniq_id: u32, // Next free id, kept first to be updated in place
     pub name: String,
     pub owner: Pubkey,
     pub template_storage: Pubkey,
-    pub uniq_id: u32,
+}
+
+impl Project {
+    pub const UNIQ_ID_OFFSET: usize = solcery_crud::RecordData::WRITABLE_START_INDEX;
 }
 
 #[must_use]
 pub fn check_access(user_info: &AccountInfo, project_info: &AccountInfo) -> bool {
-    let record_data =
-        solcery_crud::RecordData::deserialize(&mut &project_info.data.borrow()[..]).unwrap();
-    let project = Project::deserialize(&mut &record_data.data.bytes[..]).unwrap();
-    project.owner == *user_info.key
+    match solcery_crud::read_record::<Project>(project_info, solcery_crud::RecordKind::Project) {
+        Ok(project) => user_info.is_signer && project.owner == *user_info.key,
+        Err(_) => false,
+    }
 }
 
 pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
     let (tag, _data) = instruction_data.split_first().unwrap();
     let accounts_iter = &mut accounts.iter();
-    let signer_info = next_account_info(accounts_iter)?;
-    match (tag) {
+    let _signer_info = next_account_info(accounts_iter)?;
+    match tag {
     	0 => {
             let project_info = next_account_info(accounts_iter)?;
     		let project_templates_storage_info = next_account_info(accounts_iter)?;
             let owner_info = next_account_info(accounts_iter)?;
     		create(project_info, project_templates_storage_info, owner_info)
     	},
-        _ => return Err(ProgramError::InvalidAccountData)
+        _ => Err(ProgramError::InvalidAccountData),
     }
 }
 
@@ -48,24 +55,80 @@ pub fn create(
 ) -> ProgramResult {
     msg!("Project/Create");
     let project_data = Project {
+        uniq_id: 0,
         name: "New project".to_string(), // TODO: name
         owner: *owner_info.key,
         template_storage: *project_templates_storage_info.key,
-        uniq_id: 0,
     };
-    solcery_crud::initialize(project_info, project_info);
-    solcery_crud::write(project_info, 0, project_data.try_to_vec().unwrap());
+    solcery_crud::initialize(project_info, project_info, solcery_crud::RecordKind::Project)?;
+    solcery_crud::write_record(project_info, solcery_crud::RecordKind::Project, &project_data)?;
+    solcery_crud::emit(
+        solcery_crud::RecordKind::Project,
+        project_info,
+        solcery_crud::Change::Create,
+    );
     solcery_storage::assign(project_info, project_templates_storage_info, project_info)?;
     Ok(())
 }
 
-#[must_use]
-pub fn get_uniq_id(project_info: &AccountInfo) -> u32 {
-    let mut project_data = Project::deserialize(
-        &mut &project_info.data.borrow()[solcery_crud::RecordData::WRITABLE_START_INDEX..],
-    )
-    .unwrap();
-    project_data.uniq_id += 1;
-    solcery_crud::write(project_info, 0, project_data.try_to_vec().unwrap());
-    project_data.uniq_id - 1
+/// Next id to be allocated, the counter is left as is
+pub fn next_uniq_id(project_info: &AccountInfo) -> Result<u32, ProgramError> {
+    solcery_crud::check_kind(project_info, solcery_crud::RecordKind::Project)?;
+    let project_data = project_info.data.borrow();
+    let counter_data = project_data
+        .get(Project::UNIQ_ID_OFFSET..Project::UNIQ_ID_OFFSET + 4)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    Ok(u32::deserialize(&mut &*counter_data)?)
+}
+
+/// Allocates a single id
+pub fn get_uniq_id(project_info: &AccountInfo) -> Result<u32, ProgramError> {
+    reserve_ids(project_info, 1)
+}
+
+/// Allocates `amount` consecutive ids and returns the first one.
+/// The counter is updated in place, the rest of the project is left untouched
+pub fn reserve_ids(project_info: &AccountInfo, amount: u32) -> Result<u32, ProgramError> {
+    solcery_crud::check_kind(project_info, solcery_crud::RecordKind::Project)?;
+    if amount == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut project_data = project_info.data.borrow_mut();
+    let counter_data = project_data
+        .get_mut(Project::UNIQ_ID_OFFSET..Project::UNIQ_ID_OFFSET + 4)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let first = u32::deserialize(&mut &*counter_data)?;
+    let next = first.checked_add(amount).ok_or(ProjectError::IdOverflow)?;
+    counter_data.copy_from_slice(&next.to_le_bytes());
+    drop(project_data);
+    solcery_crud::emit(
+        solcery_crud::RecordKind::Project,
+        project_info,
+        solcery_crud::Change::Update,
+    );
+    Ok(first)
+}
+
+/// Legacy projects kept the counter after the name
+pub fn migrate_v1(project_info: &AccountInfo) -> ProgramResult {
+    solcery_crud::insert_kind(project_info)?;
+    #[derive(BorshDeserialize)]
+    struct LegacyProject {
+        name: String,
+        owner: Pubkey,
+        template_storage: Pubkey,
+        uniq_id: u32,
+    }
+    let legacy = {
+        let project_data =
+            &project_info.data.borrow()[solcery_crud::RecordData::WRITABLE_START_INDEX..];
+        LegacyProject::deserialize(&mut &*project_data)?
+    };
+    let project = Project {
+        uniq_id: legacy.uniq_id,
+        name: legacy.name,
+        owner: legacy.owner,
+        template_storage: legacy.template_storage,
+    };
+    solcery_crud::write(project_info, 0, project.try_to_vec().unwrap())
 }
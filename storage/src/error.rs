@@ -0,0 +1,40 @@
+use {
+    solana_program::{decode_error::DecodeError, program_error::ProgramError},
+    thiserror::Error,
+};
+
+/// Errors that may be returned by storage instructions, codes 0x200..0x2ff
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum StorageError {
+    /// Account is already in the storage
+    #[error("Account is already in the storage")]
+    AlreadyInStorage = 0x200,
+
+    /// Account is not in the storage
+    #[error("Account is not in the storage")]
+    NotInStorage,
+
+    /// Account doesn't match the template of the storage
+    #[error("Account doesn't match the template of the storage")]
+    TemplateMismatch,
+
+    /// Operation would break the order of a sorted storage
+    #[error("Operation would break the order of a sorted storage")]
+    SortedStorage,
+
+    /// Account key is outside the range of the page
+    #[error("Account key is outside the range of the page")]
+    OutOfPageRange,
+}
+
+impl From<StorageError> for ProgramError {
+    fn from(e: StorageError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for StorageError {
+    fn type_of() -> &'static str {
+        "Storage Error"
+    }
+}
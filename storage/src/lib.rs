@@ -1,5 +1,9 @@
+pub mod error;
+pub mod paged;
+
 use {
     borsh::{BorshDeserialize, BorshSerialize},
+    error::StorageError,
     solana_program::{
         account_info::{next_account_info, AccountInfo},
         entrypoint::ProgramResult,
@@ -13,15 +17,27 @@ use solcery_crud as crud;
 #[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Debug)]
 pub struct AccountStorage {
     pub template: Pubkey, // Self for abstract
+    pub sorted: bool,     // Keys are kept in ascending order and looked up with binary search
     pub accounts: Vec<Pubkey>,
 }
 
+/// Borsh layout of AccountStorage is fixed: template, sorted flag, u32 length and contiguous
+/// keys, so storage operations work on account data in place instead of reserializing it
+impl AccountStorage {
+    pub const SORTED_OFFSET: usize = crud::RecordData::WRITABLE_START_INDEX + 32;
+    pub const LEN_OFFSET: usize = Self::SORTED_OFFSET + 1;
+    pub const KEYS_OFFSET: usize = Self::LEN_OFFSET + 4;
+
+    /// Objects start with u32 id followed by their template
+    pub const OBJECT_TEMPLATE_OFFSET: usize = crud::RecordData::WRITABLE_START_INDEX + 4;
+}
+
 pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
-    let (tag, _data) = instruction_data.split_first().unwrap();
+    let (tag, data) = instruction_data.split_first().unwrap();
     let accounts_iter = &mut accounts.iter();
-    let signer_info = next_account_info(accounts_iter)?;
+    let _signer_info = next_account_info(accounts_iter)?;
     let project_info = next_account_info(accounts_iter)?;
-    match (tag) {
+    match tag {
     	0 => {
     		let storage_info = next_account_info(accounts_iter)?;
     		let account_info = next_account_info(accounts_iter)?;
@@ -32,7 +48,67 @@ pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) ->
     		let account_info = next_account_info(accounts_iter)?;
     		remove(storage_info, account_info)
     	}
-        _ => return Err(ProgramError::InvalidAccountData)
+        2 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let target_info = next_account_info(accounts_iter)?;
+            let page_capacity = u32::deserialize(&mut &data[..])?;
+            paged::create(project_info, storage_info, target_info, page_capacity)
+        }
+        3 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let page_info = next_account_info(accounts_iter)?;
+            let account_info = next_account_info(accounts_iter)?;
+            paged::add(project_info, storage_info, page_info, account_info)
+        }
+        4 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let page_info = next_account_info(accounts_iter)?;
+            let account_info = next_account_info(accounts_iter)?;
+            paged::remove(storage_info, page_info, account_info)
+        }
+        5 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let account_info = next_account_info(accounts_iter)?;
+            swap_remove(storage_info, account_info)
+        }
+        6 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let sorted = bool::deserialize(&mut &data[..])?;
+            set_sorted(storage_info, sorted)
+        }
+        7 => {
+            let from_info = next_account_info(accounts_iter)?;
+            let to_info = next_account_info(accounts_iter)?;
+            let account_info = next_account_info(accounts_iter)?;
+            transfer(from_info, to_info, account_info)
+        }
+        8 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let account_info = next_account_info(accounts_iter)?;
+            let index = u32::deserialize(&mut &data[..])?;
+            insert_at(storage_info, account_info, index as usize)
+        }
+        9 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let buf = &mut &data[..];
+            let from = u32::deserialize(buf)?;
+            let to = u32::deserialize(buf)?;
+            move_key(storage_info, from as usize, to as usize)
+        }
+        10 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let buf = &mut &data[..];
+            let a = u32::deserialize(buf)?;
+            let b = u32::deserialize(buf)?;
+            swap(storage_info, a as usize, b as usize)
+        }
+        11 => {
+            let storage_info = next_account_info(accounts_iter)?;
+            let page_info = next_account_info(accounts_iter)?;
+            let new_page_info = next_account_info(accounts_iter)?;
+            paged::split(project_info, storage_info, page_info, new_page_info)
+        }
+        _ => Err(ProgramError::InvalidAccountData),
     }
 }
 
@@ -44,38 +120,351 @@ pub fn assign(
     msg!("Storage/Assign");
     let storage = AccountStorage {
         template: *target_info.key,
+        sorted: false,
         accounts: Vec::new(),
     };
-    crud::initialize(project_info, storage_info);
-    crud::write(storage_info, 0, storage.try_to_vec().unwrap())?;
+    crud::initialize(project_info, storage_info, crud::RecordKind::Storage)?;
+    crud::write_record(storage_info, crud::RecordKind::Storage, &storage)?;
+    crud::emit(crud::RecordKind::Storage, storage_info, crud::Change::Create);
     Ok(())
 }
 
+/// Storages are sets, adding an account twice is an error
 pub fn add(storage_info: &AccountInfo, account_info: &AccountInfo) -> ProgramResult {
     msg!("Storage/Add");
-    let mut storage = {
-        let storage_data =
-            &storage_info.data.borrow()[crud::RecordData::WRITABLE_START_INDEX..];
-        AccountStorage::deserialize(&mut &*storage_data)?
+    let index = match search(storage_info, account_info.key)? {
+        Ok(_) => return Err(StorageError::AlreadyInStorage.into()),
+        Err(index) => index,
     };
-    storage.accounts.push(*account_info.key);
-    crud::write(storage_info, 0, storage.try_to_vec().unwrap())?;
+    insert(storage_info, account_info, index)
+}
+
+/// Adds accounts updating the length once, sorted storages insert them one by one
+pub fn add_many(storage_info: &AccountInfo, account_infos: &[AccountInfo]) -> ProgramResult {
+    if is_sorted(storage_info)? {
+        return account_infos.iter().try_for_each(|x| add(storage_info, x));
+    }
+    msg!("Storage/AddMany");
+    let template = get_template(storage_info)?;
+    for (i, account_info) in account_infos.iter().enumerate() {
+        check_template(storage_info, &template, account_info)?;
+        if contains(storage_info, account_info.key)?
+            || account_infos[..i].iter().any(|x| x.key == account_info.key)
+        {
+            return Err(StorageError::AlreadyInStorage.into());
+        }
+    }
+    let len = len(storage_info)?;
+    let start = AccountStorage::KEYS_OFFSET + len * 32;
+    let end = start + account_infos.len() * 32;
+    let mut storage_data = storage_info.data.borrow_mut();
+    if end > storage_data.len() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    for (chunk, account_info) in storage_data[start..end]
+        .chunks_exact_mut(32)
+        .zip(account_infos)
+    {
+        chunk.copy_from_slice(account_info.key.as_ref());
+    }
+    set_len(&mut storage_data, len + account_infos.len());
+    drop(storage_data);
+    crud::emit(crud::RecordKind::Storage, storage_info, crud::Change::Update);
     Ok(())
 }
 
+/// Adds the account at the given position, not available for sorted storages
+pub fn insert_at(
+    storage_info: &AccountInfo,
+    account_info: &AccountInfo,
+    index: usize,
+) -> ProgramResult {
+    msg!("Storage/InsertAt");
+    if is_sorted(storage_info)? {
+        return Err(StorageError::SortedStorage.into());
+    }
+    if contains(storage_info, account_info.key)? {
+        return Err(StorageError::AlreadyInStorage.into());
+    }
+    if index > len(storage_info)? {
+        return Err(ProgramError::InvalidArgument);
+    }
+    insert(storage_info, account_info, index)
+}
+
+/// Moves the key at `from` to position `to` shifting the keys in between
+pub fn move_key(storage_info: &AccountInfo, from: usize, to: usize) -> ProgramResult {
+    msg!("Storage/Move");
+    if is_sorted(storage_info)? {
+        return Err(StorageError::SortedStorage.into());
+    }
+    let len = len(storage_info)?;
+    if from >= len || to >= len {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut storage_data = storage_info.data.borrow_mut();
+    let keys_data =
+        &mut storage_data[AccountStorage::KEYS_OFFSET..AccountStorage::KEYS_OFFSET + len * 32];
+    if from < to {
+        keys_data[from * 32..(to + 1) * 32].rotate_left(32);
+    } else {
+        keys_data[to * 32..(from + 1) * 32].rotate_right(32);
+    }
+    drop(storage_data);
+    crud::emit(crud::RecordKind::Storage, storage_info, crud::Change::Update);
+    Ok(())
+}
+
+pub fn swap(storage_info: &AccountInfo, a: usize, b: usize) -> ProgramResult {
+    msg!("Storage/Swap");
+    if is_sorted(storage_info)? {
+        return Err(StorageError::SortedStorage.into());
+    }
+    let len = len(storage_info)?;
+    if a >= len || b >= len {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if a == b {
+        return Ok(());
+    }
+    let mut storage_data = storage_info.data.borrow_mut();
+    let keys_data =
+        &mut storage_data[AccountStorage::KEYS_OFFSET..AccountStorage::KEYS_OFFSET + len * 32];
+    let (low, high) = (a.min(b), a.max(b));
+    let (head, tail) = keys_data.split_at_mut(high * 32);
+    head[low * 32..low * 32 + 32].swap_with_slice(&mut tail[..32]);
+    drop(storage_data);
+    crud::emit(crud::RecordKind::Storage, storage_info, crud::Change::Update);
+    Ok(())
+}
+
+/// Removes the account keeping the order of the rest
 pub fn remove(storage_info: &AccountInfo, account_info: &AccountInfo) -> ProgramResult {
     msg!("Storage/Remove");
-    let mut storage = {
-        let storage_data =
-            &storage_info.data.borrow()[crud::RecordData::WRITABLE_START_INDEX..];
-        AccountStorage::deserialize(&mut &*storage_data)?
-    };
-    for i in 0..storage.accounts.len() {
-        if storage.accounts[i] == *account_info.key {
-            storage.accounts.remove(i);
-            break;
+    let index = search(storage_info, account_info.key)?.map_err(|_| StorageError::NotInStorage)?;
+    let len = len(storage_info)?;
+    let start = AccountStorage::KEYS_OFFSET + index * 32;
+    let end = AccountStorage::KEYS_OFFSET + len * 32;
+    let mut storage_data = storage_info.data.borrow_mut();
+    storage_data.copy_within(start + 32..end, start);
+    storage_data[end - 32..end].fill(0);
+    set_len(&mut storage_data, len - 1);
+    drop(storage_data);
+    crud::emit(crud::RecordKind::Storage, storage_info, crud::Change::Update);
+    Ok(())
+}
+
+/// Removes the account moving the last key in its place, sorted storages keep the order
+pub fn swap_remove(storage_info: &AccountInfo, account_info: &AccountInfo) -> ProgramResult {
+    if is_sorted(storage_info)? {
+        return remove(storage_info, account_info);
+    }
+    msg!("Storage/SwapRemove");
+    let index = search(storage_info, account_info.key)?.map_err(|_| StorageError::NotInStorage)?;
+    let len = len(storage_info)?;
+    let start = AccountStorage::KEYS_OFFSET + index * 32;
+    let last = AccountStorage::KEYS_OFFSET + (len - 1) * 32;
+    let mut storage_data = storage_info.data.borrow_mut();
+    storage_data.copy_within(last..last + 32, start);
+    storage_data[last..last + 32].fill(0);
+    set_len(&mut storage_data, len - 1);
+    drop(storage_data);
+    crud::emit(crud::RecordKind::Storage, storage_info, crud::Change::Update);
+    Ok(())
+}
+
+/// Moves the account between storages, the target storage checks its template as on add
+pub fn transfer(
+    from_info: &AccountInfo,
+    to_info: &AccountInfo,
+    account_info: &AccountInfo,
+) -> ProgramResult {
+    msg!("Storage/Transfer");
+    remove(from_info, account_info)?;
+    add(to_info, account_info)
+}
+
+/// Switching sorted mode on sorts the keys once, after that they stay sorted on add
+pub fn set_sorted(storage_info: &AccountInfo, sorted: bool) -> ProgramResult {
+    msg!("Storage/SetSorted");
+    let len = len(storage_info)?;
+    let mut storage_data = storage_info.data.borrow_mut();
+    if sorted {
+        let keys_data =
+            &mut storage_data[AccountStorage::KEYS_OFFSET..AccountStorage::KEYS_OFFSET + len * 32];
+        let mut keys: Vec<[u8; 32]> = keys_data
+            .chunks_exact(32)
+            .map(|x| {
+                let mut key = [0; 32];
+                key.copy_from_slice(x);
+                key
+            })
+            .collect();
+        keys.sort_unstable();
+        for (chunk, key) in keys_data.chunks_exact_mut(32).zip(keys.iter()) {
+            chunk.copy_from_slice(key);
         }
     }
-    crud::write(storage_info, 0, storage.try_to_vec().unwrap())?;
+    storage_data[AccountStorage::SORTED_OFFSET] = sorted as u8;
+    drop(storage_data);
+    crud::emit(crud::RecordKind::Storage, storage_info, crud::Change::Update);
+    Ok(())
+}
+
+/// Storages accept records of their own project only:
+/// - abstract storages (template is the storage itself) accept any record
+/// - the project template storage (template is the project) accepts templates
+/// - other storages accept objects of their template
+pub fn check_template(
+    storage_info: &AccountInfo,
+    template: &Pubkey,
+    account_info: &AccountInfo,
+) -> ProgramResult {
+    let project = crud::get_project(storage_info)?;
+    crud::check_version(account_info).map_err(|_| StorageError::TemplateMismatch)?;
+    if crud::get_project(account_info)? != project {
+        return Err(StorageError::TemplateMismatch.into());
+    }
+    if template == storage_info.key {
+        return Ok(());
+    }
+    if *template == project {
+        return match crud::get_kind(account_info)? {
+            crud::RecordKind::Template => Ok(()),
+            _ => Err(StorageError::TemplateMismatch.into()),
+        };
+    }
+    if crud::get_kind(account_info)? != crud::RecordKind::Object {
+        return Err(StorageError::TemplateMismatch.into());
+    }
+    let account_data = account_info.data.borrow();
+    let object_template = account_data
+        .get(AccountStorage::OBJECT_TEMPLATE_OFFSET..AccountStorage::OBJECT_TEMPLATE_OFFSET + 32)
+        .ok_or(StorageError::TemplateMismatch)?;
+    if object_template != template.as_ref() {
+        return Err(StorageError::TemplateMismatch.into());
+    }
     Ok(())
 }
+
+pub fn get_template(storage_info: &AccountInfo) -> Result<Pubkey, ProgramError> {
+    crud::check_kind(storage_info, crud::RecordKind::Storage)?;
+    let storage_data = storage_info.data.borrow();
+    let template_data = storage_data
+        .get(crud::RecordData::WRITABLE_START_INDEX..AccountStorage::SORTED_OFFSET)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    Ok(Pubkey::new(template_data))
+}
+
+pub fn is_sorted(storage_info: &AccountInfo) -> Result<bool, ProgramError> {
+    crud::check_kind(storage_info, crud::RecordKind::Storage)?;
+    let storage_data = storage_info.data.borrow();
+    let sorted_data = storage_data
+        .get(AccountStorage::SORTED_OFFSET..AccountStorage::LEN_OFFSET)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    Ok(bool::deserialize(&mut &*sorted_data)?)
+}
+
+pub fn len(storage_info: &AccountInfo) -> Result<usize, ProgramError> {
+    crud::check_kind(storage_info, crud::RecordKind::Storage)?;
+    let storage_data = storage_info.data.borrow();
+    let len_data = storage_data
+        .get(AccountStorage::LEN_OFFSET..AccountStorage::KEYS_OFFSET)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let len = u32::deserialize(&mut &*len_data)? as usize;
+    if AccountStorage::KEYS_OFFSET + len * 32 > storage_data.len() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(len)
+}
+
+/// Paged storages are never full, their pages are split to make room
+pub fn is_full(storage_info: &AccountInfo) -> Result<bool, ProgramError> {
+    if crud::header_kind(storage_info) == crud::RecordKind::PagedStorage {
+        paged::load_storage(storage_info)?;
+        return Ok(false);
+    }
+    let len = len(storage_info)?;
+    Ok(AccountStorage::KEYS_OFFSET + (len + 1) * 32 > storage_info.data_len())
+}
+
+pub fn get(storage_info: &AccountInfo, index: usize) -> Result<Pubkey, ProgramError> {
+    if index >= len(storage_info)? {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let start = AccountStorage::KEYS_OFFSET + index * 32;
+    Ok(Pubkey::new(&storage_info.data.borrow()[start..start + 32]))
+}
+
+pub fn contains(storage_info: &AccountInfo, key: &Pubkey) -> Result<bool, ProgramError> {
+    Ok(search(storage_info, key)?.is_ok())
+}
+
+pub fn position(storage_info: &AccountInfo, key: &Pubkey) -> Result<Option<usize>, ProgramError> {
+    Ok(search(storage_info, key)?.ok())
+}
+
+/// Like `slice::binary_search`: Ok with the index of the key or Err with the index where it
+/// should be added. Sorted storages use binary search, others scan and add to the end
+pub fn search(
+    storage_info: &AccountInfo,
+    key: &Pubkey,
+) -> Result<Result<usize, usize>, ProgramError> {
+    let sorted = is_sorted(storage_info)?;
+    let len = len(storage_info)?;
+    let storage_data = storage_info.data.borrow();
+    let keys = &storage_data[AccountStorage::KEYS_OFFSET..AccountStorage::KEYS_OFFSET + len * 32];
+    if sorted {
+        let (mut low, mut high) = (0, len);
+        while low < high {
+            let mid = (low + high) / 2;
+            match keys[mid * 32..mid * 32 + 32].cmp(key.as_ref()) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Ok(Ok(mid)),
+            }
+        }
+        Ok(Err(low))
+    } else {
+        match keys.chunks_exact(32).position(|x| x == key.as_ref()) {
+            Some(index) => Ok(Ok(index)),
+            None => Ok(Err(len)),
+        }
+    }
+}
+
+/// Legacy storages had no sorted flag, it goes between the template and the length
+pub fn migrate_v1(storage_info: &AccountInfo) -> ProgramResult {
+    crud::insert_kind(storage_info)?;
+    let mut storage_data = storage_info.data.borrow_mut();
+    let end = storage_data.len();
+    if end < AccountStorage::KEYS_OFFSET || storage_data[end - 1] != 0 {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    storage_data.copy_within(AccountStorage::SORTED_OFFSET..end - 1, AccountStorage::LEN_OFFSET);
+    storage_data[AccountStorage::SORTED_OFFSET] = 0;
+    Ok(())
+}
+
+fn insert(storage_info: &AccountInfo, account_info: &AccountInfo, index: usize) -> ProgramResult {
+    let template = get_template(storage_info)?;
+    check_template(storage_info, &template, account_info)?;
+    let len = len(storage_info)?;
+    let start = AccountStorage::KEYS_OFFSET + index * 32;
+    let end = AccountStorage::KEYS_OFFSET + len * 32;
+    let mut storage_data = storage_info.data.borrow_mut();
+    if end + 32 > storage_data.len() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    storage_data.copy_within(start..end, start + 32);
+    storage_data[start..start + 32].copy_from_slice(account_info.key.as_ref());
+    set_len(&mut storage_data, len + 1);
+    drop(storage_data);
+    crud::emit(crud::RecordKind::Storage, storage_info, crud::Change::Update);
+    Ok(())
+}
+
+fn set_len(storage_data: &mut [u8], len: usize) {
+    storage_data[AccountStorage::LEN_OFFSET..AccountStorage::KEYS_OFFSET]
+        .copy_from_slice(&(len as u32).to_le_bytes());
+}
@@ -0,0 +1,262 @@
+use solcery_crud as crud;
+use {
+    crate::error::StorageError,
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{
+        account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+        pubkey::Pubkey,
+    },
+};
+
+/// Head of a storage spread over page accounts of a fixed capacity.
+/// Every page holds the keys of its own range and the ranges of all pages cover the key space,
+/// so `add` and `remove` touch the head and the single page whose range has the key.
+/// A full page is split in two to make room, pages are linked from `last_page`
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Debug)]
+pub struct PagedStorage {
+    pub template: Pubkey, // Self for abstract
+    pub page_capacity: u32,
+    pub len: u32,
+    pub pages: u32,
+    pub last_page: Option<Pubkey>,
+}
+
+/// Page of the keys from `start` up to `end`, which is out of the range.
+/// The page without an end holds the keys up to the last one
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Debug)]
+pub struct StoragePage {
+    pub storage: Pubkey,
+    pub prev: Option<Pubkey>,
+    pub start: Pubkey,
+    pub end: Option<Pubkey>,
+    pub accounts: Vec<Pubkey>,
+}
+
+impl StoragePage {
+    /// Account size needed for a page of the given capacity
+    pub fn space(page_capacity: u32) -> usize {
+        crud::RecordData::WRITABLE_START_INDEX + 32 + 33 + 32 + 33 + 4 + 32 * page_capacity as usize
+    }
+
+    /// First page of a storage, it takes the whole key space
+    pub fn new(storage: Pubkey) -> Self {
+        StoragePage {
+            storage,
+            prev: None,
+            start: Pubkey::default(),
+            end: None,
+            accounts: Vec::new(),
+        }
+    }
+
+    pub fn covers(&self, key: &Pubkey) -> bool {
+        *key >= self.start && !matches!(self.end, Some(end) if *key >= end)
+    }
+
+    /// Moves the lower half of the keys with the lower part of the range to a new page,
+    /// which goes right before this one in the chain. Takes at least two keys
+    pub fn split(&mut self, new_page: &Pubkey) -> Result<StoragePage, ProgramError> {
+        if self.accounts.len() < 2 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.accounts.sort();
+        let upper = self.accounts.split_off(self.accounts.len() / 2);
+        let middle = upper[0];
+        let lower = StoragePage {
+            storage: self.storage,
+            prev: self.prev,
+            start: self.start,
+            end: Some(middle),
+            accounts: std::mem::replace(&mut self.accounts, upper),
+        };
+        self.start = middle;
+        self.prev = Some(*new_page);
+        Ok(lower)
+    }
+}
+
+pub fn create(
+    project_info: &AccountInfo,
+    storage_info: &AccountInfo,
+    target_info: &AccountInfo,
+    page_capacity: u32,
+) -> ProgramResult {
+    msg!("Storage/CreatePaged");
+    // A full page is split in two, so it takes at least two accounts
+    if page_capacity < 2 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let storage = PagedStorage {
+        template: *target_info.key,
+        page_capacity,
+        len: 0,
+        pages: 0,
+        last_page: None,
+    };
+    crud::initialize(project_info, storage_info, crud::RecordKind::PagedStorage)?;
+    crud::write_record(storage_info, crud::RecordKind::PagedStorage, &storage)?;
+    crud::emit(
+        crud::RecordKind::PagedStorage,
+        storage_info,
+        crud::Change::Create,
+    );
+    Ok(())
+}
+
+/// Adds the account to the page whose range has its key. The first page of the storage
+/// comes from an uninitialized account, others from `split`
+pub fn add(
+    project_info: &AccountInfo,
+    storage_info: &AccountInfo,
+    page_info: &AccountInfo,
+    account_info: &AccountInfo,
+) -> ProgramResult {
+    msg!("Storage/AddPaged");
+    let mut storage = load_storage(storage_info)?;
+    crate::check_template(storage_info, &storage.template, account_info)?;
+    let mut page = if crud::is_initialized(page_info) || storage.pages > 0 {
+        load_page(storage_info, page_info)?
+    } else {
+        if page_info.data_len() < StoragePage::space(storage.page_capacity) {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        crud::initialize(project_info, page_info, crud::RecordKind::StoragePage)?;
+        storage.last_page = Some(*page_info.key);
+        storage.pages = 1;
+        crud::emit(crud::RecordKind::StoragePage, page_info, crud::Change::Create);
+        StoragePage::new(*storage_info.key)
+    };
+    if !page.covers(account_info.key) {
+        return Err(StorageError::OutOfPageRange.into());
+    }
+    if page.accounts.contains(account_info.key) {
+        return Err(StorageError::AlreadyInStorage.into());
+    }
+    if page.accounts.len() >= storage.page_capacity as usize {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    page.accounts.push(*account_info.key);
+    storage.len += 1;
+    crud::write_record(page_info, crud::RecordKind::StoragePage, &page)?;
+    crud::write_record(storage_info, crud::RecordKind::PagedStorage, &storage)?;
+    crud::emit(crud::RecordKind::StoragePage, page_info, crud::Change::Update);
+    crud::emit(crud::RecordKind::PagedStorage, storage_info, crud::Change::Update);
+    Ok(())
+}
+
+/// Splits the page with `StoragePage::split` into an uninitialized account
+pub fn split(
+    project_info: &AccountInfo,
+    storage_info: &AccountInfo,
+    page_info: &AccountInfo,
+    new_page_info: &AccountInfo,
+) -> ProgramResult {
+    msg!("Storage/SplitPage");
+    let mut storage = load_storage(storage_info)?;
+    let mut page = load_page(storage_info, page_info)?;
+    if new_page_info.data_len() < StoragePage::space(storage.page_capacity) {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    crud::initialize(project_info, new_page_info, crud::RecordKind::StoragePage)?;
+    let new_page = page.split(new_page_info.key)?;
+    storage.pages += 1;
+    crud::write_record(new_page_info, crud::RecordKind::StoragePage, &new_page)?;
+    crud::write_record(page_info, crud::RecordKind::StoragePage, &page)?;
+    crud::write_record(storage_info, crud::RecordKind::PagedStorage, &storage)?;
+    crud::emit(
+        crud::RecordKind::StoragePage,
+        new_page_info,
+        crud::Change::Create,
+    );
+    crud::emit(
+        crud::RecordKind::StoragePage,
+        page_info,
+        crud::Change::Update,
+    );
+    crud::emit(
+        crud::RecordKind::PagedStorage,
+        storage_info,
+        crud::Change::Update,
+    );
+    Ok(())
+}
+
+/// Page among `page_infos` that can take the key right away, for routing new objects
+pub fn find_page<'a, 'b>(
+    storage_info: &AccountInfo,
+    page_infos: &'a [AccountInfo<'b>],
+    key: &Pubkey,
+) -> Result<Option<&'a AccountInfo<'b>>, ProgramError> {
+    let storage = load_storage(storage_info)?;
+    for page_info in page_infos {
+        if crud::header_kind(page_info) != crud::RecordKind::StoragePage {
+            continue;
+        }
+        let page: StoragePage = crud::read_record(page_info, crud::RecordKind::StoragePage)?;
+        if page.storage == *storage_info.key
+            && page.covers(key)
+            && page.accounts.len() < storage.page_capacity as usize
+        {
+            return Ok(Some(page_info));
+        }
+    }
+    Ok(None)
+}
+
+pub fn remove(
+    storage_info: &AccountInfo,
+    page_info: &AccountInfo,
+    account_info: &AccountInfo,
+) -> ProgramResult {
+    msg!("Storage/RemovePaged");
+    let mut storage = load_storage(storage_info)?;
+    let mut page = load_page(storage_info, page_info)?;
+    let index = page
+        .accounts
+        .iter()
+        .position(|x| x == account_info.key)
+        .ok_or(StorageError::NotInStorage)?;
+    page.accounts.swap_remove(index);
+    storage.len -= 1;
+    crud::write_record(page_info, crud::RecordKind::StoragePage, &page)?;
+    crud::write_record(storage_info, crud::RecordKind::PagedStorage, &storage)?;
+    crud::emit(crud::RecordKind::StoragePage, page_info, crud::Change::Update);
+    crud::emit(crud::RecordKind::PagedStorage, storage_info, crud::Change::Update);
+    Ok(())
+}
+
+/// Collects all accounts of the storage walking pages from the last one,
+/// `page_infos` must contain every page of the storage in any order
+pub fn accounts(
+    storage_info: &AccountInfo,
+    page_infos: &[AccountInfo],
+) -> Result<Vec<Pubkey>, ProgramError> {
+    let storage = load_storage(storage_info)?;
+    let mut accounts = Vec::with_capacity(storage.len as usize);
+    let mut next = storage.last_page;
+    while let Some(page_key) = next {
+        let page_info = page_infos
+            .iter()
+            .find(|x| *x.key == page_key)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let page = load_page(storage_info, page_info)?;
+        accounts.extend(page.accounts);
+        next = page.prev;
+    }
+    Ok(accounts)
+}
+
+pub fn load_storage(storage_info: &AccountInfo) -> Result<PagedStorage, ProgramError> {
+    crud::read_record(storage_info, crud::RecordKind::PagedStorage)
+}
+
+pub fn load_page(
+    storage_info: &AccountInfo,
+    page_info: &AccountInfo,
+) -> Result<StoragePage, ProgramError> {
+    let page: StoragePage = crud::read_record(page_info, crud::RecordKind::StoragePage)?;
+    if page.storage != *storage_info.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(page)
+}
@@ -0,0 +1,32 @@
+use {
+    solana_program::{decode_error::DecodeError, program_error::ProgramError},
+    thiserror::Error,
+};
+
+/// Errors that may be returned by template instructions, codes 0x000..0x0ff
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum TemplateError {
+    /// Published template versions can't be changed
+    #[error("Published template versions can't be changed")]
+    Published = 0x000,
+
+    /// Template is a draft
+    #[error("Template is a draft")]
+    Draft,
+
+    /// Link default doesn't point to an object of the linked template
+    #[error("Link default doesn't point to an object of the linked template")]
+    InvalidDefault,
+}
+
+impl From<TemplateError> for ProgramError {
+    fn from(e: TemplateError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for TemplateError {
+    fn type_of() -> &'static str {
+        "Template Error"
+    }
+}
@@ -1,4 +1,7 @@
+pub mod error;
+
 use {
+    crate::error::TemplateError,
     borsh::{BorshDeserialize, BorshSchema, BorshSerialize},
     solana_program::{
         account_info::{next_account_info, AccountInfo},
@@ -6,37 +9,46 @@ use {
         program_error::ProgramError,
         pubkey::Pubkey,
     },
+    solcery_crud::{Change, RecordKind},
 };
 
-#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug, PartialEq)]
-pub enum SolceryType {
-    Error,
-    SBool,
-    SInt,
-    SString,
-    SUrl,
-    SLink { template: Pubkey },
-    SBrick { brick_type: u32 }, //TODO
-    SArray { nested_type: SolceryNestedType },
-    SEnum { values: Vec<String> },
-    SMap { key_type: SolceryNestedType, value_type: SolceryNestedType },
-}
-
-#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug, PartialEq)]
-pub enum SolceryNestedType {
-    Error,
-    SBool,
-    SInt,
-    SString,
-    SUrl,
-    SLink { template: Pubkey },
-    SBrick, //TODO: error
-    SArray, //TODO: error
-    SEnum { values: Vec<String> },
-    SMap, //TODO: error
-}
-
-#[derive(Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+pub use types::{SolceryNestedType, SolceryType};
+
+// The BorshSchema derive of borsh 0.9 declares per variant structs whose fields are never read
+#[allow(dead_code)]
+mod types {
+    use super::*;
+
+    #[derive(BorshSerialize, BorshDeserialize, BorshSchema, Clone, Debug, PartialEq)]
+    pub enum SolceryType {
+        Error,
+        SBool,
+        SInt,
+        SString,
+        SUrl,
+        SLink { template: Pubkey },
+        SBrick { brick_type: u32 }, //TODO
+        SArray { nested_type: SolceryNestedType },
+        SEnum { values: Vec<String> },
+        SMap { key_type: SolceryNestedType, value_type: SolceryNestedType },
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, BorshSchema, Clone, Debug, PartialEq)]
+    pub enum SolceryNestedType {
+        Error,
+        SBool,
+        SInt,
+        SString,
+        SUrl,
+        SLink { template: Pubkey },
+        SBrick, //TODO: error
+        SArray, //TODO: error
+        SEnum { values: Vec<String> },
+        SMap, //TODO: error
+    }
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
 pub struct TemplateData {
     pub id: u32,
     pub name: String,
@@ -45,15 +57,23 @@ pub struct TemplateData {
     pub max_field_index: u32,
     pub fields: Vec<Field>,
     pub custom_data: String,
+    pub indexes: Vec<Pubkey>, // Field indexes maintained by object instructions
+    pub backrefs: Vec<Pubkey>, // Indexes of link fields of other templates pointing here
+    pub links: Vec<LinkRule>,  // Delete rules of own link fields, Restrict if not listed
+    pub parent: Option<Pubkey>,
+    pub defaults: Vec<FieldDefault>, // Values of new objects, override defaults of ancestors
+    pub version: u32,
+    pub published: bool, // Published versions are frozen, edits go to the next draft
+    pub versions: Vec<Pubkey>, // Snapshots of published versions, the first one is version 0
 }
 
-#[derive(Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
 pub struct Field {
     pub id: u32,
     pub params: FieldParams,
 }
 
-#[derive(Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
 pub struct FieldParams {
     pub field_type: SolceryType,
     pub name: String,
@@ -62,12 +82,90 @@ pub struct FieldParams {
     pub construct_server: bool,
 }
 
+/// What happens to objects linking to an object being deleted
+#[derive(Clone, Copy, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+pub enum OnDelete {
+    Restrict,
+    Nullify,
+    Cascade,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+pub struct LinkRule {
+    pub field_id: u32,
+    pub on_delete: OnDelete,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+pub struct FieldDefault {
+    pub field_id: u32,
+    pub value: Vec<u8>,
+}
+
+/// Template schema combined with the schemas of its ancestors
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergedTemplate {
+    pub data: TemplateData,
+    pub ancestors: Vec<Pubkey>, // From the parent to the root
+}
+
+impl TemplateData {
+    /// Draft of a new template with the default fields, which take the ids after its own
+    pub fn new(id: u32, storage: Pubkey) -> Self {
+        TemplateData {
+            id,
+            name: "New template".to_string(),
+            code: "newTemplate".to_string(),
+            storages: vec![storage], // TODO: template without storage
+            max_field_index: id + 2,
+            fields: vec![
+                Field {
+                    id: id + 1,
+                    params: FieldParams {
+                        field_type: SolceryType::SString,
+                        name: String::from("Name"),
+                        code: String::from("name"),
+                        construct_client: true,
+                        construct_server: false,
+                    },
+                },
+                Field {
+                    id: id + 2,
+                    params: FieldParams {
+                        field_type: SolceryType::SBool,
+                        name: String::from("Enabled"),
+                        code: String::from("enabled"),
+                        construct_client: false,
+                        construct_server: false,
+                    },
+                }
+            ],
+            custom_data: String::new(),
+            indexes: Vec::new(),
+            backrefs: Vec::new(),
+            links: Vec::new(),
+            parent: None,
+            defaults: Vec::new(),
+            version: 0,
+            published: false,
+            versions: Vec::new(),
+        }
+    }
+
+    pub fn on_delete(&self, field_id: u32) -> OnDelete {
+        self.links
+            .iter()
+            .find(|x| x.field_id == field_id)
+            .map_or(OnDelete::Restrict, |x| x.on_delete)
+    }
+}
+
 pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
     let (tag, data) = instruction_data.split_first().unwrap();
     let accounts_iter = &mut accounts.iter();
-    let signer_info = next_account_info(accounts_iter)?;
+    let _signer_info = next_account_info(accounts_iter)?;
     let project_info = next_account_info(accounts_iter)?;
-    match (tag) {
+    match tag {
     	0 => {
     		let template_info = next_account_info(accounts_iter)?;
     		let storage_info = next_account_info(accounts_iter)?;
@@ -77,7 +175,7 @@ pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) ->
     	1 => {
     		let template_info = next_account_info(accounts_iter)?;
             let field_params = FieldParams::deserialize(&mut &data[..])?;
-    		add_field(template_info, field_params)
+    		add_field(project_info, template_info, field_params)
     	}
         2 => {
             let template_info = next_account_info(accounts_iter)?;
@@ -96,15 +194,58 @@ pub fn process_instruction(accounts: &[AccountInfo], instruction_data: &[u8]) ->
         }
         5 => {
             let template_info = next_account_info(accounts_iter)?;
+            let ancestor_infos = accounts_iter.as_slice(); // Parent and its ancestors
             let data = TemplateData::deserialize(&mut &data[..])?;
-            update(template_info, data)
+            update(project_info, template_info, data, ancestor_infos)
         }
         6 => {
             let template_info = next_account_info(accounts_iter)?;
             let storage_info = next_account_info(accounts_iter)?;
             set_storage(project_info, template_info, storage_info)
         }
-        _ => return Err(ProgramError::InvalidAccountData)
+        7 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let storage_info = next_account_info(accounts_iter)?;
+            attach_storage(project_info, template_info, storage_info)
+        }
+        8 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let storage_info = next_account_info(accounts_iter)?;
+            detach_storage(template_info, storage_info)
+        }
+        9 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let rule = LinkRule::deserialize(&mut &data[..])?;
+            set_link_rule(template_info, rule)
+        }
+        10 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let parent_info = next_account_info(accounts_iter)?;
+            let ancestor_infos = accounts_iter.as_slice(); // Ancestors of the parent
+            set_parent(project_info, template_info, parent_info, ancestor_infos)
+        }
+        11 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let extra_infos = accounts_iter.as_slice(); // Ancestors, object of a link default
+            let default = FieldDefault::deserialize(&mut &data[..])?;
+            set_default(template_info, default, extra_infos)
+        }
+        12 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let snapshot_info = next_account_info(accounts_iter)?;
+            publish(project_info, template_info, snapshot_info)
+        }
+        13 => {
+            let template_info = next_account_info(accounts_iter)?;
+            new_draft(template_info)
+        }
+        14 => {
+            let template_info = next_account_info(accounts_iter)?;
+            let storage_info = next_account_info(accounts_iter)?;
+            let page_capacity = u32::deserialize(&mut &data[..])?;
+            attach_paged_storage(project_info, template_info, storage_info, page_capacity)
+        }
+        _ => Err(ProgramError::InvalidAccountData),
     }
 }
 
@@ -114,120 +255,382 @@ pub fn create(
     storage_info: &AccountInfo,
     project_templates_storage_info: &AccountInfo,
 ) -> ProgramResult {
-    let new_template_data = TemplateData {
-        id: solcery_project::get_uniq_id(project_info),
-        name: "New template".to_string(),
-        code: "newTemplate".to_string(),
-        storages: vec![*storage_info.key], // TODO: template without storage
-        max_field_index: 10,
-        fields: vec![ 
-            Field {
-                id: 1,
-                params: FieldParams {
-                    field_type: SolceryType::SString,
-                    name: String::from("Name"),
-                    code: String::from("name"),
-                    construct_client: true,
-                    construct_server: false,  
-                },
-            },
-            Field {
-                id: 2,
-                params: FieldParams {
-                    field_type: SolceryType::SBool,
-                    name: String::from("Enabled"),
-                    code: String::from("enabled"),
-                    construct_client: false,
-                    construct_server: false,  
-                },    
-            }       
-        ],
-        custom_data: String::new(),
-    };
-    solcery_crud::initialize(project_info, template_info);
-    solcery_crud::write(template_info, 0, new_template_data.try_to_vec().unwrap());
+    let id = solcery_project::reserve_ids(project_info, 3)?; // With the default fields
+    let new_template_data = TemplateData::new(id, *storage_info.key);
+    solcery_crud::initialize(project_info, template_info, RecordKind::Template)?;
+    solcery_crud::write_record(template_info, RecordKind::Template, &new_template_data)?;
+    solcery_crud::emit(RecordKind::Template, template_info, Change::Create);
     solcery_storage::assign(project_info, storage_info, template_info)?;
     solcery_storage::add(project_templates_storage_info, template_info)?;
     Ok(())
 }
 
+/// Writes the template as is, version checks are up to the caller
+pub fn write_template(template_info: &AccountInfo, template: &TemplateData) -> ProgramResult {
+    solcery_crud::write_record(template_info, RecordKind::Template, template)?;
+    solcery_crud::emit(RecordKind::Template, template_info, Change::Update);
+    Ok(())
+}
+
 pub fn set_storage( // DEPRECATED
     project_info: &AccountInfo,
     template_info: &AccountInfo,
     storage_info: &AccountInfo,
 ) -> ProgramResult {
-    let mut template = {
-        let mut template_data = &template_info.data.borrow()[solcery_crud::RecordData::WRITABLE_START_INDEX..];
-        TemplateData::deserialize(&mut &template_data[..])?
-    };
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
     template.storages = vec![ *storage_info.key ];
-    solcery_storage::assign(&project_info, &storage_info, &template_info)?;
-    solcery_crud::write(template_info, 0, template.try_to_vec().unwrap());
+    solcery_storage::assign(project_info, storage_info, template_info)?;
+    write_template(template_info, &template)
+}
+
+/// Creates one more storage for objects of the template
+pub fn attach_storage(
+    project_info: &AccountInfo,
+    template_info: &AccountInfo,
+    storage_info: &AccountInfo,
+) -> ProgramResult {
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
+    solcery_storage::assign(project_info, storage_info, template_info)?;
+    template.storages.push(*storage_info.key);
+    write_template(template_info, &template)
+}
+
+/// Creates a paged storage for objects of the template
+pub fn attach_paged_storage(
+    project_info: &AccountInfo,
+    template_info: &AccountInfo,
+    storage_info: &AccountInfo,
+    page_capacity: u32,
+) -> ProgramResult {
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
+    solcery_storage::paged::create(project_info, storage_info, template_info, page_capacity)?;
+    template.storages.push(*storage_info.key);
+    write_template(template_info, &template)
+}
+
+/// Only empty storages can be detached, objects should be transferred to other storages first
+pub fn detach_storage(template_info: &AccountInfo, storage_info: &AccountInfo) -> ProgramResult {
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
+    let index = template
+        .storages
+        .iter()
+        .position(|x| x == storage_info.key)
+        .ok_or(ProgramError::InvalidArgument)?;
+    let len = match solcery_crud::header_kind(storage_info) {
+        RecordKind::PagedStorage => {
+            solcery_storage::paged::load_storage(storage_info)?.len as usize
+        }
+        _ => solcery_storage::len(storage_info)?,
+    };
+    if len > 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    template.storages.remove(index);
+    write_template(template_info, &template)
+}
+
+pub fn set_link_rule(template_info: &AccountInfo, rule: LinkRule) -> ProgramResult {
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
+    let field = template
+        .fields
+        .iter()
+        .find(|x| x.id == rule.field_id)
+        .ok_or(ProgramError::InvalidArgument)?;
+    if !matches!(field.params.field_type, SolceryType::SLink { .. }) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    template.links.retain(|x| x.field_id != rule.field_id);
+    template.links.push(rule);
+    write_template(template_info, &template)
+}
+
+/// Parent fields are inherited as they are, so own field ids of the template have to be
+/// above the ids of the parent schema. Further fields are added after the inherited ones.
+/// Templates created before field ids came from the project counter all have their default
+/// fields at ids 1 and 2, so two of them can't be parent and child until one of them gets
+/// its fields recreated under new ids
+pub fn set_parent<'a>(
+    project_info: &AccountInfo,
+    template_info: &AccountInfo,
+    parent_info: &AccountInfo<'a>,
+    ancestor_infos: &[AccountInfo<'a>],
+) -> ProgramResult {
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
+    template.parent = Some(*parent_info.key);
+    let accounts = [std::slice::from_ref(parent_info), ancestor_infos].concat();
+    check_parent(project_info, template_info.key, &template, &accounts)?;
+    write_template(template_info, &template)
+}
+
+/// The parent has to be a template of the same project and can't be the template or one of
+/// its descendants. Fields of the template and its ancestors have to be apart and below the
+/// project counter, which new field ids come from, so fields added to any of them later
+/// can't collide either
+fn check_parent(
+    project_info: &AccountInfo,
+    template_key: &Pubkey,
+    template: &TemplateData,
+    ancestor_infos: &[AccountInfo],
+) -> ProgramResult {
+    let parent_key = match template.parent {
+        Some(parent_key) => parent_key,
+        None => return Ok(()),
+    };
+    let parent_info = ancestor_infos
+        .iter()
+        .find(|x| *x.key == parent_key)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if solcery_crud::get_project(parent_info)? != *project_info.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let parent = load_merged(parent_info, ancestor_infos)?;
+    if parent_key == *template_key || parent.ancestors.contains(template_key) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let next_id = solcery_project::next_uniq_id(project_info)?;
+    for field in &template.fields {
+        if parent.data.fields.iter().any(|x| x.id == field.id) {
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+    if parent.data.fields.iter().chain(&template.fields).any(|x| x.id >= next_id) {
+        return Err(ProgramError::InvalidArgument);
+    }
     Ok(())
 }
 
+/// Default of a link field has to point to an object of the linked template,
+/// which is passed among `accounts` along with the ancestors
+pub fn set_default(
+    template_info: &AccountInfo,
+    default: FieldDefault,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let merged = load_merged(template_info, accounts)?;
+    let field = merged
+        .data
+        .fields
+        .iter()
+        .find(|x| x.id == default.field_id)
+        .ok_or(ProgramError::InvalidArgument)?;
+    if let SolceryType::SLink { template } = field.params.field_type {
+        check_link_default(template_info, &template, &default.value, accounts)?;
+    }
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
+    template.defaults.retain(|x| x.field_id != default.field_id);
+    template.defaults.push(default);
+    write_template(template_info, &template)
+}
+
+fn check_link_default(
+    template_info: &AccountInfo,
+    linked_template: &Pubkey,
+    value: &[u8],
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if value.len() != 32 {
+        return Err(TemplateError::InvalidDefault.into());
+    }
+    let object_key = Pubkey::new(value);
+    let object_info = accounts
+        .iter()
+        .find(|x| *x.key == object_key)
+        .ok_or(TemplateError::InvalidDefault)?;
+    solcery_crud::check_kind(object_info, RecordKind::Object)
+        .map_err(|_| TemplateError::InvalidDefault)?;
+    if solcery_crud::get_project(object_info)? != solcery_crud::get_project(template_info)? {
+        return Err(TemplateError::InvalidDefault.into());
+    }
+    let offset = solcery_storage::AccountStorage::OBJECT_TEMPLATE_OFFSET;
+    if object_info.data.borrow().get(offset..offset + 32) != Some(linked_template.as_ref()) {
+        return Err(TemplateError::InvalidDefault.into());
+    }
+    Ok(())
+}
+
+/// Loads the template with fields, defaults and link rules of its ancestors, which have to be
+/// passed among `accounts`. Merged data is for reading only and is never written back
+pub fn load_merged(
+    template_info: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> Result<MergedTemplate, ProgramError> {
+    let mut merged = load(template_info)?;
+    let mut ancestors = Vec::new();
+    let mut next = merged.parent;
+    while let Some(parent_key) = next {
+        if parent_key == *template_info.key || ancestors.contains(&parent_key) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let parent_info = accounts
+            .iter()
+            .find(|x| *x.key == parent_key)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let parent = load(parent_info)?;
+        for field in parent.fields {
+            if merged.fields.iter().any(|x| x.id == field.id) {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            merged.fields.insert(0, field);
+        }
+        for default in parent.defaults {
+            if !merged.defaults.iter().any(|x| x.field_id == default.field_id) {
+                merged.defaults.push(default);
+            }
+        }
+        for rule in parent.links {
+            if !merged.links.iter().any(|x| x.field_id == rule.field_id) {
+                merged.links.push(rule);
+            }
+        }
+        merged.max_field_index = merged.max_field_index.max(parent.max_field_index);
+        ancestors.push(parent_key);
+        next = parent.parent;
+    }
+    merged.fields.sort_by_key(|x| x.id);
+    Ok(MergedTemplate {
+        data: merged,
+        ancestors,
+    })
+}
+
+pub fn load(template_info: &AccountInfo) -> Result<TemplateData, ProgramError> {
+    solcery_crud::read_record(template_info, RecordKind::Template)
+}
+
 pub fn add_field( // DEPRECATED
+    project_info: &AccountInfo,
     template_info: &AccountInfo,
     field_params: FieldParams,
 ) -> ProgramResult {
-    let mut template = {
-        let template_data =
-            &template_info.data.borrow()[solcery_crud::RecordData::WRITABLE_START_INDEX..];
-        TemplateData::deserialize(&mut &*template_data)?
-    };
-    template.max_field_index += 1;
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
+    let id = solcery_project::get_uniq_id(project_info)?;
+    template.max_field_index = template.max_field_index.max(id);
     let field = Field {
-        id: template.max_field_index,
+        id,
         params: field_params,
     };
     template.fields.push(field);
-    solcery_crud::write(template_info, 0, template.try_to_vec().unwrap());
-    Ok(())
+    write_template(template_info, &template)
 }
 
 pub fn delete_field(template_info: &AccountInfo, field_id: u32) -> ProgramResult { // DEPRECATED
-    let mut template = {
-        let template_data =
-            &template_info.data.borrow()[solcery_crud::RecordData::WRITABLE_START_INDEX..];
-        TemplateData::deserialize(&mut &*template_data)?
-    };
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
     let index_of_field_id = template.fields.iter().position(|x| x.id == field_id);
     match index_of_field_id {
         Some(ind) => {
             template.fields.remove(ind);
-            solcery_crud::write(template_info, 0, template.try_to_vec().unwrap());
-            Ok(())
+            write_template(template_info, &template)
         }
         _ => Err(ProgramError::InvalidAccountData),
     }
 }
 
 pub fn change_name(template_info: &AccountInfo, name: String) -> ProgramResult { // DEPRECATED
-    let mut template = {
-        let template_data =
-            &template_info.data.borrow()[solcery_crud::RecordData::WRITABLE_START_INDEX..];
-        TemplateData::deserialize(&mut &*template_data)?
-    };
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
     template.name = name;
-    solcery_crud::write(template_info, 0, template.try_to_vec().unwrap());
-    Ok(())
+    write_template(template_info, &template)
 }
 
 pub fn change_code(template_info: &AccountInfo, code: String) -> ProgramResult { // DEPRECATED
-    let mut template = {
-        let template_data =
-            &template_info.data.borrow()[solcery_crud::RecordData::WRITABLE_START_INDEX..];
-        TemplateData::deserialize(&mut &*template_data)?
-    };
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
     template.code = code;
-    solcery_crud::write(template_info, 0, template.try_to_vec().unwrap());
-    Ok(())
+    write_template(template_info, &template)
 }
 
+/// Replaces name, code, fields, custom data and parent of the draft. The id, version and
+/// records maintained by other instructions, storages, links and defaults included, are kept.
+/// Fields new to the template have to take ids the project counter hasn't given out yet,
+/// the counter is moved past them
 pub fn update(
+    project_info: &AccountInfo,
     template_info: &AccountInfo,
-    template: TemplateData,
+    mut template: TemplateData,
+    ancestor_infos: &[AccountInfo],
 ) -> ProgramResult {
-    solcery_crud::write(template_info, 0, template.try_to_vec().unwrap());
+    let stored = load(template_info)?;
+    check_draft(&stored)?;
+    let next_id = solcery_project::next_uniq_id(project_info)?;
+    let mut max_id = None;
+    for (i, field) in template.fields.iter().enumerate() {
+        if template.fields[..i].iter().any(|x| x.id == field.id) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if !stored.fields.iter().any(|x| x.id == field.id) {
+            if field.id < next_id {
+                return Err(ProgramError::InvalidArgument);
+            }
+            max_id = max_id.max(Some(field.id));
+        }
+    }
+    if let Some(max_id) = max_id {
+        let amount = max_id.checked_add(1).ok_or(ProgramError::InvalidArgument)? - next_id;
+        solcery_project::reserve_ids(project_info, amount)?;
+    }
+    check_parent(project_info, template_info.key, &template, ancestor_infos)?;
+    let max_field_index = template.fields.iter().map(|x| x.id).max().unwrap_or_default();
+    template.max_field_index = max_field_index.max(stored.max_field_index);
+    template.id = stored.id;
+    template.storages = stored.storages;
+    template.links = stored.links;
+    template.defaults = stored.defaults;
+    template.indexes = stored.indexes;
+    template.backrefs = stored.backrefs;
+    template.version = stored.version;
+    template.published = stored.published;
+    template.versions = stored.versions;
+    write_template(template_info, &template)
+}
+
+/// Freezes the current version, objects created or updated from now on conform to it.
+/// A copy goes to `snapshot_info` as a `TemplateVersion` record, which no instruction
+/// changes afterwards, while the template itself moves on with the next draft
+pub fn publish(
+    project_info: &AccountInfo,
+    template_info: &AccountInfo,
+    snapshot_info: &AccountInfo,
+) -> ProgramResult {
+    let mut template = load(template_info)?;
+    check_draft(&template)?;
+    template.published = true;
+    let mut snapshot = template.clone();
+    snapshot.versions = Vec::new();
+    solcery_crud::initialize(project_info, snapshot_info, RecordKind::TemplateVersion)?;
+    solcery_crud::write_record(snapshot_info, RecordKind::TemplateVersion, &snapshot)?;
+    solcery_crud::emit(RecordKind::TemplateVersion, snapshot_info, Change::Create);
+    template.versions.push(*snapshot_info.key);
+    write_template(template_info, &template)
+}
+
+/// Template data of a published version as frozen by `publish`
+pub fn load_version(snapshot_info: &AccountInfo) -> Result<TemplateData, ProgramError> {
+    solcery_crud::read_record(snapshot_info, RecordKind::TemplateVersion)
+}
+
+/// Opens the next version of a published template for editing
+pub fn new_draft(template_info: &AccountInfo) -> ProgramResult {
+    let mut template = load(template_info)?;
+    if !template.published {
+        return Err(TemplateError::Draft.into());
+    }
+    template.version += 1;
+    template.published = false;
+    write_template(template_info, &template)
+}
+
+fn check_draft(template: &TemplateData) -> ProgramResult {
+    if template.published {
+        return Err(TemplateError::Published.into());
+    }
     Ok(())
 }